@@ -7,6 +7,8 @@ import { AccountTag } from "./market_state";
 export enum EventType {
   Fill = 0,
   Out = 1,
+  Reject = 2,
+  Tick = 3,
 }
 
 /** @enum {number} */
@@ -15,6 +17,31 @@ export enum Side {
   Ask = 1,
 }
 
+/** @enum {number} */
+export enum RejectReason {
+  InvalidLimitPrice = 0,
+  PriceOutOfBand = 1,
+  CrossesSpread = 2,
+  TooSmall = 3,
+  BookFull = 4,
+  SidePaused = 5,
+  InvalidLotSize = 6,
+  DuplicateOrderId = 7,
+  TooManyOrders = 8,
+  TooManyLevels = 9,
+}
+
+/**
+ * The optional parts of the event queue layout, which depend on the features
+ * the program was built with
+ */
+export interface EventQueueFeatures {
+  /** Whether the timestamp of each event is stored (`event-timestamps`) */
+  eventTimestamps?: boolean;
+  /** Whether the maker's size left by each fill is stored (`fill-maker-remaining`) */
+  fillMakerRemaining?: boolean;
+}
+
 /**
  * Event queue header object
  */
@@ -23,8 +50,16 @@ export class EventQueueHeader {
   head: BN;
   count: BN;
   seqNum: BN;
+  feePerEvent: BN;
+  accruedCrankFees: BN;
+  headSeqNum: BN;
+  overflowPolicy: BN;
+  orderIdMode: BN;
+  tailSeqNum: BN;
+  orderIdTimestamp: BN;
+  orderIdTimestampCount: BN;
 
-  static LEN: number = 32;
+  static LEN: number = 96;
 
   static schema: Schema = new Map([
     [
@@ -36,16 +71,45 @@ export class EventQueueHeader {
           ["head", "u64"],
           ["count", "u64"],
           ["seqNum", "u64"],
+          ["feePerEvent", "u64"],
+          ["accruedCrankFees", "u64"],
+          ["headSeqNum", "u64"],
+          ["overflowPolicy", "u64"],
+          ["orderIdMode", "u64"],
+          ["tailSeqNum", "u64"],
+          ["orderIdTimestamp", "u64"],
+          ["orderIdTimestampCount", "u64"],
         ],
       },
     ],
   ]);
 
-  constructor(arg: { tag: number; head: BN; count: BN; seqNum: BN }) {
-    this.tag = arg.tag as AccountTag;
+  constructor(arg: {
+    tag: BN;
+    head: BN;
+    count: BN;
+    seqNum: BN;
+    feePerEvent: BN;
+    accruedCrankFees: BN;
+    headSeqNum: BN;
+    overflowPolicy: BN;
+    orderIdMode: BN;
+    tailSeqNum: BN;
+    orderIdTimestamp: BN;
+    orderIdTimestampCount: BN;
+  }) {
+    this.tag = arg.tag.toNumber() as AccountTag;
     this.head = arg.head;
     this.count = arg.count;
     this.seqNum = arg.seqNum;
+    this.feePerEvent = arg.feePerEvent;
+    this.accruedCrankFees = arg.accruedCrankFees;
+    this.headSeqNum = arg.headSeqNum;
+    this.overflowPolicy = arg.overflowPolicy;
+    this.orderIdMode = arg.orderIdMode;
+    this.tailSeqNum = arg.tailSeqNum;
+    this.orderIdTimestamp = arg.orderIdTimestamp;
+    this.orderIdTimestampCount = arg.orderIdTimestampCount;
   }
 }
 
@@ -54,13 +118,21 @@ export class EventQueueHeader {
  */
 export class EventFill {
   takerSide: Side;
+  version: number;
   quoteSize: BN;
   makerOrderId: BN;
   baseSize: BN;
+  /** The fill price (FP32), only set from version 2 onwards */
+  price?: BN;
+  /** The order id of the taker order, only set from version 1 onwards */
+  takerOrderId?: BN;
+  seqNum!: BN;
+  timestamp?: BN;
+  makerRemaining?: BN;
   makerCallbackInfo!: number[];
   takerCallbackInfo!: number[];
 
-  static LEN: number = 40;
+  static LEN: number = 64;
 
   static schema: Schema = new Map([
     [
@@ -70,10 +142,13 @@ export class EventFill {
         fields: [
           ["tag", "u8"],
           ["takerSide", "u8"],
-          ["_padding", [6]],
+          ["version", "u8"],
+          ["_padding", [5]],
           ["quoteSize", "u64"],
           ["makerOrderId", "u128"],
           ["baseSize", "u64"],
+          ["price", "u64"],
+          ["takerOrderId", "u128"],
         ],
       },
     ],
@@ -82,14 +157,24 @@ export class EventFill {
   constructor(arg: {
     tag: number;
     takerSide: Side;
+    version: number;
     quoteSize: BN;
     makerOrderId: BN;
     baseSize: BN;
+    price: BN;
+    takerOrderId: BN;
   }) {
     this.takerSide = arg.takerSide as Side;
+    this.version = arg.version;
     this.makerOrderId = arg.makerOrderId;
     this.quoteSize = arg.quoteSize;
     this.baseSize = arg.baseSize;
+    if (arg.version >= 1) {
+      this.takerOrderId = arg.takerOrderId;
+    }
+    if (arg.version >= 2) {
+      this.price = arg.price;
+    }
   }
 }
 
@@ -98,9 +183,11 @@ export class EventFill {
  */
 export class EventOut {
   side: Side;
+  version: number;
   orderId: BN;
   baseSize: BN;
-  delete: boolean;
+  seqNum!: BN;
+  timestamp?: BN;
   callbackInfo!: number[];
 
   static schema: Schema = new Map([
@@ -111,7 +198,7 @@ export class EventOut {
         fields: [
           ["tag", "u8"],
           ["side", "u8"],
-          ["delete", "u8"],
+          ["version", "u8"],
           ["_padding", [13]],
           ["orderId", "u128"],
           ["baseSize", "u64"],
@@ -122,45 +209,170 @@ export class EventOut {
 
   constructor(arg: {
     side: number;
+    version: number;
     orderId: BN;
     baseSize: BN;
-    delete: number;
   }) {
     this.side = arg.side as Side;
+    this.version = arg.version;
     this.orderId = arg.orderId;
     this.baseSize = arg.baseSize;
-    this.delete = arg.delete === 1;
   }
 }
 
+/**
+ * EventReject object, pushed for each order of a batch which couldn't be posted
+ */
+export class EventReject {
+  side: Side;
+  version: number;
+  reason: RejectReason;
+  batchIndex: BN;
+  limitPrice: BN;
+  baseSize: BN;
+  seqNum!: BN;
+  timestamp?: BN;
+  callbackInfo!: number[];
+
+  static schema: Schema = new Map([
+    [
+      EventReject,
+      {
+        kind: "struct",
+        fields: [
+          ["tag", "u8"],
+          ["side", "u8"],
+          ["version", "u8"],
+          ["reason", "u8"],
+          ["_padding", [4]],
+          ["batchIndex", "u64"],
+          ["limitPrice", "u64"],
+          ["baseSize", "u64"],
+        ],
+      },
+    ],
+  ]);
+
+  constructor(arg: {
+    side: number;
+    version: number;
+    reason: number;
+    batchIndex: BN;
+    limitPrice: BN;
+    baseSize: BN;
+  }) {
+    this.side = arg.side as Side;
+    this.version = arg.version;
+    this.reason = arg.reason as RejectReason;
+    this.batchIndex = arg.batchIndex;
+    this.limitPrice = arg.limitPrice;
+    this.baseSize = arg.baseSize;
+  }
+}
+
+/**
+ * EventTick object, a keep-alive event which carries no order information
+ */
+export class EventTick {
+  version: number;
+  timestamp: BN;
+  seqNum: BN;
+
+  static schema: Schema = new Map([
+    [
+      EventTick,
+      {
+        kind: "struct",
+        fields: [
+          ["tag", "u8"],
+          ["_padding", "u8"],
+          ["version", "u8"],
+          ["_paddingVersion", [5]],
+          ["timestamp", "u64"],
+          ["seqNum", "u64"],
+        ],
+      },
+    ],
+  ]);
+
+  constructor(arg: { version: number; timestamp: BN; seqNum: BN }) {
+    this.version = arg.version;
+    // The timestamp is a signed integer
+    this.timestamp = arg.timestamp.fromTwos(64);
+    this.seqNum = arg.seqNum;
+  }
+}
+
+export type Event = EventFill | EventOut | EventReject | EventTick;
+
 /**
  * Event queue object
  */
 export class EventQueue {
   header: EventQueueHeader;
   eventsBuffer: number[];
+  seqNumsBuffer: number[];
+  timestampsBuffer?: number[];
+  makerRemainingsBuffer?: number[];
   callbackInfosBuffer: number[];
   callBackInfoLen: number;
 
+  /** Byte length of the sequence number stored with each event */
+  static SEQ_NUM_LEN: number = 8;
+  /** Byte length of the timestamp stored with each event */
+  static TIMESTAMP_LEN: number = 8;
+  /** Byte length of the maker's remaining size stored with each event */
+  static MAKER_REMAINING_LEN: number = 8;
+
   constructor(arg: {
     header: EventQueueHeader;
     eventsBuffer: number[];
+    seqNumsBuffer: number[];
+    timestampsBuffer?: number[];
+    makerRemainingsBuffer?: number[];
     callbackInfosBuffer: number[];
     callBackInfoLen: number;
   }) {
     this.header = arg.header;
     this.eventsBuffer = arg.eventsBuffer;
+    this.seqNumsBuffer = arg.seqNumsBuffer;
+    this.timestampsBuffer = arg.timestampsBuffer;
+    this.makerRemainingsBuffer = arg.makerRemainingsBuffer;
     this.callbackInfosBuffer = arg.callbackInfosBuffer;
     this.callBackInfoLen = arg.callBackInfoLen;
   }
 
+  /**
+   * Returns the byte size of an event along with the data stored with it
+   * @param callBackInfoLen Length of the callback information
+   * @param features The optional parts of the layout the program was built with
+   * @returns The byte size of an event slot
+   */
+  static eventSize(
+    callBackInfoLen: number,
+    features: EventQueueFeatures = {}
+  ): number {
+    return (
+      EventFill.LEN +
+      EventQueue.SEQ_NUM_LEN +
+      (features.eventTimestamps ? EventQueue.TIMESTAMP_LEN : 0) +
+      (features.fillMakerRemaining ? EventQueue.MAKER_REMAINING_LEN : 0) +
+      2 * callBackInfoLen
+    );
+  }
+
   /**
    * Deserialize a buffer into an EventQueue object
    * @param callBackInfoLen Length of the callback information
    * @param data Buffer to deserialize
+   * @param features The optional parts of the layout the program was built with
    * @returns Returns an EventQueue object
    */
-  static parse(callBackInfoLen: number, data: Buffer) {
+  static parse(
+    callBackInfoLen: number,
+    data: Buffer,
+    features: EventQueueFeatures = {}
+  ) {
     let header = deserializeUnchecked(
       EventQueueHeader.schema,
       EventQueueHeader,
@@ -168,14 +380,32 @@ export class EventQueue {
     ) as EventQueueHeader;
     let capacity =
       (data.length - EventQueueHeader.LEN) /
-      (EventFill.LEN + 2 * callBackInfoLen);
-    let callbackInfosOffset = EventQueueHeader.LEN + capacity * EventFill.LEN;
-    let eventsBuffer = data.slice(EventQueueHeader.LEN, callbackInfosOffset);
-    let callbackInfosBuffer = data.slice(callbackInfosOffset);
+      EventQueue.eventSize(callBackInfoLen, features);
+    if (!Number.isInteger(capacity)) {
+      throw new Error("The account size doesn't match the event size");
+    }
+    let offset = EventQueueHeader.LEN;
+    const take = (len: number) => {
+      const slice = data.slice(offset, offset + capacity * len);
+      offset += capacity * len;
+      return [...slice];
+    };
+    let eventsBuffer = take(EventFill.LEN);
+    let seqNumsBuffer = take(EventQueue.SEQ_NUM_LEN);
+    let timestampsBuffer = features.eventTimestamps
+      ? take(EventQueue.TIMESTAMP_LEN)
+      : undefined;
+    let makerRemainingsBuffer = features.fillMakerRemaining
+      ? take(EventQueue.MAKER_REMAINING_LEN)
+      : undefined;
+    let callbackInfosBuffer = take(2 * callBackInfoLen);
     return new EventQueue({
       header,
-      eventsBuffer: [...eventsBuffer],
-      callbackInfosBuffer: [...callbackInfosBuffer],
+      eventsBuffer,
+      seqNumsBuffer,
+      timestampsBuffer,
+      makerRemainingsBuffer,
+      callbackInfosBuffer,
       callBackInfoLen,
     });
   }
@@ -185,18 +415,20 @@ export class EventQueue {
    * @param connection The solana connection object to the RPC node
    * @param address The address of the event queue
    * @param callBackInfoLen The length of the callback information
+   * @param features The optional parts of the layout the program was built with
    * @returns Returns an EventQueue object
    */
   static async load(
     connection: Connection,
     address: PublicKey,
-    callBackInfoLen: number
+    callBackInfoLen: number,
+    features: EventQueueFeatures = {}
   ) {
     const accountInfo = await connection.getAccountInfo(address);
     if (!accountInfo?.data) {
       throw new Error("Invalid address provided");
     }
-    return this.parse(callBackInfoLen, accountInfo.data);
+    return this.parse(callBackInfoLen, accountInfo.data, features);
   }
 
   /**
@@ -204,46 +436,80 @@ export class EventQueue {
    * @param idx Index of the event to parse
    * @returns Returns an Event object
    */
-  parseEvent(idx: number) {
+  parseEvent(idx: number): Event {
     let eventsOffset = idx * EventFill.LEN;
     let data = Buffer.from(
       this.eventsBuffer.slice(eventsOffset, eventsOffset + EventFill.LEN)
     );
+    let event: Event;
     switch (data[0]) {
       case EventType.Fill: {
-        let event = deserializeUnchecked(
+        let fill = deserializeUnchecked(
           EventFill.schema,
           EventFill,
           data
         ) as EventFill;
         let makerOffset = 2 * idx * this.callBackInfoLen;
         let takerOffset = (2 * idx + 1) * this.callBackInfoLen;
-        event.makerCallbackInfo = this.callbackInfosBuffer.slice(
+        fill.makerCallbackInfo = this.callbackInfosBuffer.slice(
           makerOffset,
           makerOffset + this.callBackInfoLen
         );
-        event.takerCallbackInfo = this.callbackInfosBuffer.slice(
+        fill.takerCallbackInfo = this.callbackInfosBuffer.slice(
           takerOffset,
           takerOffset + this.callBackInfoLen
         );
-        return event;
+        if (this.makerRemainingsBuffer) {
+          fill.makerRemaining = readU64(this.makerRemainingsBuffer, idx);
+        }
+        event = fill;
+        break;
       }
       case EventType.Out: {
-        let event = deserializeUnchecked(
+        let out = deserializeUnchecked(
           EventOut.schema,
           EventOut,
           data
         ) as EventOut;
-        let offset = 2 * idx * this.callBackInfoLen;
-        event.callbackInfo = this.callbackInfosBuffer.slice(
-          offset,
-          offset + this.callBackInfoLen
-        );
-        return event;
+        out.callbackInfo = this.getCallbackInfo(idx);
+        event = out;
+        break;
+      }
+      case EventType.Reject: {
+        let reject = deserializeUnchecked(
+          EventReject.schema,
+          EventReject,
+          data
+        ) as EventReject;
+        reject.callbackInfo = this.getCallbackInfo(idx);
+        event = reject;
+        break;
       }
+      case EventType.Tick:
+        return deserializeUnchecked(
+          EventTick.schema,
+          EventTick,
+          data
+        ) as EventTick;
       default:
         throw new Error("Invalid data provided");
     }
+    event.seqNum = readU64(this.seqNumsBuffer, idx);
+    if (this.timestampsBuffer) {
+      event.timestamp = readU64(this.timestampsBuffer, idx).fromTwos(64);
+    }
+    return event;
+  }
+
+  /**
+   * Returns the callback information of an event which carries a single one
+   */
+  getCallbackInfo(idx: number) {
+    let offset = 2 * idx * this.callBackInfoLen;
+    return this.callbackInfosBuffer.slice(
+      offset,
+      offset + this.callBackInfoLen
+    );
   }
 
   /**
@@ -276,20 +542,28 @@ export class EventQueue {
    * @returns Returns an EventQueueHeader object
    */
   static parseEventQueueHeader(data: Buffer) {
-    return deserialize(
+    return deserializeUnchecked(
       EventQueueHeader.schema,
       EventQueueHeader,
-      data
+      data.slice(0, EventQueueHeader.LEN)
     ) as EventQueueHeader;
   }
 
   static computeAllocationSize(
     desiredEventCapacity: number,
-    callbackInfoLen: number
+    callbackInfoLen: number,
+    features: EventQueueFeatures = {}
   ): number {
     return (
-      desiredEventCapacity * (EventFill.LEN + 2 * callbackInfoLen) +
+      desiredEventCapacity * EventQueue.eventSize(callbackInfoLen, features) +
       EventQueueHeader.LEN
     );
   }
 }
+
+/**
+ * Reads the little-endian u64 at position `idx` of a buffer of u64s
+ */
+function readU64(buffer: number[], idx: number): BN {
+  return new BN(buffer.slice(8 * idx, 8 * (idx + 1)), "le");
+}
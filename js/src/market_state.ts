@@ -23,6 +23,16 @@ export enum SelfTradeBehavior {
   AbortTransaction = 2,
 }
 
+/**
+ * The bits of `MarketState.flags`
+ */
+export const MarketFlags = {
+  BidsPaused: 1 << 0,
+  AsksPaused: 1 << 1,
+  LogMatches: 1 << 2,
+  FillsOnly: 1 << 3,
+};
+
 /**
  * MarketState object
  */
@@ -33,9 +43,22 @@ export class MarketState {
   asks: PublicKey;
   minBaseOrderSize: BN;
   tickSize: BN;
+  baseLotSize: BN;
+  authority: PublicKey;
+  feeRecipient: PublicKey;
+  lastTradePrice: BN;
+  maxLevelsPerSide: number;
+  priceBandBps: number;
+  flags: number;
+  maxOrdersPerOwner: number;
+  makerPriceImprovementBps: number;
+  takerFeeBps: number;
+  selfTradeMatchOffset: number;
+  selfTradeMatchLen: number;
+  selfTradeMatchKind: number;
   callbackInfoLen!: number;
 
-  static LEN: number = 120;
+  static LEN: number = 224;
 
   static schema: Schema = new Map([
     [
@@ -49,6 +72,20 @@ export class MarketState {
           ["asks", [32]],
           ["minBaseOrderSize", "u64"],
           ["tickSize", "u64"],
+          ["baseLotSize", "u64"],
+          ["authority", [32]],
+          ["feeRecipient", [32]],
+          ["lastTradePrice", "u64"],
+          ["maxLevelsPerSide", "u32"],
+          ["priceBandBps", "u16"],
+          ["flags", "u16"],
+          ["maxOrdersPerOwner", "u16"],
+          ["makerPriceImprovementBps", "u16"],
+          ["takerFeeBps", "u16"],
+          ["selfTradeMatchOffset", "u16"],
+          ["selfTradeMatchLen", "u16"],
+          ["selfTradeMatchKind", "u8"],
+          ["_padding", [5]],
         ],
       },
     ],
@@ -61,6 +98,19 @@ export class MarketState {
     asks: Uint8Array;
     minBaseOrderSize: BN;
     tickSize: BN;
+    baseLotSize: BN;
+    authority: Uint8Array;
+    feeRecipient: Uint8Array;
+    lastTradePrice: BN;
+    maxLevelsPerSide: number;
+    priceBandBps: number;
+    flags: number;
+    maxOrdersPerOwner: number;
+    makerPriceImprovementBps: number;
+    takerFeeBps: number;
+    selfTradeMatchOffset: number;
+    selfTradeMatchLen: number;
+    selfTradeMatchKind: number;
   }) {
     this.tag = new BN(arg.tag);
     this.eventQueue = new PublicKey(arg.eventQueue);
@@ -68,6 +118,19 @@ export class MarketState {
     this.asks = new PublicKey(arg.asks);
     this.minBaseOrderSize = arg.minBaseOrderSize;
     this.tickSize = arg.tickSize;
+    this.baseLotSize = arg.baseLotSize;
+    this.authority = new PublicKey(arg.authority);
+    this.feeRecipient = new PublicKey(arg.feeRecipient);
+    this.lastTradePrice = arg.lastTradePrice;
+    this.maxLevelsPerSide = arg.maxLevelsPerSide;
+    this.priceBandBps = arg.priceBandBps;
+    this.flags = arg.flags;
+    this.maxOrdersPerOwner = arg.maxOrdersPerOwner;
+    this.makerPriceImprovementBps = arg.makerPriceImprovementBps;
+    this.takerFeeBps = arg.takerFeeBps;
+    this.selfTradeMatchOffset = arg.selfTradeMatchOffset;
+    this.selfTradeMatchLen = arg.selfTradeMatchLen;
+    this.selfTradeMatchKind = arg.selfTradeMatchKind;
   }
 
   /**
@@ -13,8 +13,10 @@ export class InnerNode {
   prefixLen: BN;
   key: BN;
   children: number[];
+  /** The total base quantity of the orders in the node's subtree */
+  baseQuantity: BN;
 
-  static LEN = 32;
+  static LEN = 48;
 
   static schema: Schema = new Map([
     [
@@ -25,15 +27,22 @@ export class InnerNode {
           ["key", "u128"],
           ["prefixLen", "u64"],
           ["children", ["u32", 2]],
+          ["baseQuantity", "u128"],
         ],
       },
     ],
   ]);
 
-  constructor(arg: { prefixLen: BN; key: BN; children: number[] }) {
+  constructor(arg: {
+    prefixLen: BN;
+    key: BN;
+    children: number[];
+    baseQuantity: BN;
+  }) {
     this.prefixLen = arg.prefixLen;
     this.key = arg.key;
     this.children = arg.children;
+    this.baseQuantity = arg.baseQuantity;
   }
 }
 
@@ -5,7 +5,7 @@ import { ChildProcess } from "child_process";
 
 import { createMarket } from "../bindings";
 import { newOrderInstruction } from "../instructions";
-import { MarketState } from "../market_state";
+import { AccountTag, MarketState } from "../market_state";
 
 import {
   airdropPayer,
@@ -71,6 +71,7 @@ test("create bids", async () => {
     const marketState = await MarketState.retrieve(
       connection,
       market.publicKey,
+      10,
       "finalized"
     );
     const bidsSlab = await marketState.loadBidsSlab(connection, "finalized");
@@ -80,18 +81,13 @@ test("create bids", async () => {
     );
     expect(marketState.bids.toString()).toBe(bids.publicKey.toString());
     expect(marketState.asks.toString()).toBe(asks.publicKey.toString());
-    expect(marketState.callBackInfoLen.toString()).toBe("10");
-    expect(marketState.callBackIdLen.toString()).toBe("5");
-    expect(marketState.minOrderSize.toString()).toBe("20");
-    expect(marketState.feeBudget.toString()).toBe("0");
-    expect(bidsSlab.callBackInfoLen.toNumber()).toBe(10);
-    expect(bidsSlab.header.marketAddress.toString()).toBe(
-      market.publicKey.toString()
-    );
-    expect(asksSlab.callBackInfoLen.toNumber()).toBe(10);
-    expect(asksSlab.header.marketAddress.toString()).toBe(
-      market.publicKey.toString()
-    );
+    expect(marketState.minBaseOrderSize.toString()).toBe("20");
+    expect(marketState.lastTradePrice.toString()).toBe("0");
+    expect(marketState.takerFeeBps).toBe(0);
+    expect(bidsSlab.callBackInfoLen).toBe(10);
+    expect(bidsSlab.header.accountTag).toBe(AccountTag.Bids);
+    expect(asksSlab.callBackInfoLen).toBe(10);
+    expect(asksSlab.header.accountTag).toBe(AccountTag.Asks);
   }
 
   const sendBid = async (args: {
@@ -180,6 +176,7 @@ test("create bids", async () => {
     const marketState = await MarketState.retrieve(
       connection,
       market.publicKey,
+      10,
       "finalized"
     );
     const bidsSlab = await marketState.loadBidsSlab(connection, "finalized");
@@ -223,6 +220,7 @@ test("create bids", async () => {
     const marketState = await MarketState.retrieve(
       connection,
       market.publicKey,
+      10,
       "finalized"
     );
     const bidsSlab = await marketState.loadBidsSlab(connection, "finalized");
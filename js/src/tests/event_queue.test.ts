@@ -0,0 +1,166 @@
+import { expect, test } from "@jest/globals";
+import BN from "bn.js";
+
+import {
+  EventFill,
+  EventOut,
+  EventQueue,
+  EventQueueHeader,
+  EventReject,
+  EventTick,
+  EventType,
+  RejectReason,
+  Side,
+} from "../event_queue";
+import { AccountTag } from "../market_state";
+
+const CALLBACK_INFO_LEN = 2;
+const CAPACITY = 4;
+
+function writeU128(buffer: Buffer, value: bigint, offset: number) {
+  buffer.writeBigUInt64LE(BigInt.asUintN(64, value), offset);
+  buffer.writeBigUInt64LE(value >> BigInt(64), offset + 8);
+}
+
+// Lays out an event queue account like the program does: the header, the events,
+// their sequence numbers and then their callback infos
+function eventQueueAccount(
+  events: Buffer[],
+  seqNums: number[],
+  callbackInfos: number[][]
+): Buffer {
+  const data = Buffer.alloc(
+    EventQueue.computeAllocationSize(CAPACITY, CALLBACK_INFO_LEN)
+  );
+  data.writeBigUInt64LE(BigInt(AccountTag.EventQueue), 0);
+  // head, count
+  data.writeBigUInt64LE(BigInt(1), 8);
+  data.writeBigUInt64LE(BigInt(events.length), 16);
+  // head_seq_num, tail_seq_num
+  data.writeBigUInt64LE(BigInt(seqNums[0]), 48);
+  data.writeBigUInt64LE(BigInt(seqNums[seqNums.length - 1] + 1), 72);
+  const seqNumsOffset = EventQueueHeader.LEN + CAPACITY * EventFill.LEN;
+  const callbackInfosOffset =
+    seqNumsOffset + CAPACITY * EventQueue.SEQ_NUM_LEN;
+  events.forEach((event, i) => {
+    const slot = 1 + i;
+    event.copy(data, EventQueueHeader.LEN + slot * EventFill.LEN);
+    data.writeBigUInt64LE(BigInt(seqNums[i]), seqNumsOffset + 8 * slot);
+    Buffer.from(callbackInfos[i]).copy(
+      data,
+      callbackInfosOffset + 2 * slot * CALLBACK_INFO_LEN
+    );
+  });
+  return data;
+}
+
+test("event queue layout", () => {
+  expect(EventQueue.computeAllocationSize(CAPACITY, CALLBACK_INFO_LEN)).toBe(
+    96 + CAPACITY * (64 + 8 + 2 * CALLBACK_INFO_LEN)
+  );
+  expect(
+    EventQueue.computeAllocationSize(CAPACITY, CALLBACK_INFO_LEN, {
+      eventTimestamps: true,
+      fillMakerRemaining: true,
+    })
+  ).toBe(96 + CAPACITY * (64 + 8 + 8 + 8 + 2 * CALLBACK_INFO_LEN));
+});
+
+test("decode events", () => {
+  const fill = Buffer.alloc(EventFill.LEN);
+  fill.writeUInt8(EventType.Fill, 0);
+  fill.writeUInt8(Side.Ask, 1);
+  fill.writeUInt8(2, 2);
+  fill.writeBigUInt64LE(BigInt(1_500), 8);
+  writeU128(fill, BigInt(42), 16);
+  fill.writeBigUInt64LE(BigInt(100), 32);
+  fill.writeBigUInt64LE(BigInt(15) << BigInt(32), 40);
+  writeU128(fill, BigInt(43), 48);
+
+  // A version 0 fill doesn't carry the price or the taker order id
+  const legacyFill = Buffer.from(fill);
+  legacyFill.writeUInt8(0, 2);
+
+  const out = Buffer.alloc(EventFill.LEN);
+  out.writeUInt8(EventType.Out, 0);
+  out.writeUInt8(Side.Bid, 1);
+  out.writeUInt8(1, 2);
+  writeU128(out, BigInt(44), 16);
+  out.writeBigUInt64LE(BigInt(7), 32);
+
+  const reject = Buffer.alloc(EventFill.LEN);
+  reject.writeUInt8(EventType.Reject, 0);
+  reject.writeUInt8(Side.Bid, 1);
+  reject.writeUInt8(RejectReason.TooManyLevels, 3);
+  reject.writeBigUInt64LE(BigInt(3), 8);
+  reject.writeBigUInt64LE(BigInt(9) << BigInt(32), 16);
+  reject.writeBigUInt64LE(BigInt(5), 24);
+
+  const eventQueue = EventQueue.parse(
+    CALLBACK_INFO_LEN,
+    eventQueueAccount(
+      [fill, legacyFill, out],
+      [5, 6, 8],
+      [
+        [1, 2],
+        [3, 4],
+        [5, 6],
+      ]
+    )
+  );
+  expect(eventQueue.header.tag).toBe(AccountTag.EventQueue);
+  expect(eventQueue.header.count.toNumber()).toBe(3);
+  expect(eventQueue.header.headSeqNum.toNumber()).toBe(5);
+  expect(eventQueue.header.tailSeqNum.toNumber()).toBe(9);
+
+  const first = eventQueue.peekAt(0) as EventFill;
+  expect(first).toBeInstanceOf(EventFill);
+  expect(first.takerSide).toBe(Side.Ask);
+  expect(first.quoteSize.toNumber()).toBe(1_500);
+  expect(first.makerOrderId.toNumber()).toBe(42);
+  expect(first.baseSize.toNumber()).toBe(100);
+  expect(first.price?.toString()).toBe(new BN(15).shln(32).toString());
+  expect(first.takerOrderId?.toNumber()).toBe(43);
+  expect(first.seqNum.toNumber()).toBe(5);
+  expect(first.makerCallbackInfo).toStrictEqual([1, 2]);
+
+  const second = eventQueue.peekAt(1) as EventFill;
+  expect(second.baseSize.toNumber()).toBe(100);
+  expect(second.price).toBeUndefined();
+  expect(second.takerOrderId).toBeUndefined();
+  expect(second.seqNum.toNumber()).toBe(6);
+
+  // Sequence numbers skip the events popped from the back
+  const third = eventQueue.peekAt(2) as EventOut;
+  expect(third).toBeInstanceOf(EventOut);
+  expect(third.side).toBe(Side.Bid);
+  expect(third.orderId.toNumber()).toBe(44);
+  expect(third.baseSize.toNumber()).toBe(7);
+  expect(third.seqNum.toNumber()).toBe(8);
+  expect(third.callbackInfo).toStrictEqual([5, 6]);
+
+  const rejected = EventQueue.parse(
+    CALLBACK_INFO_LEN,
+    eventQueueAccount([reject], [0], [[7, 8]])
+  ).peekAt(0) as EventReject;
+  expect(rejected).toBeInstanceOf(EventReject);
+  expect(rejected.reason).toBe(RejectReason.TooManyLevels);
+  expect(rejected.batchIndex.toNumber()).toBe(3);
+  expect(rejected.baseSize.toNumber()).toBe(5);
+  expect(rejected.callbackInfo).toStrictEqual([7, 8]);
+});
+
+test("decode tick events", () => {
+  const tick = Buffer.alloc(EventFill.LEN);
+  tick.writeUInt8(EventType.Tick, 0);
+  tick.writeBigInt64LE(BigInt(-1), 8);
+  tick.writeBigUInt64LE(BigInt(12), 16);
+
+  const event = EventQueue.parse(
+    CALLBACK_INFO_LEN,
+    eventQueueAccount([tick], [12], [[0, 0]])
+  ).peekAt(0) as EventTick;
+  expect(event).toBeInstanceOf(EventTick);
+  expect(event.timestamp.toNumber()).toBe(-1);
+  expect(event.seqNum.toNumber()).toBe(12);
+});
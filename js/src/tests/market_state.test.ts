@@ -0,0 +1,92 @@
+import { expect, test } from "@jest/globals";
+import { Keypair } from "@solana/web3.js";
+
+import { AccountTag, MarketFlags, MarketState } from "../market_state";
+import { InnerNode, LeafNode, Slab, SlabHeader } from "../slab";
+
+test("decode market state", () => {
+  const [eventQueue, bids, asks, authority, feeRecipient] = [...Array(5)].map(
+    () => new Keypair().publicKey
+  );
+  const data = Buffer.alloc(MarketState.LEN);
+  data.writeBigUInt64LE(BigInt(AccountTag.Market), 0);
+  eventQueue.toBuffer().copy(data, 8);
+  bids.toBuffer().copy(data, 40);
+  asks.toBuffer().copy(data, 72);
+  data.writeBigUInt64LE(BigInt(10), 104);
+  data.writeBigUInt64LE(BigInt(1) << BigInt(32), 112);
+  data.writeBigUInt64LE(BigInt(5), 120);
+  authority.toBuffer().copy(data, 128);
+  feeRecipient.toBuffer().copy(data, 160);
+  data.writeBigUInt64LE(BigInt(3) << BigInt(32), 192);
+  data.writeUInt32LE(20, 200);
+  data.writeUInt16LE(500, 204);
+  data.writeUInt16LE(MarketFlags.AsksPaused | MarketFlags.FillsOnly, 206);
+  data.writeUInt16LE(8, 208);
+  data.writeUInt16LE(2_500, 210);
+  data.writeUInt16LE(25, 212);
+  data.writeUInt16LE(4, 214);
+  data.writeUInt16LE(16, 216);
+  data.writeUInt8(1, 218);
+
+  const marketState = MarketState.deserialize(data, 32);
+  expect(marketState.tag.toNumber()).toBe(AccountTag.Market);
+  expect(marketState.eventQueue.equals(eventQueue)).toBe(true);
+  expect(marketState.bids.equals(bids)).toBe(true);
+  expect(marketState.asks.equals(asks)).toBe(true);
+  expect(marketState.minBaseOrderSize.toNumber()).toBe(10);
+  expect(marketState.tickSize.toNumber()).toBe(2 ** 32);
+  expect(marketState.baseLotSize.toNumber()).toBe(5);
+  expect(marketState.authority.equals(authority)).toBe(true);
+  expect(marketState.feeRecipient.equals(feeRecipient)).toBe(true);
+  expect(marketState.lastTradePrice.toNumber()).toBe(3 * 2 ** 32);
+  expect(marketState.maxLevelsPerSide).toBe(20);
+  expect(marketState.priceBandBps).toBe(500);
+  expect(marketState.flags & MarketFlags.AsksPaused).toBeTruthy();
+  expect(marketState.flags & MarketFlags.BidsPaused).toBeFalsy();
+  expect(marketState.maxOrdersPerOwner).toBe(8);
+  expect(marketState.makerPriceImprovementBps).toBe(2_500);
+  expect(marketState.takerFeeBps).toBe(25);
+  expect(marketState.selfTradeMatchOffset).toBe(4);
+  expect(marketState.selfTradeMatchLen).toBe(16);
+  expect(marketState.selfTradeMatchKind).toBe(1);
+  expect(marketState.callbackInfoLen).toBe(32);
+});
+
+test("decode slab nodes", () => {
+  const callbackInfoLen = 4;
+  const data = Buffer.alloc(Slab.computeAllocationSize(2, callbackInfoLen));
+  expect(data.length).toBe(40 + 2 * (32 + callbackInfoLen) + 48);
+  data.writeBigUInt64LE(BigInt(AccountTag.Bids), 0);
+  // root_node (the first inner node), leaf_count
+  data.writeUInt32LE(0xffffffff, 32);
+  data.writeUInt32LE(2, 36);
+
+  // Two orders at prices 1 and 2 under a single inner node
+  const leavesOffset = SlabHeader.LEN;
+  const innerNodesOffset = leavesOffset + 2 * LeafNode.LEN;
+  [1, 2].forEach((price, i) => {
+    const offset = leavesOffset + i * LeafNode.LEN;
+    data.writeBigUInt64LE(BigInt(i), offset);
+    data.writeBigUInt64LE(BigInt(price), offset + 8);
+    data.writeBigUInt64LE(BigInt(10 * price), offset + 16);
+    data.writeBigUInt64LE(BigInt(2) ** BigInt(64) - BigInt(1), offset + 24);
+  });
+  data.writeBigUInt64LE(BigInt(2), innerNodesOffset + 8);
+  data.writeBigUInt64LE(BigInt(62), innerNodesOffset + 16);
+  data.writeUInt32LE(0, innerNodesOffset + 24);
+  data.writeUInt32LE(1, innerNodesOffset + 28);
+  data.writeBigUInt64LE(BigInt(30), innerNodesOffset + 32);
+
+  const slab = Slab.deserialize(data, callbackInfoLen);
+  expect(slab.orderCapacity).toBe(1);
+  const root = slab.getNode(slab.header.rootNode) as InnerNode;
+  expect(root).toBeInstanceOf(InnerNode);
+  expect(root.prefixLen.toNumber()).toBe(62);
+  expect(root.children).toStrictEqual([0, 1]);
+  expect(root.baseQuantity.toNumber()).toBe(30);
+
+  const depth = slab.getL2DepthJS(2, true);
+  expect(depth.map((l) => l.price.toNumber())).toStrictEqual([1, 2]);
+  expect(depth.map((l) => l.size.toNumber())).toStrictEqual([10, 20]);
+});
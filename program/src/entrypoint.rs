@@ -1,5 +1,4 @@
-use crate::{error::AoError, processor};
-use borsh::BorshSerialize;
+use crate::{error::AoError, processor, utils::write_register_output};
 use num_traits::FromPrimitive;
 use solana_program::{
     account_info::AccountInfo, decode_error::DecodeError, entrypoint::ProgramResult, msg,
@@ -19,6 +18,7 @@ pub fn process_instruction(
 ) -> ProgramResult {
     msg!("Entrypoint");
     let (register_account, accounts) = accounts.split_last().unwrap();
+    let register_before = register_account.data.borrow().to_vec();
     match processor::process_instruction::<[u8; 32]>(program_id, accounts, instruction_data) {
         Err(error) => {
             // catch the error so we can print it
@@ -26,8 +26,12 @@ pub fn process_instruction(
             return Err(error);
         }
         Ok(r) => {
-            let mut a: &mut [u8] = &mut register_account.data.borrow_mut();
-            r.serialize(&mut a).unwrap();
+            // The output is only written once the instruction has been fully processed
+            write_register_output(
+                &mut register_account.data.borrow_mut(),
+                &register_before,
+                &r,
+            )?;
         }
     }
     Ok(())
@@ -55,7 +55,63 @@ pub enum AoError {
     #[error("Numerical overlflow")]
     NumericalOverflow,
     #[error("Invalid callback info")]
-    InvalidCallbackInfo,
+    InvalidCallbackInfo, // 25
+    #[error("An invalid market authority has been provided")]
+    WrongMarketAuthority,
+    #[error("The batch contains too many orders")]
+    BatchTooLarge,
+    #[error("The bids and asks accounts don't match the market's order book")]
+    WrongBookAccount,
+    #[error("The quote size doesn't fit in a u64")]
+    QuoteOverflow,
+    #[error("The order size must be greater than zero")]
+    ZeroOrderSize, // 30
+    #[error("The event queue's header is inconsistent with its size")]
+    CorruptQueue,
+    #[error("The orderbook holds more orders than the traversal limit")]
+    TraversalLimitExceeded,
+    #[error("The account is too small to hold its header and a single entry")]
+    AccountTooSmall,
+    #[error("The event queue contains an event which can't be read")]
+    InvalidEvent,
+    #[error("The limit price is outside of the market's price band")]
+    PriceOutOfBand, // 35
+    #[error("The account's tag doesn't match its expected type")]
+    WrongAccountTag,
+    #[error("The account isn't owned by the expected program")]
+    WrongAccountOwner,
+    #[error("The account should be writable")]
+    AccountNotWritable,
+    #[error("The best bid is greater than or equal to the best ask")]
+    BookCrossed,
+    #[error("The value doesn't fit in the register at the given offset")]
+    RegisterOutOfBounds, // 40
+    #[error("The owner has too many resting orders")]
+    TooManyOrders,
+    #[error("New orders are paused on this side of the book")]
+    SidePaused,
+    #[error("The register was written to while the instruction was being processed")]
+    RegisterClobbered,
+    #[error("The order's base size isn't a multiple of the market's base lot size")]
+    InvalidLotSize,
+    #[error("The slab's free lists are corrupted")]
+    CorruptSlab, // 45
+    #[error("An order with the same order id is already in the orderbook")]
+    DuplicateOrderId,
+    #[error("The order would open a new price level beyond the market's maximum number of levels per side")]
+    TooManyLevels,
+    #[error("The event queue is empty")]
+    EventQueueEmpty,
+    #[error("The event queue's size isn't a whole number of events for its callback info length")]
+    EventSizeMismatch,
+    #[error("The callback info is longer than the program's callback info length")]
+    CallbackInfoLengthMismatch,
+    #[error("The pending events don't fit in the new event queue account")]
+    WontFit,
+    #[error("The account data isn't aligned for its fields to be read in place")]
+    UnalignedAccountData,
+    #[error("The fee in basis points must be at most 10_000")]
+    InvalidFeeBps,
 }
 
 impl From<AoError> for ProgramError {
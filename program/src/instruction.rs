@@ -8,8 +8,8 @@ use solana_program::{
 use bonfida_utils::{BorshSize, InstructionsAccount};
 
 pub use crate::processor::{
-    cancel_order, close_market, consume_events, create_market, mass_cancel_orders, new_order,
-    prune_orders,
+    cancel_all, cancel_order, close_market, consume_events, consume_events_with_merkle_root,
+    create_market, evict_expired, mass_cancel_orders, new_order, post_orders_batch, prune_orders,
 };
 #[derive(BorshDeserialize, BorshSerialize, FromPrimitive)]
 /// Describes all possible instructions and their required accounts
@@ -101,6 +101,51 @@ pub enum AgnosticOrderbookInstruction {
     /// | 3     | ✅       | ❌     | The asks account        |
     /// | 4     | ❌       | ✅     | The caller authority    |
     PruneOrders,
+    /// Post a batch of orders to the orderbook without matching them.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ✅       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    PostOrdersBatch,
+    /// Evict the orders which have exceeded their max timestamp from the orderbook.
+    ///
+    /// This instruction is permissionless.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description             |
+    /// |-------|----------|--------|-------------------------|
+    /// | 0     | ❌       | ❌     | The market account      |
+    /// | 1     | ✅       | ❌     | The event queue account |
+    /// | 2     | ✅       | ❌     | The bids account        |
+    /// | 3     | ✅       | ❌     | The asks account        |
+    EvictExpired,
+    /// Cancel the orders in the orderbook regardless of their owner, for an emergency wind-down of the market.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                |
+    /// |-------|----------|--------|----------------------------|
+    /// | 0     | ❌       | ❌     | The market account         |
+    /// | 1     | ✅       | ❌     | The event queue account    |
+    /// | 2     | ✅       | ❌     | The bids account           |
+    /// | 3     | ✅       | ❌     | The asks account           |
+    /// | 4     | ❌       | ✅     | The market authority       |
+    CancelAll,
+    /// Pop a series of events off the event queue, committing to them with a Merkle root.
+    ///
+    /// Required accounts
+    ///
+    /// | index | writable | signer | description                  |
+    /// |-------|----------|--------|------------------------------|
+    /// | 0     | ✅       | ❌     | The market account           |
+    /// | 1     | ✅       | ❌     | The event queue account      |
+    ConsumeEventsWithMerkleRoot,
 }
 
 /**
@@ -181,6 +226,8 @@ pub fn cancel_order(
 }
 
 /// Pop a series of events off the event queue.
+///
+/// The crank fee owed for the consumed events is written to the register account as a borsh-serialized `u64`.
 pub fn consume_events(
     accounts: consume_events::Accounts<Pubkey>,
     register_account: Pubkey,
@@ -256,4 +303,88 @@ pub fn prune_orders(
         is_writable: true,
     });
     i
-}
\ No newline at end of file
+}
+
+/// Post a batch of orders to the orderbook without matching them.
+///
+/// The batch is rejected as a whole if any of its orders can't be posted, unless
+/// [`allow_partial`][`post_orders_batch::Params::allow_partial`] is set. The ids of the posted orders are written to the
+/// register account as a borsh-serialized `Vec<OrderId>`, which needs a register of at least
+/// [`INSTRUCTION_OUTPUT_SIZE`][`crate::utils::INSTRUCTION_OUTPUT_SIZE`] bytes.
+pub fn post_orders_batch<C: BorshSerialize + BorshSize>(
+    accounts: post_orders_batch::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: post_orders_batch::Params<C>,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::PostOrdersBatch as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Evict up to a given number of expired orders from the orderbook.
+pub fn evict_expired(
+    accounts: evict_expired::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: evict_expired::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::EvictExpired as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Cancel up to a given number of orders from the orderbook regardless of their owner.
+pub fn cancel_all(
+    accounts: cancel_all::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: cancel_all::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::CancelAll as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
+
+/// Pop a series of events off the event queue, committing to them with a Merkle root.
+///
+/// Like [`consume_events`], the register account receives the crank fee owed for the consumed events, followed by the
+/// root of a Merkle tree of the consumed events, see [`ConsumeSummary`][`crate::state::event_queue::ConsumeSummary`].
+pub fn consume_events_with_merkle_root(
+    accounts: consume_events_with_merkle_root::Accounts<Pubkey>,
+    register_account: Pubkey,
+    params: consume_events_with_merkle_root::Params,
+) -> Instruction {
+    let mut i = accounts.get_instruction(
+        crate::id(),
+        AgnosticOrderbookInstruction::ConsumeEventsWithMerkleRoot as u8,
+        params,
+    );
+    i.accounts.push(AccountMeta {
+        pubkey: register_account,
+        is_signer: false,
+        is_writable: true,
+    });
+    i
+}
@@ -63,4 +63,7 @@ pub(crate) mod processor;
 /// Utility functions
 pub mod utils;
 
+#[cfg(test)]
+pub(crate) mod test_harness;
+
 declare_id!("aaobKniTtDGvCZces7GH5UReLYP671bBkB96ahr9x3e");
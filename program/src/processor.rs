@@ -4,24 +4,56 @@ use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError
 
 use crate::{
     instruction::AgnosticOrderbookInstruction,
-    state::orderbook::{CallbackInfo, OrderSummary},
+    state::{
+        event_queue::ConsumeSummary,
+        orderbook::{CallbackInfo, OrderSummary},
+        OrderId,
+    },
 };
 
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 
+pub mod cancel_all;
 pub mod cancel_order;
 pub mod close_market;
 pub mod consume_events;
+pub mod consume_events_with_merkle_root;
 pub mod create_market;
+pub mod evict_expired;
 pub mod mass_cancel_orders;
 pub mod new_order;
+pub mod post_orders_batch;
 pub mod prune_orders;
 
+/// The output of an instruction, which is written to the register account.
+///
+/// Instructions never write to the register themselves: their output is returned to the entrypoint, which writes it
+/// once processing is over, see [`write_register_output`][`crate::utils::write_register_output`].
+pub enum InstructionOutput {
+    /// The summary of an order related instruction, or `None` for instructions which don't produce one
+    OrderSummary(Option<OrderSummary>),
+    /// The summary of the events popped by a consume_events or consume_events_with_merkle_root instruction, starting
+    /// with the crank fee owed for them
+    ConsumeSummary(ConsumeSummary),
+    /// The ids of the orders posted by a post_orders_batch instruction, in batch order
+    OrderIds(Vec<OrderId>),
+}
+
+impl BorshSerialize for InstructionOutput {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            InstructionOutput::OrderSummary(o) => o.serialize(writer),
+            InstructionOutput::ConsumeSummary(s) => s.serialize(writer),
+            InstructionOutput::OrderIds(ids) => ids.serialize(writer),
+        }
+    }
+}
+
 pub fn process_instruction<C: Pod + BorshDeserialize + CallbackInfo + PartialEq>(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
-) -> Result<Option<OrderSummary>, ProgramError>
+) -> Result<InstructionOutput, ProgramError>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
@@ -44,21 +76,24 @@ where
             let accounts = new_order::Accounts::parse(accounts)?;
             let params = new_order::Params::<C>::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return new_order::process(program_id, accounts, params).map(Some);
+            return new_order::process(program_id, accounts, params)
+                .map(|o| InstructionOutput::OrderSummary(Some(o)));
         }
         AgnosticOrderbookInstruction::ConsumeEvents => {
             msg!("Instruction: Consume Events");
             let accounts = consume_events::Accounts::parse(accounts)?;
             let params = consume_events::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            consume_events::process::<C>(program_id, accounts, params)?;
+            return consume_events::process::<C>(program_id, accounts, params)
+                .map(InstructionOutput::ConsumeSummary);
         }
         AgnosticOrderbookInstruction::CancelOrder => {
             msg!("Instruction: Cancel Order");
             let accounts = cancel_order::Accounts::parse(accounts)?;
             let params = cancel_order::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return cancel_order::process::<C>(program_id, accounts, params).map(Some);
+            return cancel_order::process::<C>(program_id, accounts, params)
+                .map(|o| InstructionOutput::OrderSummary(Some(o)));
         }
         AgnosticOrderbookInstruction::CloseMarket => {
             msg!("Instruction: Close Market");
@@ -70,7 +105,8 @@ where
             let accounts = mass_cancel_orders::Accounts::parse(accounts)?;
             let params = mass_cancel_orders::Params::try_from_slice(instruction_data)
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
-            return mass_cancel_orders::process::<C>(program_id, accounts, params).map(Some);
+            return mass_cancel_orders::process::<C>(program_id, accounts, params)
+                .map(|o| InstructionOutput::OrderSummary(Some(o)));
         }
         AgnosticOrderbookInstruction::PruneOrders => {
             msg!("Instruction: Prune Orders");
@@ -79,6 +115,36 @@ where
                 .map_err(|_| ProgramError::InvalidInstructionData)?;
             prune_orders::process::<C>(program_id, accounts, params)?;
         }
+        AgnosticOrderbookInstruction::PostOrdersBatch => {
+            msg!("Instruction: Post Orders Batch");
+            let accounts = post_orders_batch::Accounts::parse(accounts)?;
+            let params = post_orders_batch::Params::<C>::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return post_orders_batch::process(program_id, accounts, params)
+                .map(InstructionOutput::OrderIds);
+        }
+        AgnosticOrderbookInstruction::EvictExpired => {
+            msg!("Instruction: Evict Expired");
+            let accounts = evict_expired::Accounts::parse(accounts)?;
+            let params = evict_expired::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            evict_expired::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::CancelAll => {
+            msg!("Instruction: Cancel All");
+            let accounts = cancel_all::Accounts::parse(accounts)?;
+            let params = cancel_all::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            cancel_all::process::<C>(program_id, accounts, params)?;
+        }
+        AgnosticOrderbookInstruction::ConsumeEventsWithMerkleRoot => {
+            msg!("Instruction: Consume Events With Merkle Root");
+            let accounts = consume_events_with_merkle_root::Accounts::parse(accounts)?;
+            let params = consume_events_with_merkle_root::Params::try_from_slice(instruction_data)
+                .map_err(|_| ProgramError::InvalidInstructionData)?;
+            return consume_events_with_merkle_root::process::<C>(program_id, accounts, params)
+                .map(InstructionOutput::ConsumeSummary);
+        }
     }
-    Ok(None)
+    Ok(InstructionOutput::OrderSummary(None))
 }
@@ -14,7 +14,7 @@ use crate::state::orderbook::{CallbackInfo, OrderBookState, OrderSummary};
 use crate::state::AccountTag;
 use crate::{
     error::AoError,
-    state::{get_side_from_order_id, market_state::MarketState},
+    state::{market_state::MarketState, OrderId},
     utils::{check_account_key, check_account_owner},
 };
 #[derive(BorshDeserialize, BorshSerialize, Clone, BorshSize)]
@@ -23,7 +23,7 @@ The required arguments for a cancel_order instruction.
 */
 pub struct Params {
     /// The order id is a unique identifier for a particular order
-    pub order_id: u128,
+    pub order_id: OrderId,
 }
 
 /// The required accounts for a cancel_order instruction.
@@ -92,22 +92,27 @@ where
 
     let mut order_book = OrderBookState::<C>::new_safe(&mut bids_guard, &mut asks_guard)?;
 
-    let slab = order_book.get_tree(get_side_from_order_id(params.order_id));
-    let (leaf_node, _) = slab
-        .remove_by_key(params.order_id)
-        .ok_or(AoError::OrderNotFound)?;
-    let total_base_qty = leaf_node.base_quantity;
-    let total_quote_qty = fp32_mul_floor(leaf_node.base_quantity, leaf_node.price())
-        .ok_or(AoError::NumericalOverflow)?;
+    let order_summary = execute(&mut order_book, params.order_id)?;
 
-    let order_summary = OrderSummary {
+    Ok(order_summary)
+}
+
+/// Removes an order from already loaded accounts, and summarizes what was left of it.
+pub(crate) fn execute<C: Pod>(
+    order_book: &mut OrderBookState<'_, C>,
+    order_id: OrderId,
+) -> Result<OrderSummary, AoError> {
+    let removed = order_book.cancel_order(order_id)?;
+    let total_quote_qty =
+        fp32_mul_floor(removed.base_quantity, removed.price).ok_or(AoError::NumericalOverflow)?;
+
+    Ok(OrderSummary {
         posted_order_id: None,
-        total_base_qty,
+        total_base_qty: removed.base_quantity,
         total_quote_qty,
         total_base_qty_posted: 0,
-    };
-
-    Ok(order_summary)
+        total_fee: 0,
+    })
 }
 
 fn check_accounts<'a, 'b: 'a>(
@@ -7,7 +7,7 @@ use crate::{
         orderbook::{CallbackInfo, OrderBookState},
         AccountTag,
     },
-    utils::{check_account_key, check_account_owner},
+    utils::{check_account_key, check_account_owner, check_writable},
 };
 use bonfida_utils::{BorshSize, InstructionsAccount};
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -66,6 +66,7 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
             &program_id.to_bytes(),
             AoError::WrongMarketOwner,
         )?;
+        check_writable(self.lamports_target_account)?;
         Ok(())
     }
 }
@@ -13,7 +13,10 @@ use solana_program::{
 use crate::{
     error::AoError,
     state::{
-        event_queue::EventQueue, market_state::MarketState, orderbook::CallbackInfo, AccountTag,
+        event_queue::{ConsumeSummary, EventQueue},
+        market_state::MarketState,
+        orderbook::CallbackInfo,
+        AccountTag,
     },
     utils::{check_account_key, check_account_owner},
 };
@@ -63,12 +66,23 @@ impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
     }
 }
 
-/// Apply the consume_events instruction to the provided accounts
+/// Apply the consume_events instruction to the provided accounts, returning the summary of the consumed events
 pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
     program_id: &Pubkey,
     accounts: Accounts<'a, AccountInfo<'b>>,
     params: Params,
-) -> ProgramResult {
+) -> Result<ConsumeSummary, ProgramError> {
+    consume::<C>(program_id, accounts, params, false)
+}
+
+/// Pops the events off the event queue, committing to them with a Merkle root if `merkle_root` is set, see
+/// [`EventQueue::consume_with_merkle_root`]
+pub(crate) fn consume<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+    merkle_root: bool,
+) -> Result<ConsumeSummary, ProgramError> {
     accounts.perform_checks(program_id)?;
     let mut market_data = accounts.market.data.borrow_mut();
     let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
@@ -78,21 +92,20 @@ pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
     let mut event_queue =
         EventQueue::<C>::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
 
-    // Reward payout
-    let capped_number_of_entries_consumed = std::cmp::min(
-        event_queue.header.count,
-        params.number_of_entries_to_consume,
-    );
-
     // Pop Events
-    event_queue.pop_n(params.number_of_entries_to_consume);
+    let summary = if merkle_root {
+        event_queue.consume_with_merkle_root(params.number_of_entries_to_consume)
+    } else {
+        event_queue.consume_with_summary(params.number_of_entries_to_consume, |_| {})
+    };
 
-    msg!(
-        "Number of events consumed: {:?}",
-        capped_number_of_entries_consumed
-    );
+    msg!("Number of events consumed: {:?}", summary.events_consumed);
+    msg!("Crank fee owed: {:?}", summary.crank_fee);
+    if let Some(root) = summary.merkle_root {
+        msg!("Merkle root of the consumed events: {:?}", root);
+    }
 
-    Ok(())
+    Ok(summary)
 }
 
 fn check_accounts<'a, 'b: 'a>(
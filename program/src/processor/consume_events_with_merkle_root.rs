@@ -0,0 +1,23 @@
+//! Pop a series of events off the event queue, committing to them with a Merkle root.
+use bytemuck::Pod;
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::state::{event_queue::ConsumeSummary, orderbook::CallbackInfo};
+
+/// The required arguments for a consume_events_with_merkle_root instruction, which are those of a consume_events
+/// instruction.
+pub use super::consume_events::Params;
+
+/// The required accounts for a consume_events_with_merkle_root instruction, which are those of a consume_events
+/// instruction.
+pub use super::consume_events::Accounts;
+
+/// Apply the consume_events_with_merkle_root instruction to the provided accounts, returning the summary of the
+/// consumed events along with their Merkle root
+pub fn process<'a, 'b: 'a, C: CallbackInfo + Pod + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params,
+) -> Result<ConsumeSummary, ProgramError> {
+    super::consume_events::consume::<C>(program_id, accounts, params, true)
+}
@@ -15,7 +15,12 @@ use solana_program::{
 
 use crate::{
     error::AoError,
-    state::{critbit::Slab, event_queue::EventQueue, market_state::MarketState, AccountTag},
+    state::{
+        critbit::Slab,
+        event_queue::{EventQueueHeaderBuilder, OrderIdMode, OverflowPolicy},
+        market_state::{MarketFlags, MarketState},
+        SelfTradeMatch,
+    },
     utils::check_account_owner,
 };
 
@@ -28,6 +33,41 @@ pub struct Params {
     pub min_base_order_size: u64,
     /// Enables the limiting of price precision on the orderbook (price ticks)
     pub tick_size: u64,
+    /// The account which is allowed to change the market's configuration
+    pub authority: Pubkey,
+    /// The crank fee (in lamports) which accrues for each event pushed to the event queue
+    pub fee_per_event: u64,
+    /// Describes what happens when an event is pushed to the full event queue
+    pub overflow_policy: OverflowPolicy,
+    /// The maximum deviation of a new order's limit price from the last trade price, in basis points.
+    ///
+    /// A value of 0 disables the check.
+    pub price_band_bps: u16,
+    /// The maximum number of resting orders a single owner can have in the orderbook.
+    ///
+    /// A value of 0 disables the limit.
+    pub max_orders_per_owner: u16,
+    /// The base size of every order must be a multiple of the base lot size.
+    ///
+    /// A value of 0 disables the check.
+    pub base_lot_size: u64,
+    /// Describes how the callback infos of a taker and a maker are compared to detect self trading
+    pub self_trade_match: SelfTradeMatch,
+    /// Share of the price improvement given to the maker when a taker's limit price is better than the maker's
+    /// price, in basis points.
+    ///
+    /// A value of 0 gives all of the price improvement to the taker.
+    pub maker_price_improvement_bps: u16,
+    /// The maximum number of price levels on each side of the book.
+    ///
+    /// A value of 0 disables the limit.
+    pub max_levels_per_side: u32,
+    /// The account to which the caller program routes the taker fees
+    pub fee_recipient: Pubkey,
+    /// The fee charged to takers on the quote size of each fill, in basis points
+    pub taker_fee_bps: u16,
+    /// Describes how the ids of new orders are generated
+    pub order_id_mode: OrderIdMode,
 }
 
 /// The required accounts for a create_market instruction.
@@ -81,6 +121,18 @@ pub fn process<'a, 'b: 'a, C: Pod>(
     let Params {
         min_base_order_size,
         tick_size,
+        authority,
+        fee_per_event,
+        overflow_policy,
+        price_band_bps,
+        max_orders_per_owner,
+        base_lot_size,
+        self_trade_match,
+        maker_price_improvement_bps,
+        max_levels_per_side,
+        fee_recipient,
+        taker_fee_bps,
+        order_id_mode,
     } = params;
 
     check_rent(&accounts)?;
@@ -90,12 +142,20 @@ pub fn process<'a, 'b: 'a, C: Pod>(
         return Err(ProgramError::InvalidArgument);
     }
 
-    EventQueue::<C>::check_buffer_size(&accounts.event_queue.data.borrow()).unwrap();
+    if !self_trade_match.is_valid::<C>() {
+        msg!("The self trade match range must be non-empty and within the callback info");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if maker_price_improvement_bps > 10_000 || taker_fee_bps > 10_000 {
+        msg!("maker_price_improvement_bps and taker_fee_bps must be <= 10_000");
+        return Err(ProgramError::InvalidArgument);
+    }
 
     let mut market_data = accounts.market.data.borrow_mut();
 
     MarketState::check_buffer_size(&market_data)?;
-    let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Uninitialized)?;
+    let market_state = MarketState::initialize(&mut market_data)?;
 
     *market_state = MarketState {
         event_queue: *accounts.event_queue.key,
@@ -103,11 +163,28 @@ pub fn process<'a, 'b: 'a, C: Pod>(
         asks: *accounts.asks.key,
         min_base_order_size,
         tick_size,
+        base_lot_size,
+        authority,
+        fee_recipient,
+        last_trade_price: 0,
+        max_levels_per_side,
+        price_band_bps,
+        flags: MarketFlags::default(),
+        max_orders_per_owner,
+        maker_price_improvement_bps,
+        taker_fee_bps,
+        self_trade_match_offset: 0,
+        self_trade_match_len: 0,
+        self_trade_match_kind: 0,
+        _padding: [0; 5],
     };
+    market_state.set_self_trade_match(self_trade_match);
 
-    let mut event_queue_data = accounts.event_queue.data.borrow_mut();
-
-    EventQueue::<C>::from_buffer(&mut event_queue_data, AccountTag::Uninitialized)?;
+    EventQueueHeaderBuilder::new()
+        .fee_per_event(fee_per_event)
+        .overflow_policy(overflow_policy)
+        .order_id_mode(order_id_mode)
+        .initialize::<C>(&mut accounts.event_queue.data.borrow_mut())?;
 
     Slab::<C>::initialize(
         &mut accounts.asks.data.borrow_mut(),
@@ -13,10 +13,9 @@ use solana_program::{
 use crate::{
     error::AoError,
     state::{
-        get_side_from_order_id,
         market_state::MarketState,
         orderbook::{CallbackInfo, OrderBookState, OrderSummary},
-        AccountTag,
+        AccountTag, OrderId,
     },
     utils::{check_account_key, check_account_owner},
 };
@@ -26,7 +25,7 @@ The required arguments for a cancel_order instruction.
 */
 pub struct Params {
     /// The order id is a unique identifier for a particular order
-    pub order_ids: Vec<u128>,
+    pub order_ids: Vec<OrderId>,
 }
 
 /// The required accounts for a cancel_order instruction.
@@ -98,8 +97,10 @@ where
     let mut total_quote_qty = 0u64;
 
     for order_id in params.order_ids {
-        let slab = order_book.get_tree(get_side_from_order_id(order_id));
-        let (leaf_node, _) = slab.remove_by_key(order_id).ok_or(AoError::OrderNotFound)?;
+        let slab = order_book.get_tree(order_id.side());
+        let (leaf_node, _) = slab
+            .remove_by_key(order_id.into())
+            .ok_or(AoError::OrderNotFound)?;
         total_base_qty = total_base_qty.checked_add(leaf_node.base_quantity).unwrap();
         total_quote_qty = fp32_mul_floor(leaf_node.base_quantity, leaf_node.price())
             .and_then(|n| n.checked_add(total_quote_qty))
@@ -111,6 +112,7 @@ where
         total_base_qty,
         total_quote_qty,
         total_base_qty_posted: 0,
+        total_fee: 0,
     };
 
     Ok(order_summary)
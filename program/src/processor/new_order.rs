@@ -15,11 +15,11 @@ use crate::{
     error::AoError,
     state::{
         event_queue::EventQueue,
-        market_state::MarketState,
+        market_state::{MarketFlags, MarketState},
         orderbook::{CallbackInfo, OrderBookState},
-        AccountTag, OrderSummary, SelfTradeBehavior, Side,
+        AccountTag, OrderSummary, PostOnly, SelfTradeBehavior, Side,
     },
-    utils::{check_account_key, check_account_owner},
+    utils::{check_account_key, check_account_owner, check_book_accounts},
 };
 
 #[derive(Clone, BorshSerialize, BorshDeserialize)]
@@ -43,12 +43,14 @@ pub struct Params<C> {
     pub match_limit: u64,
     /// The callback information is used to attach metadata to an order. This callback information will be transmitted back through the event queue.
     ///
-    /// The size of this vector should not exceed the current market's [`callback_info_len`][`MarketState::callback_info_len`].
+    /// Its size is fixed by the callback info type `C` the program was built with, and is shared by every market
+    /// and event queue handled by that program.
     pub callback_info: C,
-    /// The order will not be matched against the orderbook and will be direcly written into it.
+    /// Whether the order will be matched against the orderbook or directly written into it.
     ///
-    /// The operation will fail if the order's limit_price crosses the spread.
-    pub post_only: bool,
+    /// With [`PostOnly::Slide`], an order which would cross the spread is repriced to one tick inside the best
+    /// opposing price.
+    pub post_only: PostOnly,
     /// The order will be matched against the orderbook, but what remains will not be written as a new order into the orderbook.
     pub post_allowed: bool,
     /// Describes what would happen if this order was matched against an order with an equal `callback_info` field.
@@ -76,6 +78,7 @@ impl<C: BorshSize> BorshSize for Params<C> {
 #[derive(InstructionsAccount)]
 pub struct Accounts<'a, T> {
     #[allow(missing_docs)]
+    #[cons(writable)]
     pub market: &'a T,
     #[allow(missing_docs)]
     #[cons(writable)]
@@ -132,29 +135,91 @@ where
 
     check_accounts(&accounts, market_state)?;
 
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+    let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    let clock = Clock::get()?;
+    event_queue.set_timestamp(clock.unix_timestamp);
+    let cur_ts = clock.unix_timestamp as u64;
+
+    let order_summary = execute(
+        market_state,
+        &mut order_book,
+        &mut event_queue,
+        params,
+        cur_ts,
+    )?;
+    msg!("Order summary : {:?}", order_summary);
+
+    Ok(order_summary)
+}
+
+/// Applies a new order to already loaded accounts, from the market's checks to the recording of its trades.
+///
+/// This holds the whole of the instruction's logic, so that it can be run without a Solana runtime.
+pub(crate) fn execute<C: Pod + CallbackInfo + PartialEq>(
+    market_state: &mut MarketState,
+    order_book: &mut OrderBookState<'_, C>,
+    event_queue: &mut EventQueue<'_, C>,
+    mut params: Params<C>,
+    cur_ts: u64,
+) -> Result<OrderSummary, AoError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    market_state.check_side_not_paused(params.side)?;
+    market_state.check_lot_size(params.max_base_qty)?;
+
     if params.limit_price % market_state.tick_size != 0 {
-        return Err(AoError::InvalidLimitPrice.into());
+        return Err(AoError::InvalidLimitPrice);
+    }
+
+    order_book.self_trade_match = market_state.self_trade_match();
+    order_book.maker_price_improvement_bps = market_state.maker_price_improvement_bps;
+    order_book.log_matches = market_state.flags.get(MarketFlags::LOG_MATCHES);
+    order_book.taker_fee_bps = market_state.taker_fee_bps;
+    order_book.fills_only = market_state.flags.get(MarketFlags::FILLS_ONLY);
+
+    if params.post_only == PostOnly::Slide {
+        params.limit_price =
+            order_book.slide_limit_price(params.side, params.limit_price, market_state.tick_size);
     }
 
     if params.post_allowed && params.limit_price < market_state.tick_size {
         msg!(
             "Can't attempt to post an order of price less than market tick size to the orderbook!"
         );
-        return Err(AoError::InvalidLimitPrice.into());
+        return Err(AoError::InvalidLimitPrice);
     }
 
-    let mut bids_guard = accounts.bids.data.borrow_mut();
-    let mut asks_guard = accounts.asks.data.borrow_mut();
-
-    let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
+    market_state.check_price_band(params.limit_price)?;
 
-    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
-    let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
-    let cur_ts = Clock::get()?.unix_timestamp as u64;
+    let callback_info = params.callback_info;
+    let first_event_seq_num = event_queue.next_event_seq_num();
+    let order_summary = order_book.new_order(
+        params,
+        event_queue,
+        market_state.min_base_order_size,
+        cur_ts,
+    )?;
+    if let Some(order_id) = order_summary.posted_order_id {
+        order_book.check_orders_per_owner(
+            callback_info.as_callback_id(),
+            market_state.max_orders_per_owner,
+        )?;
+        order_book.check_new_levels(
+            order_id.side(),
+            &[order_id],
+            market_state.max_levels_per_side,
+        )?;
+    }
+    #[cfg(feature = "debug-asserts")]
+    order_book.assert_not_crossed()?;
 
-    let order_summary =
-        order_book.new_order(params, &mut event_queue, market_state.min_base_order_size, cur_ts)?;
-    msg!("Order summary : {:?}", order_summary);
+    market_state.record_trades(event_queue, first_event_seq_num);
 
     Ok(order_summary)
 }
@@ -168,8 +233,7 @@ fn check_accounts<'a, 'b: 'a>(
         &market_state.event_queue,
         AoError::WrongEventQueueAccount,
     )?;
-    check_account_key(accounts.bids, &market_state.bids, AoError::WrongBidsAccount)?;
-    check_account_key(accounts.asks, &market_state.asks, AoError::WrongAsksAccount)?;
+    check_book_accounts(accounts.bids, accounts.asks, market_state)?;
 
     Ok(())
 }
@@ -0,0 +1,214 @@
+//! Post a batch of orders to the orderbook without matching them
+use bonfida_utils::{BorshSize, InstructionsAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::AoError,
+    state::{
+        event_queue::{EventQueue, RejectReason},
+        market_state::MarketState,
+        orderbook::{CallbackInfo, OrderBookState},
+        AccountTag, OrderId, Side,
+    },
+    utils::{check_account_key, check_account_owner, check_book_accounts},
+};
+
+/// The maximum number of orders which can be posted by a single post_orders_batch instruction.
+pub const MAX_BATCH_SIZE: usize = 16;
+
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+/// An order to be posted as part of a post_orders_batch instruction.
+pub struct BatchOrder<C> {
+    /// The order's side.
+    pub side: Side,
+    /// The limit price of the order. This value is understood as a 32-bit fixed point number.
+    /// Must be a tick size multiple (see [`round_price`][`crate::utils::round_price`])
+    pub limit_price: u64,
+    /// The quantity of base to be posted.
+    pub base_qty: u64,
+    /// The callback information is used to attach metadata to an order. This callback information will be transmitted back through the event queue.
+    pub callback_info: C,
+}
+
+impl<C: BorshSize> BorshSize for BatchOrder<C> {
+    fn borsh_len(&self) -> usize {
+        self.side.borsh_len()
+            + self.limit_price.borsh_len()
+            + self.base_qty.borsh_len()
+            + self.callback_info.borsh_len()
+    }
+}
+
+#[derive(Clone, BorshSerialize, BorshDeserialize)]
+/**
+The required arguments for a post_orders_batch instruction.
+*/
+pub struct Params<C> {
+    /// The orders to post, at most [`MAX_BATCH_SIZE`] of them.
+    ///
+    /// None of the orders can cross the spread.
+    pub orders: Vec<BatchOrder<C>>,
+    /// If any of the orders can't be posted, the whole batch is rejected unless this is set. When set, the orders
+    /// which can't be posted are rejected individually with a Reject event, and the other orders are still posted.
+    pub allow_partial: bool,
+}
+
+impl<C: BorshSize> BorshSize for Params<C> {
+    fn borsh_len(&self) -> usize {
+        4 + self.orders.iter().map(|o| o.borsh_len()).sum::<usize>()
+            + self.allow_partial.borsh_len()
+    }
+}
+
+/// The required accounts for a post_orders_batch instruction.
+#[derive(InstructionsAccount)]
+pub struct Accounts<'a, T> {
+    #[allow(missing_docs)]
+    pub market: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub event_queue: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub bids: &'a T,
+    #[allow(missing_docs)]
+    #[cons(writable)]
+    pub asks: &'a T,
+}
+
+impl<'a, 'b: 'a> Accounts<'a, AccountInfo<'b>> {
+    pub(crate) fn parse(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let a = Self {
+            market: next_account_info(accounts_iter)?,
+            event_queue: next_account_info(accounts_iter)?,
+            bids: next_account_info(accounts_iter)?,
+            asks: next_account_info(accounts_iter)?,
+        };
+        Ok(a)
+    }
+    pub(crate) fn perform_checks(&self, program_id: &Pubkey) -> Result<(), ProgramError> {
+        check_account_owner(
+            self.market,
+            &program_id.to_bytes(),
+            AoError::WrongMarketOwner,
+        )?;
+        check_account_owner(
+            self.event_queue,
+            &program_id.to_bytes(),
+            AoError::WrongEventQueueOwner,
+        )?;
+        check_account_owner(self.bids, &program_id.to_bytes(), AoError::WrongBidsOwner)?;
+        check_account_owner(self.asks, &program_id.to_bytes(), AoError::WrongAsksOwner)?;
+        Ok(())
+    }
+}
+
+/// Apply the post_orders_batch instruction to the provided accounts, returning the ids of the posted orders
+pub fn process<'a, 'b: 'a, C: Pod + CallbackInfo + PartialEq>(
+    program_id: &Pubkey,
+    accounts: Accounts<'a, AccountInfo<'b>>,
+    params: Params<C>,
+) -> Result<Vec<OrderId>, ProgramError>
+where
+    <C as CallbackInfo>::CallbackId: PartialEq,
+{
+    accounts.perform_checks(program_id)?;
+    let mut market_data = accounts.market.data.borrow_mut();
+    let market_state = MarketState::from_buffer(&mut market_data, AccountTag::Market)?;
+
+    check_accounts(&accounts, market_state)?;
+
+    if !params.allow_partial {
+        for order in params.orders.iter() {
+            check_order(market_state, order).map_err(AoError::from)?;
+        }
+    }
+
+    let mut bids_guard = accounts.bids.data.borrow_mut();
+    let mut asks_guard = accounts.asks.data.borrow_mut();
+
+    let mut order_book = OrderBookState::new_safe(&mut bids_guard, &mut asks_guard)?;
+
+    let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
+    let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    event_queue.set_timestamp(Clock::get()?.unix_timestamp);
+
+    let order_ids = if params.allow_partial {
+        order_book
+            .post_orders_batch_partial(
+                &params.orders,
+                &mut event_queue,
+                market_state.min_base_order_size,
+                market_state.max_orders_per_owner,
+                market_state.max_levels_per_side,
+                |order| check_order(market_state, order),
+            )?
+            .into_iter()
+            .flatten()
+            .collect()
+    } else {
+        let order_ids = order_book.post_orders_batch(
+            &params.orders,
+            &mut event_queue,
+            market_state.min_base_order_size,
+        )?;
+        // Each owner's orders are only counted once, however many orders of the batch they own
+        let mut owners = Vec::with_capacity(params.orders.len());
+        for order in params.orders.iter() {
+            let owner = order.callback_info.as_callback_id();
+            if !owners.contains(&owner) {
+                order_book.check_orders_per_owner(owner, market_state.max_orders_per_owner)?;
+                owners.push(owner);
+            }
+        }
+        for &side in &[Side::Bid, Side::Ask] {
+            order_book.check_new_levels(side, &order_ids, market_state.max_levels_per_side)?;
+        }
+        order_ids
+    };
+    msg!("Posted {} orders", order_ids.len());
+
+    Ok(order_ids)
+}
+
+fn check_order<C>(market_state: &MarketState, order: &BatchOrder<C>) -> Result<(), RejectReason> {
+    market_state
+        .check_side_not_paused(order.side)
+        .map_err(|_| RejectReason::SidePaused)?;
+    market_state
+        .check_lot_size(order.base_qty)
+        .map_err(|_| RejectReason::InvalidLotSize)?;
+    if order.limit_price % market_state.tick_size != 0 || order.limit_price < market_state.tick_size
+    {
+        return Err(RejectReason::InvalidLimitPrice);
+    }
+    market_state
+        .check_price_band(order.limit_price)
+        .map_err(|_| RejectReason::PriceOutOfBand)
+}
+
+fn check_accounts<'a, 'b: 'a>(
+    accounts: &Accounts<'a, AccountInfo<'b>>,
+    market_state: &MarketState,
+) -> ProgramResult {
+    check_account_key(
+        accounts.event_queue,
+        &market_state.event_queue,
+        AoError::WrongEventQueueAccount,
+    )?;
+    check_book_accounts(accounts.bids, accounts.asks, market_state)?;
+
+    Ok(())
+}
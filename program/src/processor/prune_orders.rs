@@ -8,6 +8,8 @@ use solana_program::{
     account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
     pubkey::Pubkey,
 };
+#[cfg(feature = "event-timestamps")]
+use solana_program::{clock::Clock, sysvar::Sysvar};
 use std::cmp;
 
 use crate::state::event_queue::EventQueue;
@@ -96,6 +98,8 @@ where
 
     let mut event_queue_guard = accounts.event_queue.data.borrow_mut();
     let mut event_queue = EventQueue::from_buffer(&mut event_queue_guard, AccountTag::EventQueue)?;
+    #[cfg(feature = "event-timestamps")]
+    event_queue.set_timestamp(Clock::get()?.unix_timestamp);
     let num_bids = u64::from(order_book.get_tree(Side::Bid).header.leaf_count);
     // Number of bids/asks to prune is bounded by: number of bids, param with max number of orders to prune
     let num_bids_to_prune = cmp::min(num_bids, params.num_orders_to_prune);
@@ -1,9 +1,12 @@
+use std::fmt;
+
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
-use bytemuck::{CheckedBitPattern, NoUninit};
+use bytemuck::{CheckedBitPattern, NoUninit, Pod, Zeroable};
 use num_derive::{FromPrimitive, ToPrimitive};
 
-pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
+use crate::state::orderbook::CallbackInfo;
+pub use crate::state::orderbook::{BookSnapshot, Level, OrderSummary, ORDER_SUMMARY_SIZE};
 #[cfg(feature = "no-entrypoint")]
 pub use crate::utils::get_spread;
 
@@ -68,9 +71,96 @@ pub enum SelfTradeBehavior {
     AbortTransaction,
 }
 
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Debug)]
+/// Describes how the callback infos of a taker and a maker are compared to detect self trading
+pub enum SelfTradeMatch {
+    /// The callback ids of the two orders are compared, see [`CallbackInfo::as_callback_id`]. This is the default.
+    CallbackId,
+    /// The `len` bytes starting at `offset` of the two callback infos are compared
+    Prefix {
+        #[allow(missing_docs)]
+        offset: u16,
+        #[allow(missing_docs)]
+        len: u16,
+    },
+    /// The two callback infos are compared in their entirety
+    Full,
+}
+
+impl SelfTradeMatch {
+    /// Whether a taker and a maker with the given callback infos belong to the same user
+    pub fn is_self_trade<C: CallbackInfo>(&self, taker: &C, maker: &C) -> bool
+    where
+        C::CallbackId: PartialEq,
+    {
+        match *self {
+            SelfTradeMatch::CallbackId => taker.as_callback_id() == maker.as_callback_id(),
+            SelfTradeMatch::Prefix { offset, len } => {
+                let range = offset as usize..(offset as usize + len as usize);
+                match (
+                    bytemuck::bytes_of(taker).get(range.clone()),
+                    bytemuck::bytes_of(maker).get(range),
+                ) {
+                    (Some(t), Some(m)) => t == m,
+                    _ => false,
+                }
+            }
+            SelfTradeMatch::Full => bytemuck::bytes_of(taker) == bytemuck::bytes_of(maker),
+        }
+    }
+
+    /// Checks that the compared bytes are within a callback info of type `C`, and that there is at least one of them
+    pub fn is_valid<C>(&self) -> bool {
+        match *self {
+            SelfTradeMatch::Prefix { offset, len } => {
+                len != 0 && offset as usize + len as usize <= std::mem::size_of::<C>()
+            }
+            _ => true,
+        }
+    }
+}
+
+impl BorshSize for SelfTradeMatch {
+    fn borsh_len(&self) -> usize {
+        match self {
+            SelfTradeMatch::Prefix { .. } => 5,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, BorshSize)]
+/// Describes whether an order is allowed to match against the orderbook
+pub enum PostOnly {
+    /// The order is matched against the orderbook before the remainder is posted.
+    Disabled,
+    /// The order is not matched against the orderbook. Nothing is posted if its limit price crosses the spread.
+    Enabled,
+    /// The order is not matched against the orderbook. If its limit price crosses the spread, the order is
+    /// posted one tick inside the best opposing price instead.
+    Slide,
+}
+
 /// This byte flag is set for order_ids with side Bid, and unset for side Ask
 pub const ORDER_ID_SIDE_FLAG: u128 = 1 << 63;
 
+/// The number of low bits of an order's sequence number which hold its rank within its second in the ids generated in
+/// [`OrderIdMode::Timestamped`][`crate::state::event_queue::OrderIdMode::Timestamped`], below the timestamp.
+pub const TIMESTAMPED_SEQ_BITS: u32 = 24;
+
+/// Embeds a coarse timestamp in an order's sequence number, for order ids generated in
+/// [`OrderIdMode::Timestamped`][`crate::state::event_queue::OrderIdMode::Timestamped`].
+///
+/// `seq` is the order's rank among the orders generated at the same timestamp, counted from 0. Its lower
+/// [`TIMESTAMPED_SEQ_BITS`] bits are kept, and the timestamp is truncated to the bits above them which stay below the
+/// [`ORDER_ID_SIDE_FLAG`]. Orders posted in different seconds are then ordered by timestamp, and orders posted within
+/// the same second by rank.
+pub fn timestamped_seq(timestamp: u64, seq: u64) -> u64 {
+    let timestamp_mask = (1 << (63 - TIMESTAMPED_SEQ_BITS)) - 1;
+    let seq_mask = (1 << TIMESTAMPED_SEQ_BITS) - 1;
+    ((timestamp & timestamp_mask) << TIMESTAMPED_SEQ_BITS) | (seq & seq_mask)
+}
+
 /// This helper function deduces an order's side from its order_id
 pub fn get_side_from_order_id(order_id: u128) -> Side {
     if ORDER_ID_SIDE_FLAG & order_id != 0 {
@@ -79,3 +169,190 @@ pub fn get_side_from_order_id(order_id: u128) -> Side {
         Side::Ask
     }
 }
+
+/// Encodes an order's sequence number into the lower 64 bits of its order id.
+///
+/// Bid sequence numbers are bitwise inverted: bids are matched from the highest order id down, so the inversion
+/// makes older bids (with lower sequence numbers) sort higher than newer bids at the same price. Asks are matched
+/// from the lowest order id up and are stored as is.
+pub fn encode_seq(side: Side, seq: u64) -> u64 {
+    match side {
+        Side::Bid => !seq,
+        Side::Ask => seq,
+    }
+}
+
+/// Recovers an order's sequence number from the lower 64 bits of its order id, see [`encode_seq`]
+pub fn decode_seq(side: Side, encoded: u64) -> u64 {
+    // Bitwise inversion is its own inverse
+    encode_seq(side, encoded)
+}
+
+#[derive(
+    BorshDeserialize,
+    BorshSerialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+    Default,
+    Pod,
+    Zeroable,
+)]
+#[repr(transparent)]
+/// A typed wrapper around an order's `u128` identifier.
+///
+/// The upper 64 bits of an order id hold the order's limit price, and the lower 64 bits hold its sequence number.
+/// For bids, the sequence number is bitwise inverted so that older orders at the same price are matched first.
+///
+/// Order ids are unique across both sides of the book, even at the same price: every order consumes its own
+/// sequence number, and as long as sequence numbers stay below `2^63` the [`ORDER_ID_SIDE_FLAG`] bit is set for
+/// every bid and clear for every ask, so a bid's encoded sequence number can never equal an ask's.
+///
+/// In [`OrderIdMode::Timestamped`][`crate::state::event_queue::OrderIdMode::Timestamped`], the sequence number also
+/// embeds a coarse timestamp, see [`timestamped_seq`]. Order ids then stay unique as long as fewer than
+/// `2^TIMESTAMPED_SEQ_BITS` orders are posted within a second.
+pub struct OrderId(pub u128);
+
+impl OrderId {
+    /// Builds an order id from its limit price, sequence number and side.
+    pub fn from_parts(price: u64, seq: u64, side: Side) -> Self {
+        Self(((price as u128) << 64) | (encode_seq(side, seq) as u128))
+    }
+
+    /// The limit price of the order (FP32)
+    pub fn price(&self) -> u64 {
+        (self.0 >> 64) as u64
+    }
+
+    /// The sequence number of the order.
+    ///
+    /// The side is deduced from the [`ORDER_ID_SIDE_FLAG`], which means that sequence numbers are only recovered
+    /// faithfully when they are smaller than `2^63`.
+    pub fn seq(&self) -> u64 {
+        decode_seq(self.side(), self.0 as u64)
+    }
+
+    /// The side of the order
+    pub fn side(&self) -> Side {
+        get_side_from_order_id(self.0)
+    }
+}
+
+impl BorshSize for OrderId {
+    fn borsh_len(&self) -> usize {
+        16
+    }
+}
+
+impl From<u128> for OrderId {
+    fn from(order_id: u128) -> Self {
+        Self(order_id)
+    }
+}
+
+impl From<OrderId> for u128 {
+    fn from(order_id: OrderId) -> Self {
+        order_id.0
+    }
+}
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_id_parts() {
+        for side in [Side::Bid, Side::Ask] {
+            for &price in &[1, 10 << 32, u64::MAX] {
+                for &seq in &[0, 1, 42, (1 << 63) - 1] {
+                    let order_id = OrderId::from_parts(price, seq, side);
+                    assert_eq!(order_id.price(), price);
+                    assert_eq!(order_id.seq(), seq);
+                    assert_eq!(order_id.side(), side);
+                    assert_eq!(OrderId::from(u128::from(order_id)), order_id);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_seq_encoding() {
+        for side in [Side::Bid, Side::Ask] {
+            for &seq in &[0, 1, 42, 1 << 63, u64::MAX - 1, u64::MAX] {
+                assert_eq!(decode_seq(side, encode_seq(side, seq)), seq);
+                assert_eq!(encode_seq(side, decode_seq(side, seq)), seq);
+            }
+        }
+        assert_eq!(encode_seq(Side::Bid, 0), u64::MAX);
+        assert_eq!(encode_seq(Side::Ask, 0), 0);
+
+        // Older bids sort higher, older asks sort lower
+        assert!(encode_seq(Side::Bid, 1) > encode_seq(Side::Bid, 2));
+        assert!(encode_seq(Side::Ask, 1) < encode_seq(Side::Ask, 2));
+    }
+
+    #[test]
+    fn test_order_id_unique_across_sides() {
+        let price = 10 << 32;
+        let mut order_ids = std::collections::HashSet::new();
+        for seq in (0..1_000).chain((1 << 63) - 1_000..1 << 63) {
+            for side in [Side::Bid, Side::Ask] {
+                let order_id = OrderId::from_parts(price, seq, side);
+                assert_eq!(order_id.side(), side);
+                assert!(order_ids.insert(order_id));
+            }
+        }
+
+        // Beyond 2^63, a bid's inverted sequence number can alias an ask's
+        assert_eq!(
+            OrderId::from_parts(price, 0, Side::Bid),
+            OrderId::from_parts(price, u64::MAX, Side::Ask)
+        );
+    }
+
+    #[test]
+    fn test_order_id_display() {
+        let order_id = OrderId::from_parts(10 << 32, 5, Side::Ask);
+        assert_eq!(order_id.to_string(), "0000000a000000000000000000000005");
+    }
+
+    #[test]
+    fn test_self_trade_match() {
+        let mut taker = [1; 32];
+        let mut maker = [1; 32];
+        maker[31] = 2;
+        let prefix = SelfTradeMatch::Prefix { offset: 0, len: 31 };
+
+        // A shared owner prefix is only a self trade when comparing prefixes
+        assert!(prefix.is_self_trade(&taker, &maker));
+        assert!(!SelfTradeMatch::Full.is_self_trade(&taker, &maker));
+        assert!(!SelfTradeMatch::CallbackId.is_self_trade(&taker, &maker));
+
+        maker[31] = 1;
+        for mode in [prefix, SelfTradeMatch::Full, SelfTradeMatch::CallbackId] {
+            assert!(mode.is_self_trade(&taker, &maker));
+        }
+
+        taker[4] = 0;
+        let suffix = SelfTradeMatch::Prefix { offset: 8, len: 24 };
+        assert!(suffix.is_self_trade(&taker, &maker));
+        assert!(!prefix.is_self_trade(&taker, &maker));
+        assert!(!SelfTradeMatch::Full.is_self_trade(&taker, &maker));
+
+        assert!(suffix.is_valid::<[u8; 32]>());
+        assert!(!SelfTradeMatch::Prefix { offset: 8, len: 25 }.is_valid::<[u8; 32]>());
+        assert!(!SelfTradeMatch::Prefix { offset: 0, len: 0 }.is_valid::<[u8; 32]>());
+        assert!(SelfTradeMatch::Full.is_valid::<[u8; 32]>());
+    }
+}
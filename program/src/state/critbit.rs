@@ -1,6 +1,7 @@
 #![allow(missing_docs)]
 use crate::error::AoError;
-use crate::state::AccountTag;
+use crate::state::{AccountTag, OrderId};
+use crate::utils::cast_callback_infos_mut;
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::{Pod, Zeroable};
 use solana_program::program_error::ProgramError;
@@ -55,8 +56,8 @@ impl LeafNode {
     }
 
     /// Get the associated order id
-    pub fn order_id(&self) -> u128 {
-        self.key
+    pub fn order_id(&self) -> OrderId {
+        OrderId(self.key)
     }
 
     /// Deduce an associated price from an order_id
@@ -74,6 +75,9 @@ pub struct InnerNode {
     key: u128,
     prefix_len: u64,
     pub children: [u32; 2],
+    /// The total base quantity of the orders in the node's subtree, kept up to date as orders are inserted,
+    /// removed and filled so that the size of a price level or of a price range is found without visiting its leaves
+    pub base_quantity: u128,
 }
 
 impl InnerNode {
@@ -141,7 +145,7 @@ impl<'a, C: Pod> Slab<'a, C> {
             header,
             leaf_nodes: bytemuck::cast_slice_mut::<_, LeafNode>(leaves),
             inner_nodes: bytemuck::cast_slice_mut::<_, InnerNode>(inner_nodes),
-            callback_infos: bytemuck::cast_slice_mut::<_, C>(callback_infos),
+            callback_infos: cast_callback_infos_mut(callback_infos, capacity + 1),
         })
     }
 }
@@ -154,6 +158,41 @@ impl<'a, C> Slab<'a, C> {
             Some(self.header.root_node)
         }
     }
+
+    /// The number of nodes currently in the tree, leaves and inner nodes included
+    pub fn node_count(&self) -> u32 {
+        let leaf_count = self.header.leaf_count;
+        leaf_count + leaf_count.saturating_sub(1)
+    }
+
+    /// The number of orders which can still be inserted into the slab
+    pub fn free_capacity(&self) -> usize {
+        self.leaf_nodes.len() - self.header.leaf_count as usize
+    }
+
+    /// Checks that the leaf and inner node free lists only go through allocated nodes, without cycles, and that
+    /// the number of allocated nodes which aren't in the free lists matches the number of nodes in the tree.
+    ///
+    /// This walks both free lists, and is meant to be used off-chain to monitor a slab.
+    pub fn check_freelist_integrity(&self) -> Result<(), AoError> {
+        let leaf_count = self.header.leaf_count;
+        check_free_list(
+            self.header.leaf_free_list_head,
+            self.header.leaf_free_list_len,
+            self.header.leaf_bump_index,
+            leaf_count,
+            |h| self.leaf_nodes[h as usize].base_quantity as u32,
+        )?;
+        check_free_list(
+            self.header.inner_node_free_list_head,
+            self.header.inner_node_free_list_len,
+            self.header.inner_node_bump_index,
+            leaf_count.saturating_sub(1),
+            |h| self.inner_nodes[h as usize].prefix_len as u32,
+        )?;
+        Ok(())
+    }
+
     pub(crate) fn allocate_leaf(&mut self) -> Result<NodeHandle, IoError> {
         if self.header.leaf_free_list_len == 0 {
             if self.header.leaf_bump_index as usize >= self.leaf_nodes.len() {
@@ -212,17 +251,18 @@ impl<'a, C> Slab<'a, C> {
         self.header.inner_node_free_list_head = !handle;
     }
 
-    pub(crate) fn insert_leaf(
-        &mut self,
-        new_leaf: &LeafNode,
-    ) -> Result<(NodeHandle, Option<LeafNode>), AoError> {
+    /// Inserts a leaf into the tree, returning its handle.
+    ///
+    /// A leaf whose key is already in the tree is rejected with [`AoError::DuplicateOrderId`] rather than overwriting
+    /// the existing order.
+    pub(crate) fn insert_leaf(&mut self, new_leaf: &LeafNode) -> Result<NodeHandle, AoError> {
         let mut root: NodeHandle = if self.header.leaf_count == 0 {
             // create a new root if none exists
             let new_leaf_handle = self.allocate_leaf().map_err(|_| AoError::SlabOutOfSpace)?;
             self.leaf_nodes[new_leaf_handle as usize] = *new_leaf;
             self.header.root_node = new_leaf_handle;
             self.header.leaf_count += 1;
-            return Ok((new_leaf_handle, None));
+            return Ok(new_leaf_handle);
         } else {
             self.header.root_node
         };
@@ -245,12 +285,9 @@ impl<'a, C> Slab<'a, C> {
                     shared_prefix_len
                 }
                 Node::Leaf => {
-                    let root_node = &mut self.leaf_nodes[root as usize];
+                    let root_node = &self.leaf_nodes[root as usize];
                     if root_node.key == new_leaf.key {
-                        // clobber the existing leaf
-                        let leaf_copy = *root_node;
-                        *root_node = *new_leaf;
-                        return Ok((root, Some(leaf_copy)));
+                        return Err(AoError::DuplicateOrderId);
                     }
                     let shared_prefix_len: u32 = (root_node.key ^ new_leaf.key).leading_zeros();
 
@@ -267,11 +304,14 @@ impl<'a, C> Slab<'a, C> {
             self.leaf_nodes[new_leaf_handle as usize] = *new_leaf;
 
             let new_root_node_handle = self.allocate_inner_node().unwrap();
+            // The new leaf's quantity is added along with its other ancestors' below
+            let old_root_base_quantity = self.subtree_base_quantity(root);
             let new_root_node = &mut self.inner_nodes[(!new_root_node_handle) as usize];
             new_root_node.prefix_len = shared_prefix_len as u64;
             new_root_node.key = new_leaf.key;
             new_root_node.children[new_leaf_crit_bit as usize] = new_leaf_handle;
             new_root_node.children[old_root_crit_bit as usize] = root;
+            new_root_node.base_quantity = old_root_base_quantity;
 
             if let Some(n) = parent_node {
                 let node = &mut self.inner_nodes[(!n) as usize];
@@ -280,7 +320,147 @@ impl<'a, C> Slab<'a, C> {
                 self.header.root_node = new_root_node_handle;
             }
             self.header.leaf_count += 1;
-            return Ok((new_leaf_handle, None));
+            let base_quantity = new_leaf.base_quantity as u128;
+            self.update_ancestors(new_leaf.key, |q| q + base_quantity);
+            return Ok(new_leaf_handle);
+        }
+    }
+
+    /// The total base quantity of the orders in the subtree rooted at `handle`
+    fn subtree_base_quantity(&self, handle: NodeHandle) -> u128 {
+        match Node::from_handle(handle) {
+            Node::Leaf => self.leaf_nodes[handle as usize].base_quantity as u128,
+            Node::Inner => self.inner_nodes[(!handle) as usize].base_quantity,
+        }
+    }
+
+    /// Applies `f` to the cached base quantity of every inner node on the path from the root to `key`
+    fn update_ancestors<F: Fn(u128) -> u128>(&mut self, key: u128, f: F) {
+        let mut handle = match self.root() {
+            Some(h) => h,
+            None => return,
+        };
+        while let Node::Inner = Node::from_handle(handle) {
+            let node = &mut self.inner_nodes[(!handle) as usize];
+            node.base_quantity = f(node.base_quantity);
+            handle = node.walk_down(key).0;
+        }
+    }
+
+    /// Decreases the base quantity of the order at `leaf_handle` by `amount`, such as when it is partially filled.
+    ///
+    /// The base quantity of a leaf must only be changed through this method, so that the quantities cached in the
+    /// inner nodes stay up to date.
+    pub(crate) fn decrease_base_quantity(&mut self, leaf_handle: NodeHandle, amount: u64) {
+        let leaf = &mut self.leaf_nodes[leaf_handle as usize];
+        leaf.base_quantity -= amount;
+        let key = leaf.key;
+        self.update_ancestors(key, |q| q - amount as u128);
+    }
+
+    /// The total base quantity of the orders in the slab
+    pub fn total_base_quantity(&self) -> u128 {
+        self.root()
+            .map_or(0, |root| self.subtree_base_quantity(root))
+    }
+
+    /// Descends to the subtree which holds the orders resting at `price`, returning its root, or `None` if no order
+    /// rests at `price`.
+    ///
+    /// The keys of a price level share their upper 64 bits, so the level's subtree is the first node on the path to
+    /// the price whose prefix covers them.
+    pub fn find_level(&self, price: u64) -> Option<NodeHandle> {
+        let price_key = (price as u128) << 64;
+        let mut handle = self.root()?;
+        loop {
+            match Node::from_handle(handle) {
+                Node::Leaf => {
+                    if self.leaf_nodes[handle as usize].price() == price {
+                        return Some(handle);
+                    }
+                    return None;
+                }
+                Node::Inner => {
+                    let node = &self.inner_nodes[(!handle) as usize];
+                    let prefix_mask = !(u128::MAX >> std::cmp::min(node.prefix_len, 64));
+                    if node.key & prefix_mask != price_key & prefix_mask {
+                        return None;
+                    }
+                    if node.prefix_len >= 64 {
+                        return Some(handle);
+                    }
+                    handle = node.walk_down(price_key).0;
+                }
+            }
+        }
+    }
+
+    /// The total base quantity of the orders resting at `price`, found without visiting the orders themselves.
+    pub fn level_base_quantity(&self, price: u64) -> u128 {
+        self.find_level(price)
+            .map_or(0, |handle| self.subtree_base_quantity(handle))
+    }
+
+    /// The number of orders resting at `price`, which only visits the orders of that level.
+    pub fn level_order_count(&self, price: u64) -> u64 {
+        let mut stack = match self.find_level(price) {
+            Some(handle) => vec![handle],
+            None => return 0,
+        };
+        let mut order_count = 0;
+        while let Some(handle) = stack.pop() {
+            match Node::from_handle(handle) {
+                Node::Leaf => order_count += 1,
+                Node::Inner => {
+                    stack.extend_from_slice(&self.inner_nodes[(!handle) as usize].children)
+                }
+            }
+        }
+        order_count
+    }
+
+    /// The total base quantity of the orders whose key is lower than `bound`, or of all orders if `bound` is
+    /// `None`.
+    ///
+    /// This walks a single path of the tree, adding up the cached quantities of the subtrees on its lower side.
+    pub fn base_quantity_below(&self, bound: Option<u128>) -> u128 {
+        let bound = match bound {
+            Some(b) => b,
+            None => return self.total_base_quantity(),
+        };
+        let mut handle = match self.root() {
+            Some(h) => h,
+            None => return 0,
+        };
+        let mut base_quantity = 0;
+        loop {
+            match Node::from_handle(handle) {
+                Node::Leaf => {
+                    let leaf = &self.leaf_nodes[handle as usize];
+                    if leaf.key < bound {
+                        base_quantity += leaf.base_quantity as u128;
+                    }
+                    return base_quantity;
+                }
+                Node::Inner => {
+                    let node = &self.inner_nodes[(!handle) as usize];
+                    // The keys of the subtree share the node's prefix, so they are all on the same side of a bound
+                    // with another prefix
+                    let prefix_mask = !(u128::MAX >> node.prefix_len);
+                    let (node_prefix, bound_prefix) = (node.key & prefix_mask, bound & prefix_mask);
+                    if node_prefix < bound_prefix {
+                        return base_quantity + node.base_quantity;
+                    }
+                    if node_prefix > bound_prefix {
+                        return base_quantity;
+                    }
+                    let (child, crit_bit) = node.walk_down(bound);
+                    if crit_bit {
+                        base_quantity += self.subtree_base_quantity(node.children[0]);
+                    }
+                    handle = child;
+                }
+            }
         }
     }
 
@@ -294,6 +474,20 @@ impl<'a, C> Slab<'a, C> {
         &mut self.callback_infos[leaf_handle as usize]
     }
 
+    /// Removes the order with the given id, returning its data
+    pub fn remove(&mut self, order_id: OrderId) -> Option<RemovedOrder<C>>
+    where
+        C: Copy,
+    {
+        let (leaf, callback_info) = self.remove_by_key(order_id.into())?;
+        Some(RemovedOrder {
+            order_id,
+            price: leaf.price(),
+            base_quantity: leaf.base_quantity,
+            callback_info: *callback_info,
+        })
+    }
+
     pub fn remove_by_key(&mut self, search_key: u128) -> Option<(LeafNode, &C)> {
         let mut grandparent_h: Option<NodeHandle> = None;
         if self.header.leaf_count == 0 {
@@ -353,6 +547,10 @@ impl<'a, C> Slab<'a, C> {
             }
         }
 
+        // The removed leaf's quantity is taken out of its ancestors before they are unlinked
+        let base_quantity = self.leaf_nodes[child_h as usize].base_quantity as u128;
+        self.update_ancestors(search_key, |q| q - base_quantity);
+
         // replace parent with its remaining child node
         // free child_h, replace *parent_h with *other_child_h, free other_child_h
         let other_child_h = self.inner_nodes[(!parent_h) as usize].children[!crit_bit as usize];
@@ -410,6 +608,33 @@ impl<'a, C> Slab<'a, C> {
         }
     }
 
+    /// Get a price ascending or price descending iterator over references to the Slab's orders and their callback information
+    pub fn iter(&self, price_ascending: bool) -> SlabRefIterator<'_, 'a, C> {
+        SlabRefIterator {
+            search_stack: if self.header.leaf_count == 0 {
+                vec![]
+            } else {
+                vec![self.header.root_node]
+            },
+            slab: self,
+            ascending: price_ascending,
+        }
+    }
+
+    fn next_leaf(&self, search_stack: &mut Vec<u32>, ascending: bool) -> Option<NodeHandle> {
+        while let Some(current) = search_stack.pop() {
+            match Node::from_handle(current) {
+                Node::Inner => {
+                    let n = &self.inner_nodes[(!current) as usize];
+                    search_stack.push(n.children[ascending as usize]);
+                    search_stack.push(n.children[!ascending as usize]);
+                }
+                Node::Leaf => return Some(current),
+            }
+        }
+        None
+    }
+
     #[cfg(feature = "utils")]
     /// Get the current critbit's depth. Walks though the entire tree.
     pub fn get_depth(&self) -> usize {
@@ -543,12 +768,28 @@ impl<'a, C> Slab<'a, C> {
             self.header.leaf_bump_index as u64
         );
         assert_eq!(leaf_count, self.header.leaf_count as u64);
+
+        // then check the base quantities cached in the inner nodes
+        fn check_base_quantity<C>(slab: &Slab<'_, C>, h: NodeHandle) -> u128 {
+            match Node::from_handle(h) {
+                Node::Leaf => slab.leaf_nodes[h as usize].base_quantity as u128,
+                Node::Inner => {
+                    let node = &slab.inner_nodes[(!h) as usize];
+                    let base_quantity = check_base_quantity(slab, node.children[0])
+                        + check_base_quantity(slab, node.children[1]);
+                    assert_eq!(node.base_quantity, base_quantity);
+                    base_quantity
+                }
+            }
+        }
+        if let Some(root) = self.root() {
+            check_base_quantity(self, root);
+        }
     }
 
     /////////////////////////////////////////
     // Misc
 
-    #[cfg(any(test, feature = "utils"))]
     pub fn find_by_key(&self, search_key: u128) -> Option<NodeHandle> {
         let mut node_handle: NodeHandle = self.root()?;
         loop {
@@ -562,7 +803,7 @@ impl<'a, C> Slab<'a, C> {
                     }
                 }
                 Node::Inner => {
-                    let n = self.inner_nodes[(!node_handle as usize)];
+                    let n = self.inner_nodes[!node_handle as usize];
                     let common_prefix_len = (search_key ^ n.key).leading_zeros();
                     if common_prefix_len < n.prefix_len as u32 {
                         return None;
@@ -575,6 +816,48 @@ impl<'a, C> Slab<'a, C> {
 }
 
 impl<'queue, C: Clone> Slab<'queue, C> {
+    /// Rebuilds the tree from its live leaves, restoring the inner nodes and the header's free lists and counters.
+    /// Returns the number of orders in the rebuilt tree.
+    ///
+    /// This is an administrative escape hatch for a slab whose inner nodes have been corrupted. The live leaves
+    /// are the allocated leaves which aren't in the leaf free list, so that list must be intact: otherwise, or if
+    /// two live leaves share the same key, this fails with [`AoError::CorruptSlab`] and leaves the slab untouched.
+    /// The orders keep their data and callback information, but not their leaf handles.
+    pub fn rebuild(&mut self) -> Result<u32, AoError> {
+        let bump_index = self.header.leaf_bump_index;
+        let free_list_len = self.header.leaf_free_list_len;
+        if bump_index as usize > self.leaf_nodes.len() {
+            return Err(AoError::CorruptSlab);
+        }
+        let used_count = bump_index
+            .checked_sub(free_list_len)
+            .ok_or(AoError::CorruptSlab)?;
+        let free = check_free_list(
+            self.header.leaf_free_list_head,
+            free_list_len,
+            bump_index,
+            used_count,
+            |h| self.leaf_nodes[h as usize].base_quantity as u32,
+        )?;
+
+        let mut orders = (0..bump_index as usize)
+            .filter(|&h| !free[h])
+            .map(|h| (self.leaf_nodes[h], self.callback_infos[h].clone()))
+            .collect::<Vec<_>>();
+        orders.sort_unstable_by_key(|(leaf, _)| leaf.key);
+        if orders.windows(2).any(|w| w[0].0.key == w[1].0.key) {
+            return Err(AoError::CorruptSlab);
+        }
+
+        *self.header = SlabHeader::zeroed();
+        for (leaf, callback_info) in orders {
+            // The keys are distinct and there are at most as many of them as leaves, so the insertion can't fail
+            let h = self.insert_leaf(&leaf)?;
+            self.callback_infos[h as usize] = callback_info;
+        }
+        Ok(self.header.leaf_count)
+    }
+
     #[cfg(test)]
     fn traverse(&self) -> Vec<(LeafNode, C)> {
         fn walk_rec<'a, C: Clone>(
@@ -607,6 +890,48 @@ impl<'queue, C: Clone> Slab<'queue, C> {
     }
 }
 
+/// Walks a free list of `len` nodes starting at `head`, where `next` returns the node which follows a node in the
+/// list, see [`Slab::check_freelist_integrity`].
+///
+/// Returns whether each of the `bump_index` allocated nodes is in the free list.
+fn check_free_list<F: Fn(u32) -> u32>(
+    head: u32,
+    len: u32,
+    bump_index: u32,
+    used_count: u32,
+    next: F,
+) -> Result<Vec<bool>, AoError> {
+    if len.checked_add(used_count) != Some(bump_index) {
+        return Err(AoError::CorruptSlab);
+    }
+    let mut visited = vec![false; bump_index as usize];
+    let mut h = head;
+    for index in 0..len {
+        match visited.get_mut(h as usize) {
+            Some(v) if !*v => *v = true,
+            _ => return Err(AoError::CorruptSlab),
+        }
+        // The last node of the list doesn't hold a meaningful next index
+        if index + 1 < len {
+            h = next(h);
+        }
+    }
+    Ok(visited)
+}
+
+/// An order which has been removed from a [`Slab`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemovedOrder<C> {
+    /// The id of the removed order
+    pub order_id: OrderId,
+    /// The limit price of the removed order (FP32)
+    pub price: u64,
+    /// The base quantity which was left in the removed order
+    pub base_quantity: u64,
+    /// The callback information of the removed order
+    pub callback_info: C,
+}
+
 pub struct SlabIterator<'a, C> {
     slab: Slab<'a, C>,
     search_stack: Vec<u32>,
@@ -617,17 +942,25 @@ impl<'a, C: Pod> Iterator for SlabIterator<'a, C> {
     type Item = LeafNode;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(current) = self.search_stack.pop() {
-            match Node::from_handle(current) {
-                Node::Inner => {
-                    let n = &self.slab.inner_nodes[(!current) as usize];
-                    self.search_stack.push(n.children[self.ascending as usize]);
-                    self.search_stack.push(n.children[!self.ascending as usize]);
-                }
-                Node::Leaf => return Some(self.slab.leaf_nodes[current as usize]),
-            }
-        }
-        None
+        self.slab
+            .next_leaf(&mut self.search_stack, self.ascending)
+            .map(|h| self.slab.leaf_nodes[h as usize])
+    }
+}
+
+pub struct SlabRefIterator<'s, 'a, C> {
+    slab: &'s Slab<'a, C>,
+    search_stack: Vec<u32>,
+    ascending: bool,
+}
+
+impl<'s, 'a, C> Iterator for SlabRefIterator<'s, 'a, C> {
+    type Item = (&'s LeafNode, &'s C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slab = self.slab;
+        let h = slab.next_leaf(&mut self.search_stack, self.ascending)? as usize;
+        Some((&slab.leaf_nodes[h], &slab.callback_infos[h]))
     }
 }
 
@@ -704,7 +1037,7 @@ mod tests {
                 println!("key : {:x}", key);
                 println!("owner : {:?}", &owner.to_bytes());
                 println!("{}", i);
-                let h = slab.insert_leaf(&leaf).unwrap().0;
+                let h = slab.insert_leaf(&leaf).unwrap();
                 let callback_info = TestCallbackInfo {
                     key: owner.to_bytes(),
                 };
@@ -745,6 +1078,313 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_remove() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(100)];
+        bytes[0] = AccountTag::Asks as u8;
+        let mut slab = Slab::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+
+        let insert = |slab: &mut Slab<[u8; 32]>, price: u64, seq: u64| {
+            let order_id = OrderId::from_parts(price, seq, crate::state::Side::Ask);
+            let leaf = LeafNode {
+                key: order_id.into(),
+                base_quantity: price * 10 + seq,
+                max_ts: u64::MAX,
+            };
+            let h = slab.insert_leaf(&leaf).unwrap();
+            *slab.get_callback_info_mut(h) = [seq as u8; 32];
+            order_id
+        };
+
+        // Removing the root when it is the only leaf
+        let root_id = insert(&mut slab, 5, 0);
+        assert_eq!(
+            slab.remove(root_id),
+            Some(RemovedOrder {
+                order_id: root_id,
+                price: 5,
+                base_quantity: 50,
+                callback_info: [0; 32],
+            })
+        );
+        assert_eq!(slab.header.leaf_count, 0);
+        assert_eq!(slab.remove(root_id), None);
+        slab.check_invariants();
+
+        let ids = [(8, 1), (4, 2), (12, 3), (4, 4), (2, 5), (13, 6)]
+            .iter()
+            .map(|&(price, seq)| insert(&mut slab, price, seq))
+            .collect::<Vec<_>>();
+        slab.check_invariants();
+
+        // Removing a leaf whose sibling is an inner node, which collapses its parent
+        let removed = slab.remove(ids[0]).unwrap();
+        assert_eq!((removed.price, removed.base_quantity), (8, 81));
+        assert_eq!(removed.callback_info, [1; 32]);
+        slab.check_invariants();
+
+        // Removing leaves which are siblings of each other
+        let removed = slab.remove(ids[3]).unwrap();
+        assert_eq!((removed.price, removed.base_quantity), (4, 44));
+        slab.check_invariants();
+        let removed = slab.remove(ids[5]).unwrap();
+        assert_eq!((removed.price, removed.base_quantity), (13, 136));
+        slab.check_invariants();
+
+        // Orders which aren't in the slab aren't removed
+        assert_eq!(slab.remove(ids[0]), None);
+        assert_eq!(slab.header.leaf_count, 3);
+
+        let remaining = slab
+            .iter(true)
+            .map(|(leaf, callback_info)| (leaf.order_id(), callback_info[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![(ids[4], 5), (ids[1], 2), (ids[2], 3)]);
+
+        for id in [ids[1], ids[4], ids[2]].iter() {
+            assert!(slab.remove(*id).is_some());
+            slab.check_invariants();
+        }
+        assert_eq!(slab.header.leaf_count, 0);
+    }
+
+    #[test]
+    fn test_insert_duplicate_order_id() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        bytes[0] = AccountTag::Bids as u8;
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Bids).unwrap();
+        let leaf = |price, seq, base_quantity| LeafNode {
+            key: OrderId::from_parts(price, seq, crate::state::Side::Bid).into(),
+            base_quantity,
+            max_ts: u64::MAX,
+        };
+
+        // A duplicate of the root leaf, then of a leaf deeper in the tree
+        slab.insert_leaf(&leaf(10, 0, 1)).unwrap();
+        assert!(matches!(
+            slab.insert_leaf(&leaf(10, 0, 2)),
+            Err(AoError::DuplicateOrderId)
+        ));
+        for seq in 1..4 {
+            slab.insert_leaf(&leaf(10 + seq, seq, 1)).unwrap();
+        }
+        assert!(matches!(
+            slab.insert_leaf(&leaf(12, 2, 5)),
+            Err(AoError::DuplicateOrderId)
+        ));
+
+        // The existing orders are left untouched and no node was allocated for the rejected ones
+        assert_eq!(slab.header.leaf_count, 4);
+        assert!(slab.iter(true).all(|(leaf, _)| leaf.base_quantity == 1));
+        assert_eq!(slab.node_count(), 7);
+        assert_eq!(slab.header.leaf_bump_index, 4);
+        slab.check_invariants();
+    }
+
+    #[test]
+    fn test_zero_sized_callback_info() {
+        let mut bytes = vec![0u8; Slab::<[u8; 0]>::compute_allocation_size(10)];
+        bytes[0] = AccountTag::Bids as u8;
+        let mut slab = Slab::<[u8; 0]>::from_buffer(&mut bytes, AccountTag::Bids).unwrap();
+        let mut ids = vec![];
+        for (seq, &price) in [5, 3, 8, 3].iter().enumerate() {
+            let order_id = OrderId::from_parts(price, seq as u64, crate::state::Side::Bid);
+            let leaf = LeafNode {
+                key: order_id.into(),
+                base_quantity: 10,
+                max_ts: u64::MAX,
+            };
+            let h = slab.insert_leaf(&leaf).unwrap();
+            assert_eq!(slab.get_callback_info(h), &[0u8; 0]);
+            ids.push(order_id);
+        }
+        slab.check_invariants();
+        let removed = slab.remove(ids[1]).unwrap();
+        assert_eq!(removed.callback_info, [0u8; 0]);
+        assert_eq!(slab.rebuild().unwrap(), 3);
+        slab.check_invariants();
+        assert_eq!(slab.total_base_quantity(), 30);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(100)];
+        bytes[0] = AccountTag::Asks as u8;
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        let mut ids = vec![];
+        for (seq, &price) in [8, 4, 12, 4, 2, 13, 7, 12, 9, 1].iter().enumerate() {
+            let order_id = OrderId::from_parts(price, seq as u64, crate::state::Side::Ask);
+            let leaf = LeafNode {
+                key: order_id.into(),
+                base_quantity: price * 10 + seq as u64,
+                max_ts: u64::MAX,
+            };
+            let h = slab.insert_leaf(&leaf).unwrap();
+            *slab.get_callback_info_mut(h) = [seq as u8; 32];
+            ids.push(order_id);
+        }
+        // The removed orders leave their leaves in the free list
+        for &i in &[0, 3, 5] {
+            slab.remove(ids[i]).unwrap();
+        }
+        let expected = slab.traverse();
+        assert_eq!(expected.len(), 7);
+
+        // A corrupted inner node makes half of its subtree unreachable
+        let root = !slab.root().unwrap() as usize;
+        slab.inner_nodes[root].children[0] = slab.inner_nodes[root].children[1];
+        assert_ne!(slab.iter(true).count(), 7);
+        assert_eq!(slab.find_by_key(expected[0].0.key), None);
+
+        assert_eq!(slab.rebuild().unwrap(), 7);
+        slab.check_invariants();
+        assert!(slab.check_freelist_integrity().is_ok());
+        assert_eq!(slab.traverse(), expected);
+        for (leaf, callback_info) in &expected {
+            let h = slab.find_by_key(leaf.key).unwrap();
+            assert_eq!(slab.get_callback_info(h), callback_info);
+        }
+
+        // The rebuilt slab can be used as usual
+        slab.remove(expected[0].0.order_id()).unwrap();
+        assert_eq!(slab.iter(true).count(), 6);
+        slab.check_invariants();
+
+        // A corrupted leaf free list can't be used to tell the live leaves apart
+        slab.header.leaf_free_list_len = 50;
+        assert!(matches!(slab.rebuild(), Err(AoError::CorruptSlab)));
+    }
+
+    #[test]
+    fn test_slab_utilization() {
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(10)];
+        bytes[0] = AccountTag::Asks as u8;
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        assert_eq!(slab.node_count(), 0);
+        assert_eq!(slab.free_capacity(), 10);
+        assert!(slab.check_freelist_integrity().is_ok());
+
+        let ids = (0..6)
+            .map(|seq| {
+                let order_id = OrderId::from_parts(seq % 3 + 1, seq, crate::state::Side::Ask);
+                let leaf = LeafNode {
+                    key: order_id.into(),
+                    base_quantity: 1,
+                    max_ts: u64::MAX,
+                };
+                slab.insert_leaf(&leaf).unwrap();
+                order_id
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(slab.node_count(), 11);
+        assert_eq!(slab.free_capacity(), 4);
+        assert!(slab.check_freelist_integrity().is_ok());
+
+        for id in ids[1..4].iter() {
+            slab.remove(*id).unwrap();
+            assert!(slab.check_freelist_integrity().is_ok());
+        }
+        assert_eq!(slab.node_count(), 5);
+        assert_eq!(slab.free_capacity(), 7);
+
+        // Freed nodes are reused before the bump index moves
+        let leaf = LeafNode {
+            key: OrderId::from_parts(4, 6, crate::state::Side::Ask).into(),
+            base_quantity: 1,
+            max_ts: u64::MAX,
+        };
+        slab.insert_leaf(&leaf).unwrap();
+        assert_eq!(slab.node_count(), 7);
+        assert_eq!(slab.header.leaf_bump_index, 6);
+        assert!(slab.check_freelist_integrity().is_ok());
+
+        // A free list going through the same node twice is a cycle
+        let head = slab.header.leaf_free_list_head;
+        let next = slab.leaf_nodes[head as usize].base_quantity;
+        slab.leaf_nodes[head as usize].base_quantity = head as u64;
+        assert!(matches!(
+            slab.check_freelist_integrity(),
+            Err(AoError::CorruptSlab)
+        ));
+        slab.leaf_nodes[head as usize].base_quantity = next;
+        assert!(slab.check_freelist_integrity().is_ok());
+
+        // As is a free list whose length doesn't match the number of nodes in the tree
+        slab.header.leaf_count += 1;
+        assert!(matches!(
+            slab.check_freelist_integrity(),
+            Err(AoError::CorruptSlab)
+        ));
+    }
+
+    #[test]
+    fn simulate_base_quantity_aggregates() {
+        use std::collections::BTreeMap;
+
+        let mut bytes = vec![0u8; Slab::<[u8; 32]>::compute_allocation_size(300)];
+        bytes[0] = AccountTag::Asks as u8;
+        let mut slab = Slab::<[u8; 32]>::from_buffer(&mut bytes, AccountTag::Asks).unwrap();
+        let mut model: BTreeMap<u128, u64> = BTreeMap::new();
+        let mut rng = StdRng::seed_from_u64(2);
+
+        for seq in 0..5_000u64 {
+            let keys = model.keys().copied().collect::<Vec<_>>();
+            match rng.gen_range(0..3) {
+                // Few prices, so that levels hold several orders
+                0 if model.len() < 300 => {
+                    let price = rng.gen_range(1..20);
+                    let key = OrderId::from_parts(price, seq, crate::state::Side::Ask).into();
+                    let base_quantity = rng.gen_range(1..u64::MAX / 2);
+                    let leaf = LeafNode {
+                        key,
+                        base_quantity,
+                        max_ts: u64::MAX,
+                    };
+                    slab.insert_leaf(&leaf).unwrap();
+                    model.insert(key, base_quantity);
+                }
+                1 if !keys.is_empty() => {
+                    let key = *keys.choose(&mut rng).unwrap();
+                    slab.remove_by_key(key).unwrap();
+                    model.remove(&key);
+                }
+                2 if !keys.is_empty() => {
+                    let key = *keys.choose(&mut rng).unwrap();
+                    let base_quantity = model.get_mut(&key).unwrap();
+                    let amount = rng.gen_range(0..=*base_quantity);
+                    slab.decrease_base_quantity(slab.find_by_key(key).unwrap(), amount);
+                    *base_quantity -= amount;
+                }
+                _ => continue,
+            }
+            slab.check_invariants();
+
+            let price = rng.gen_range(0..21);
+            let brute_force_level: u128 = model
+                .iter()
+                .filter(|(&k, _)| LeafNode::price_from_key(k) == price)
+                .map(|(_, &q)| q as u128)
+                .sum();
+            assert_eq!(slab.level_base_quantity(price), brute_force_level);
+            let level_order_count = model
+                .keys()
+                .filter(|&&k| LeafNode::price_from_key(k) == price)
+                .count() as u64;
+            assert_eq!(slab.level_order_count(price), level_order_count);
+            assert_eq!(slab.find_level(price).is_some(), level_order_count > 0);
+
+            let bound = match rng.gen_range(0..3) {
+                0 => rng.gen(),
+                1 => (price as u128) << 64,
+                _ => keys.choose(&mut rng).copied().unwrap_or_default(),
+            };
+            let brute_force_below: u128 = model.range(..bound).map(|(_, &q)| q as u128).sum();
+            assert_eq!(slab.base_quantity_below(Some(bound)), brute_force_below);
+            let brute_force_total: u128 = model.values().map(|&q| q as u128).sum();
+            assert_eq!(slab.base_quantity_below(None), brute_force_total);
+        }
+    }
+
     #[test]
     #[cfg(not(feature = "quick-test"))]
     fn simulate_operations() {
@@ -812,19 +1452,21 @@ mod tests {
                             base_quantity: qty,
                             max_ts: u64::MAX
                         };
-                        let (leaf_h, old_leaf) = slab.insert_leaf(&leaf).unwrap();
-                        let old_owner = *slab.get_callback_info(leaf_h);
-                        *slab.get_callback_info_mut(leaf_h) = owner;
-
                         println!("Insert {:x}", key);
 
-                        all_keys.push(key);
-                        let slab_value = old_leaf.map(|l| (l, old_owner));
-                        let model_value = model.insert(key, (leaf, owner));
-                        if slab_value != model_value {
-                            slab.dump();
+                        // Duplicate keys are rejected and leave the existing leaf untouched
+                        if model.contains_key(&key) {
+                            assert!(matches!(
+                                slab.insert_leaf(&leaf),
+                                Err(AoError::DuplicateOrderId)
+                            ));
+                            continue;
                         }
-                        assert_eq!(slab_value, model_value);
+                        let leaf_h = slab.insert_leaf(&leaf).unwrap();
+                        *slab.get_callback_info_mut(leaf_h) = owner;
+
+                        all_keys.push(key);
+                        model.insert(key, (leaf, owner));
                     }
                     Op::Delete => {
                         let key = all_keys
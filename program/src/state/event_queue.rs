@@ -4,16 +4,24 @@
 //! to other use-case specific data structures. It is essential to bypass the need for predicting
 //! an instruction's required account beforehand : the runtime can freely decide which users to
 //! match together this way.
+use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::{CheckedBitPattern, NoUninit, Pod, Zeroable};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use solana_program::{entrypoint::ProgramResult, msg, program_error::ProgramError};
+use solana_program::{
+    entrypoint::ProgramResult, hash::hashv, log::sol_log_data, msg, program_error::ProgramError,
+};
 
 pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
 pub use crate::utils::get_spread;
 
-use super::{AccountTag, Side};
+use super::{orderbook::CallbackInfo, timestamped_seq, AccountTag, OrderId, Side};
+use crate::{
+    error::AoError,
+    utils::{cast_callback_infos_mut, merkle_root, try_cast_callback_infos},
+};
+use std::{collections::HashMap, hash::Hash};
 
 #[derive(Clone, Zeroable, Pod, Copy, Debug, PartialEq)]
 #[repr(C)]
@@ -23,18 +31,55 @@ pub struct FillEvent {
     pub tag: u8,
     /// The u8 representation for a [`Side`] enum
     pub taker_side: u8,
-    pub(crate) _padding: [u8; 6],
+    /// The layout version of the event, which determines which fields are meaningful
+    pub version: u8,
+    pub(crate) _padding: [u8; 5],
     /// The total quote size of the transaction
     pub quote_size: u64,
     /// The order id of the maker order
-    pub maker_order_id: u128,
+    pub maker_order_id: OrderId,
     /// The total base size of the transaction
     pub base_size: u64,
+    /// The price at which the transaction happened (FP32). Only set from version 2 onwards.
+    pub price: u64,
+    /// The order id of the taker order. Only set from version 1 onwards.
+    pub taker_order_id: OrderId,
 }
 
 impl FillEvent {
     /// Byte length of the FillEvent object
     pub const LEN: usize = std::mem::size_of::<Self>();
+
+    /// The layout version of the Fill events written by this program.
+    ///
+    /// - Version 0 events are 48 bytes long and end with the `base_size` field.
+    /// - Version 1 events are 64 bytes long and carry the `taker_order_id` field.
+    /// - Version 2 events also carry the `price` field.
+    pub const VERSION: u8 = 2;
+
+    /// Decodes a Fill event written with any version up to [`FillEvent::VERSION`].
+    ///
+    /// Version 0 events only need to span 48 bytes, the fields they don't carry are zeroed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AoError> {
+        decode_event(bytes, EventTag::Fill, FillEvent::VERSION)
+    }
+
+    /// The price at which the transaction happened (FP32), or `None` for events written with a version
+    /// which doesn't carry it.
+    pub fn price(&self) -> Option<u64> {
+        match self.version {
+            0 | 1 => None,
+            _ => Some(self.price),
+        }
+    }
+
+    /// The order id of the taker order, or `None` for events written with a version which doesn't carry it.
+    pub fn taker_order_id(&self) -> Option<OrderId> {
+        match self.version {
+            0 => None,
+            _ => Some(self.taker_order_id),
+        }
+    }
 }
 
 #[derive(Clone, Zeroable, Pod, Copy, Debug, PartialEq)]
@@ -45,11 +90,148 @@ pub struct OutEvent {
     pub tag: u8,
     /// The u8 representation for a [`Side`] enum
     pub side: u8,
-    pub(crate) _padding: [u8; 14],
+    /// The layout version of the event, which determines which fields are meaningful
+    pub version: u8,
+    pub(crate) _padding: [u8; 13],
     /// The order id of the maker order
-    pub order_id: u128,
+    pub order_id: OrderId,
     /// The total base size of the transaction
     pub base_size: u64,
+    pub(crate) _padding_end: [u8; 24],
+}
+
+impl OutEvent {
+    /// The layout version of the Out events written by this program.
+    ///
+    /// - Version 0 events are 48 bytes long and end with the `base_size` field.
+    /// - Version 1 events are padded to 64 bytes, the fields keep their offsets.
+    pub const VERSION: u8 = 1;
+
+    /// Decodes an Out event written with any version up to [`OutEvent::VERSION`].
+    ///
+    /// Version 0 events only need to span 48 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, AoError> {
+        decode_event(bytes, EventTag::Out, OutEvent::VERSION)
+    }
+}
+
+/// Byte length of the version 0 Fill and Out events
+const EVENT_V0_LEN: usize = 48;
+
+/// Decodes an event of the given tag, version 0 events being shorter than the current layout
+fn decode_event<T: Pod>(bytes: &[u8], tag: EventTag, max_version: u8) -> Result<T, AoError> {
+    if bytes.len() < 3 || bytes[0] != tag as u8 || bytes[2] > max_version {
+        return Err(AoError::InvalidEvent);
+    }
+    let len = match bytes[2] {
+        0 => EVENT_V0_LEN,
+        _ => std::mem::size_of::<T>(),
+    };
+    if bytes.len() < len {
+        return Err(AoError::InvalidEvent);
+    }
+    let mut event = T::zeroed();
+    bytemuck::bytes_of_mut(&mut event)[..len].copy_from_slice(&bytes[..len]);
+    Ok(event)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, FromPrimitive)]
+#[repr(u8)]
+/// Describes why an order of a batch was rejected
+pub enum RejectReason {
+    /// The limit price isn't a positive tick size multiple
+    InvalidLimitPrice,
+    /// The limit price is outside of the market's price band
+    PriceOutOfBand,
+    /// The order would cross the spread
+    CrossesSpread,
+    /// The order's base size is below the market's minimum order size
+    TooSmall,
+    /// There is no space left in the orderbook for the order
+    BookFull,
+    /// New orders are paused on the order's side of the book
+    SidePaused,
+    /// The order's base size isn't a multiple of the market's base lot size
+    InvalidLotSize,
+    /// An order with the same order id is already in the orderbook
+    DuplicateOrderId,
+    /// The order's owner would hold more resting orders than the market allows
+    TooManyOrders,
+    /// The order would open a new price level beyond the market's maximum number of levels per side
+    TooManyLevels,
+}
+
+impl From<RejectReason> for AoError {
+    fn from(reason: RejectReason) -> Self {
+        match reason {
+            RejectReason::InvalidLimitPrice | RejectReason::CrossesSpread => {
+                AoError::InvalidLimitPrice
+            }
+            RejectReason::PriceOutOfBand => AoError::PriceOutOfBand,
+            RejectReason::TooSmall => AoError::InvalidBaseQuantity,
+            RejectReason::BookFull => AoError::SlabOutOfSpace,
+            RejectReason::SidePaused => AoError::SidePaused,
+            RejectReason::InvalidLotSize => AoError::InvalidLotSize,
+            RejectReason::DuplicateOrderId => AoError::DuplicateOrderId,
+            RejectReason::TooManyOrders => AoError::TooManyOrders,
+            RejectReason::TooManyLevels => AoError::TooManyLevels,
+        }
+    }
+}
+
+#[derive(Clone, Zeroable, Pod, Copy, Debug, PartialEq)]
+#[repr(C)]
+/// Represents an order of a batch which was rejected while the rest of the batch was posted
+pub struct RejectEvent {
+    /// The u8 representation for an [`AccountTag`] enum
+    pub tag: u8,
+    /// The u8 representation for a [`Side`] enum
+    pub side: u8,
+    /// The layout version of the event, which determines which fields are meaningful
+    pub version: u8,
+    /// The u8 representation for a [`RejectReason`] enum
+    pub reason: u8,
+    pub(crate) _padding: [u8; 4],
+    /// The position of the rejected order within its batch
+    pub batch_index: u64,
+    /// The limit price of the rejected order (FP32)
+    pub limit_price: u64,
+    /// The base size of the rejected order
+    pub base_size: u64,
+    pub(crate) _padding_end: [u8; 32],
+}
+
+impl RejectEvent {
+    /// The layout version of the Reject events written by this program.
+    pub const VERSION: u8 = 0;
+
+    /// The reason for which the order was rejected
+    pub fn reason(&self) -> Option<RejectReason> {
+        RejectReason::from_u8(self.reason)
+    }
+}
+
+#[derive(Clone, Zeroable, Pod, Copy, Debug, PartialEq)]
+#[repr(C)]
+/// A keep-alive event which carries no order information, so that consumers using the queue as a heartbeat can
+/// detect the market's liveness and gaps in the event sequence, see [`EventQueue::push_tick`]
+pub struct TickEvent {
+    /// The u8 representation for an [`AccountTag`] enum
+    pub tag: u8,
+    pub(crate) _padding: u8,
+    /// The layout version of the event, which determines which fields are meaningful
+    pub version: u8,
+    pub(crate) _padding_version: [u8; 5],
+    /// The unix timestamp at which the tick was pushed
+    pub timestamp: i64,
+    /// The sequence number of the tick in the event queue
+    pub seq_num: u64,
+    pub(crate) _padding_end: [u8; 40],
+}
+
+impl TickEvent {
+    /// The layout version of the Tick events written by this program.
+    pub const VERSION: u8 = 0;
 }
 
 #[derive(PartialEq, Debug)]
@@ -59,6 +241,81 @@ pub enum EventRef<'a, C> {
     Fill(FillEventRef<'a, C>),
     #[allow(missing_docs)]
     Out(OutEventRef<'a, C>),
+    #[allow(missing_docs)]
+    Reject(RejectEventRef<'a, C>),
+    /// Tick events don't have any callback information
+    Tick(&'a TickEvent),
+}
+
+impl<'a, C: Copy> EventRef<'a, C> {
+    /// Copies the referenced event and its callback infos out of the event queue
+    pub fn to_owned(&self) -> OwnedEvent<C> {
+        match self {
+            EventRef::Fill(f) => OwnedEvent::Fill {
+                event: *f.event,
+                maker_callback_info: *f.maker_callback_info,
+                taker_callback_info: *f.taker_callback_info,
+            },
+            EventRef::Out(o) => OwnedEvent::Out {
+                event: *o.event,
+                callback_info: *o.callback_info,
+            },
+            EventRef::Reject(r) => OwnedEvent::Reject {
+                event: *r.event,
+                callback_info: *r.callback_info,
+            },
+            EventRef::Tick(t) => OwnedEvent::Tick { event: **t },
+        }
+    }
+}
+
+impl<'a, C: Pod> EventRef<'a, C> {
+    /// Hashes the referenced event and its callback infos into a leaf of the Merkle tree of consumed events, see
+    /// [`crate::utils::merkle_root`]
+    pub fn merkle_leaf(&self) -> [u8; 32] {
+        let (event, callback_infos): (&[u8], [&[u8]; 2]) = match self {
+            EventRef::Fill(f) => (
+                bytemuck::bytes_of(f.event),
+                [
+                    bytemuck::bytes_of(f.maker_callback_info),
+                    bytemuck::bytes_of(f.taker_callback_info),
+                ],
+            ),
+            EventRef::Out(o) => (
+                bytemuck::bytes_of(o.event),
+                [bytemuck::bytes_of(o.callback_info), &[]],
+            ),
+            EventRef::Reject(r) => (
+                bytemuck::bytes_of(r.event),
+                [bytemuck::bytes_of(r.callback_info), &[]],
+            ),
+            EventRef::Tick(t) => (bytemuck::bytes_of(*t), [&[], &[]]),
+        };
+        hashv(&[&[0], event, callback_infos[0], callback_infos[1]]).to_bytes()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+/// An owned copy of an event in the EventQueue, as returned by [`EventRef::to_owned`].
+///
+/// Inspecting events through [`EventRef`] doesn't require any copy, so this is only needed when an event has to
+/// outlive the borrow of the event queue.
+pub enum OwnedEvent<C> {
+    #[allow(missing_docs)]
+    Fill {
+        event: FillEvent,
+        maker_callback_info: C,
+        taker_callback_info: C,
+    },
+    #[allow(missing_docs)]
+    Out { event: OutEvent, callback_info: C },
+    #[allow(missing_docs)]
+    Reject {
+        event: RejectEvent,
+        callback_info: C,
+    },
+    #[allow(missing_docs)]
+    Tick { event: TickEvent },
 }
 
 #[derive(PartialEq, Debug)]
@@ -81,72 +338,423 @@ pub struct OutEventRef<'a, C> {
     pub callback_info: &'a C,
 }
 
+#[derive(PartialEq, Debug)]
+/// An immutable reference to a Reject event in the EventQueue, as well as the rejected order's callback information.
+pub struct RejectEventRef<'a, C> {
+    #[allow(missing_docs)]
+    pub event: &'a RejectEvent,
+    #[allow(missing_docs)]
+    pub callback_info: &'a C,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, BorshSize)]
+/// Summarizes the events consumed by a crank, which is written to the register account by consume_events
+pub struct ConsumeSummary {
+    /// The crank fee (in lamports) owed for the consumed events.
+    ///
+    /// This comes first so that the register starts with the crank fee, as it did before the summary was introduced.
+    pub crank_fee: u64,
+    /// The number of events which were popped off the queue
+    pub events_consumed: u64,
+    /// When requested, the root of the Merkle tree whose leaves are the consumed events, in queue order, see
+    /// [`EventQueue::consume_with_merkle_root`]
+    pub merkle_root: Option<[u8; 32]>,
+}
+
 #[derive(FromPrimitive, Clone, Copy, CheckedBitPattern, NoUninit)]
 #[repr(u8)]
 pub(crate) enum EventTag {
     Fill,
     Out,
+    Reject,
+    Tick,
 }
 
 pub(crate) type GenericEvent = FillEvent;
 
 pub(crate) trait Event {
-    fn to_generic(&mut self) -> &GenericEvent;
+    fn to_generic(&mut self) -> GenericEvent;
 }
 
 impl Event for FillEvent {
-    fn to_generic(&mut self) -> &GenericEvent {
+    fn to_generic(&mut self) -> GenericEvent {
         self.tag = EventTag::Fill as u8;
-        self
+        self.version = FillEvent::VERSION;
+        *self
     }
 }
 
 impl Event for OutEvent {
-    fn to_generic(&mut self) -> &GenericEvent {
+    fn to_generic(&mut self) -> GenericEvent {
         self.tag = EventTag::Out as u8;
-        bytemuck::cast_ref(self)
+        self.version = OutEvent::VERSION;
+        bytemuck::cast(*self)
+    }
+}
+
+impl Event for RejectEvent {
+    fn to_generic(&mut self) -> GenericEvent {
+        self.tag = EventTag::Reject as u8;
+        self.version = RejectEvent::VERSION;
+        bytemuck::cast(*self)
+    }
+}
+
+impl Event for TickEvent {
+    fn to_generic(&mut self) -> GenericEvent {
+        self.tag = EventTag::Tick as u8;
+        self.version = TickEvent::VERSION;
+        bytemuck::cast(*self)
+    }
+}
+
+/// Groups events by the callback id of their maker, keeping each maker's events in their original order, so that
+/// crankers can settle all of a maker's events in one pass.
+///
+/// Fill events are grouped by their maker callback info, and Out and Reject events by the callback info of the order
+/// which left the book or was rejected. Tick events are left out. This is meant to be used off-chain, together with [`iter_events`] or
+/// [`EventQueue::iter`].
+pub fn group_by_maker<'a, C, I>(events: I) -> HashMap<C::CallbackId, Vec<EventRef<'a, C>>>
+where
+    C: CallbackInfo + 'a,
+    C::CallbackId: Hash + Eq + Clone,
+    I: IntoIterator<Item = EventRef<'a, C>>,
+{
+    let mut groups: HashMap<C::CallbackId, Vec<EventRef<'a, C>>> = HashMap::new();
+    for event in events {
+        let maker_callback_info = match &event {
+            EventRef::Fill(f) => f.maker_callback_info,
+            EventRef::Out(o) => o.callback_info,
+            EventRef::Reject(r) => r.callback_info,
+            EventRef::Tick(_) => continue,
+        };
+        groups
+            .entry(maker_callback_info.as_callback_id().clone())
+            .or_default()
+            .push(event);
     }
+    groups
 }
 
 ////////////////////////////////////////////////////
 // Event Queue
 
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, Debug, BorshSize,
+)]
+#[repr(u8)]
+/// Describes what happens when an event is pushed to a full event queue
+pub enum OverflowPolicy {
+    /// The event is rejected, which fails the current instruction. This is the default policy.
+    Reject,
+    /// The oldest event in the queue is dropped to make room for the new one
+    Overwrite,
+    /// The event is logged instead of being pushed, so that it can still be recovered from the transaction logs.
+    ///
+    /// Each overflow event is logged with `sol_log_data` (which base64 encodes its fields) as the event's bytes,
+    /// followed by its maker callback info and, for Fill events, its taker callback info. Logged events don't accrue
+    /// crank fees.
+    Log,
+}
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, FromPrimitive, Debug, BorshSize,
+)]
+#[repr(u8)]
+/// Describes how the lower 64 bits of new order ids, which give the time priority of orders at the same price, are
+/// generated, see [`OrderId`]
+pub enum OrderIdMode {
+    /// The queue's order sequence number is used as is. This is the default mode.
+    Sequence,
+    /// The unix timestamp (in seconds) set with [`EventQueue::set_timestamp`] is embedded above the order's rank
+    /// among the orders generated within the same second, see [`timestamped_seq`].
+    ///
+    /// Time priority then survives a reset of the order sequence number, such as when the market is migrated to a
+    /// new event queue, as long as the orders were posted at least a second apart.
+    Timestamped,
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 /// Describes the current state of the event queue
 pub struct EventQueueHeader {
-    /// The current event
+    /// The current event, as an index into the circular buffer of events rather than a byte offset
     pub head: u64,
-    /// The current event queue length
+    /// The current event queue length, in events
     pub count: u64,
     seq_num: u64,
+    /// The crank fee (in lamports) which accrues for each event pushed to the queue
+    pub fee_per_event: u64,
+    /// The total crank fee (in lamports) accrued by the events currently in the queue
+    pub accrued_crank_fees: u64,
+    /// The sequence number of the event at the head of the queue, or [`EventQueueHeader::tail_seq_num`] when the
+    /// queue is empty.
+    ///
+    /// Events are numbered in the order in which they are pushed, consecutively unless some are popped with
+    /// [`EventQueue::pop_back`].
+    pub head_seq_num: u64,
+    overflow_policy: u64,
+    order_id_mode: u64,
+    /// The sequence number which will be given to the next event pushed to the queue.
+    ///
+    /// It only ever grows, so that the sequence numbers of the events popped by [`EventQueue::pop_back`] aren't given
+    /// out again.
+    pub tail_seq_num: u64,
+    /// The latest timestamp embedded in an order id in [`OrderIdMode::Timestamped`]
+    order_id_timestamp: u64,
+    /// The number of order ids generated at `order_id_timestamp`
+    order_id_timestamp_count: u64,
 }
 
 impl EventQueueHeader {
     /// The byte size for the EventQueueHeader object
     pub const LEN: usize = std::mem::size_of::<Self>();
+
+    /// Describes what happens when an event is pushed to the full event queue
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        OverflowPolicy::from_u64(self.overflow_policy).unwrap_or(OverflowPolicy::Reject)
+    }
+
+    /// Sets the event queue's overflow policy
+    pub fn set_overflow_policy(&mut self, overflow_policy: OverflowPolicy) {
+        self.overflow_policy = overflow_policy as u64;
+    }
+
+    /// Describes how the ids of new orders are generated
+    pub fn order_id_mode(&self) -> OrderIdMode {
+        OrderIdMode::from_u64(self.order_id_mode).unwrap_or(OrderIdMode::Sequence)
+    }
+
+    /// Sets how the ids of new orders are generated
+    pub fn set_order_id_mode(&mut self, order_id_mode: OrderIdMode) {
+        self.order_id_mode = order_id_mode as u64;
+    }
+}
+
+/// Configures the header of a new event queue.
+///
+/// This is the intended way to set up an event queue account: [`EventQueueHeaderBuilder::initialize`] checks that
+/// the account is sized for whole events of the queue's callback info type and that it isn't initialized yet, and
+/// the queue always starts out empty.
+#[derive(Clone, Copy, Debug)]
+pub struct EventQueueHeaderBuilder {
+    fee_per_event: u64,
+    overflow_policy: OverflowPolicy,
+    order_id_mode: OrderIdMode,
+}
+
+impl Default for EventQueueHeaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventQueueHeaderBuilder {
+    /// Starts from a queue without crank fees which rejects events once full and generates order ids from its
+    /// sequence number
+    pub fn new() -> Self {
+        Self {
+            fee_per_event: 0,
+            overflow_policy: OverflowPolicy::Reject,
+            order_id_mode: OrderIdMode::Sequence,
+        }
+    }
+
+    /// Sets the crank fee (in lamports) which accrues for each event pushed to the queue
+    pub fn fee_per_event(mut self, fee_per_event: u64) -> Self {
+        self.fee_per_event = fee_per_event;
+        self
+    }
+
+    /// Sets what happens when an event is pushed to the full queue
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Sets how the ids of new orders are generated
+    pub fn order_id_mode(mut self, order_id_mode: OrderIdMode) -> Self {
+        self.order_id_mode = order_id_mode;
+        self
+    }
+
+    /// Builds the header of an empty queue
+    pub fn build(self) -> EventQueueHeader {
+        let mut header = EventQueueHeader::zeroed();
+        header.fee_per_event = self.fee_per_event;
+        header.set_overflow_policy(self.overflow_policy);
+        header.set_order_id_mode(self.order_id_mode);
+        header
+    }
+
+    /// Initializes an event queue in an uninitialized account's buffer, writing the built header to it.
+    pub fn initialize<C: Pod>(self, buf: &mut [u8]) -> Result<EventQueue<'_, C>, ProgramError> {
+        EventQueue::<C>::check_buffer_size(buf)?;
+        let event_queue = EventQueue::<C>::from_buffer(buf, AccountTag::Uninitialized)?;
+        *event_queue.header = self.build();
+        Ok(event_queue)
+    }
 }
 
+/// The byte length of the sequence number stored alongside each event.
+pub const EVENT_SEQ_NUM_LEN: usize = 8;
+
+/// The byte length of the timestamp stored alongside each event when the `event-timestamps` feature is enabled.
+#[cfg(feature = "event-timestamps")]
+pub const EVENT_TIMESTAMP_LEN: usize = 8;
+/// The byte length of the timestamp stored alongside each event when the `event-timestamps` feature is enabled.
+#[cfg(not(feature = "event-timestamps"))]
+pub const EVENT_TIMESTAMP_LEN: usize = 0;
+
+/// The byte length of the maker's remaining size stored alongside each event when the `fill-maker-remaining` feature
+/// is enabled.
+#[cfg(feature = "fill-maker-remaining")]
+pub const MAKER_REMAINING_LEN: usize = 8;
+/// The byte length of the maker's remaining size stored alongside each event when the `fill-maker-remaining` feature
+/// is enabled.
+#[cfg(not(feature = "fill-maker-remaining"))]
+pub const MAKER_REMAINING_LEN: usize = 0;
+
 /// The event queue account contains a serialized header, a register
 /// and a circular buffer of serialized events.
 ///
-/// This struct is used at runtime but doesn't represent a serialized event queue
+/// The sequence number of each event is stored right after the events. With the `event-timestamps` feature, the
+/// unix timestamp at which each event was pushed is stored between the sequence numbers and the callback infos. With the `fill-maker-remaining` feature, the maker's remaining
+/// resting size after each Fill event is stored after the timestamps.
+///
+/// This struct is used at runtime but doesn't represent a serialized event queue. Its fields borrow the account's
+/// data in place, so every change to the header or the events is written to the account as it is made and there is
+/// nothing to persist once the struct is dropped.
 pub struct EventQueue<'a, C> {
     pub(crate) header: &'a mut EventQueueHeader,
     pub(crate) events: &'a mut [FillEvent],
+    pub(crate) seq_nums: &'a mut [u64],
+    #[cfg(feature = "event-timestamps")]
+    pub(crate) timestamps: &'a mut [i64],
+    pub(crate) current_timestamp: i64,
+    #[cfg(feature = "fill-maker-remaining")]
+    pub(crate) maker_remainings: &'a mut [u64],
     pub(crate) callback_infos: &'a mut [C],
 }
 
+fn header_in_bounds(header: &EventQueueHeader, capacity: usize) -> bool {
+    header.head < capacity as u64 && header.count <= capacity as u64
+}
+
+fn validate_events(header: &EventQueueHeader, events: &[FillEvent]) -> Result<(), AoError> {
+    if !header_in_bounds(header, events.len()) {
+        return Err(AoError::CorruptQueue);
+    }
+    for index in 0..header.count {
+        let event_idx = ((header.head + index) as usize) % events.len();
+        let event = &events[event_idx];
+        let max_version = match EventTag::from_u8(event.tag) {
+            Some(EventTag::Fill) => FillEvent::VERSION,
+            Some(EventTag::Out) => OutEvent::VERSION,
+            Some(EventTag::Reject) => RejectEvent::VERSION,
+            Some(EventTag::Tick) => TickEvent::VERSION,
+            None => return Err(AoError::InvalidEvent),
+        };
+        if event.version > max_version {
+            return Err(AoError::InvalidEvent);
+        }
+    }
+    Ok(())
+}
+
+fn event_ref<'a, C>(
+    events: &'a [FillEvent],
+    callback_infos: &'a [C],
+    event_idx: usize,
+) -> EventRef<'a, C> {
+    let event = &events[event_idx];
+    match EventTag::from_u8(event.tag).unwrap() {
+        EventTag::Fill => EventRef::Fill(FillEventRef {
+            event,
+            maker_callback_info: &callback_infos[2 * event_idx],
+            taker_callback_info: &callback_infos[2 * event_idx + 1],
+        }),
+        EventTag::Out => EventRef::Out(OutEventRef {
+            event: bytemuck::cast_ref(event),
+            callback_info: &callback_infos[2 * event_idx],
+        }),
+        EventTag::Reject => EventRef::Reject(RejectEventRef {
+            event: bytemuck::cast_ref(event),
+            callback_info: &callback_infos[2 * event_idx],
+        }),
+        EventTag::Tick => EventRef::Tick(bytemuck::cast_ref(event)),
+    }
+}
+
+/// Decodes the pending events of a raw event queue account, as fetched over RPC, in FIFO order.
+///
+/// This is a read-only counterpart to [`EventQueue::from_buffer`] and [`EventQueue::iter`] for off-chain clients:
+/// the account tag, header and every pending event are checked up front, so the returned iterator can't fail. The
+/// events are read in place, so account data which isn't aligned for them, such as a slice starting at an odd offset
+/// of a larger buffer, is rejected with [`AoError::UnalignedAccountData`] rather than copied.
+pub fn iter_events<C: Pod>(
+    account_data: &[u8],
+) -> Result<impl DoubleEndedIterator<Item = EventRef<'_, C>>, AoError> {
+    let event_size = EventQueue::<C>::event_size();
+    if account_data.len() < 8 + EventQueueHeader::LEN + event_size {
+        return Err(AoError::AccountTooSmall);
+    }
+    if account_data[0..8] != (AccountTag::EventQueue as u64).to_le_bytes() {
+        return Err(AoError::WrongAccountTag);
+    }
+    if (account_data.len() - 8 - EventQueueHeader::LEN) % event_size != 0 {
+        return Err(AoError::EventSizeMismatch);
+    }
+    let capacity = (account_data.len() - 8 - EventQueueHeader::LEN) / event_size;
+    let (header, remaining) = account_data[8..].split_at(EventQueueHeader::LEN);
+    let header: &EventQueueHeader =
+        bytemuck::try_from_bytes(header).map_err(|_| AoError::UnalignedAccountData)?;
+    let (events, remaining) = remaining.split_at(capacity * FillEvent::LEN);
+    let (_seq_nums, remaining) = remaining.split_at(capacity * EVENT_SEQ_NUM_LEN);
+    let (_timestamps, remaining) = remaining.split_at(capacity * EVENT_TIMESTAMP_LEN);
+    let (_maker_remainings, callback_infos) = remaining.split_at(capacity * MAKER_REMAINING_LEN);
+    let events: &[FillEvent] =
+        bytemuck::try_cast_slice(events).map_err(|_| AoError::UnalignedAccountData)?;
+    let callback_infos: &[C] = try_cast_callback_infos(
+        &callback_infos[..2 * capacity * std::mem::size_of::<C>()],
+        2 * capacity,
+    )?;
+
+    validate_events(header, events)?;
+    Ok((0..header.count as usize).map(move |index| {
+        event_ref(
+            events,
+            callback_infos,
+            (header.head as usize + index) % capacity,
+        )
+    }))
+}
+
+/// Decodes the pending events of a raw event queue account, as fetched over RPC, latest first.
+///
+/// This is the reverse of [`iter_events`], for off-chain consumers which only care about the most recent events.
+pub fn iter_events_rev<C: Pod>(
+    account_data: &[u8],
+) -> Result<impl Iterator<Item = EventRef<'_, C>>, AoError> {
+    Ok(iter_events(account_data)?.rev())
+}
+
 impl<'queue, C: Pod> EventQueue<'queue, C> {
     /// Instantiates an event queue object from an account's buffer
     pub fn from_buffer(
         buf: &'queue mut [u8],
         expected_tag: AccountTag,
     ) -> Result<Self, ProgramError> {
-        let callback_info_len = std::mem::size_of::<C>();
-
-        let capacity =
-            (buf.len() - 8 - EventQueueHeader::LEN) / (FillEvent::LEN + 2 * callback_info_len);
+        if buf.len() < 8 + EventQueueHeader::LEN + Self::event_size() {
+            msg!("The event queue account is too small!");
+            return Err(AoError::AccountTooSmall.into());
+        }
+        // A queue created for another callback info length would have its events read at the wrong offsets
+        if (buf.len() - 8 - EventQueueHeader::LEN) % Self::event_size() != 0 {
+            msg!("The event queue's size doesn't match its event size!");
+            return Err(AoError::EventSizeMismatch.into());
+        }
+        let capacity = (buf.len() - 8 - EventQueueHeader::LEN) / Self::event_size();
         let account_tag: &mut u64 = bytemuck::from_bytes_mut(&mut buf[0..8]);
 
         if *account_tag != expected_tag as u64 {
@@ -155,17 +763,50 @@ impl<'queue, C: Pod> EventQueue<'queue, C> {
         *account_tag = AccountTag::EventQueue as u64;
 
         let (header, remaining) = buf[8..].split_at_mut(EventQueueHeader::LEN);
+        let header: &mut EventQueueHeader = bytemuck::from_bytes_mut(header);
+
+        // All event offsets are derived from the head and count, which must stay within the circular buffer
+        if !header_in_bounds(header, capacity) {
+            msg!("The event queue's head or count is out of bounds!");
+            return Err(AoError::CorruptQueue.into());
+        }
 
-        let (events, callback_infos) = remaining.split_at_mut(capacity * FillEvent::LEN);
+        let (events, remaining) = remaining.split_at_mut(capacity * FillEvent::LEN);
+        let (seq_nums, remaining) = remaining.split_at_mut(capacity * EVENT_SEQ_NUM_LEN);
+        let (_timestamps, remaining) = remaining.split_at_mut(capacity * EVENT_TIMESTAMP_LEN);
+        let (_maker_remainings, callback_infos) =
+            remaining.split_at_mut(capacity * MAKER_REMAINING_LEN);
         Ok(Self {
-            header: bytemuck::from_bytes_mut(header),
+            header,
             events: bytemuck::cast_slice_mut(events),
-            callback_infos: bytemuck::cast_slice_mut(callback_infos),
+            seq_nums: bytemuck::cast_slice_mut(seq_nums),
+            #[cfg(feature = "event-timestamps")]
+            timestamps: bytemuck::cast_slice_mut(_timestamps),
+            current_timestamp: 0,
+            #[cfg(feature = "fill-maker-remaining")]
+            maker_remainings: bytemuck::cast_slice_mut(_maker_remainings),
+            callback_infos: cast_callback_infos_mut(callback_infos, 2 * capacity),
         })
     }
 }
 
-impl<'queue, C: Clone> EventQueue<'queue, C> {
+/// Logs an event which couldn't be pushed to the full event queue, see [`OverflowPolicy::Log`].
+fn log_overflow_event<C: Pod>(
+    event: &GenericEvent,
+    maker_callback_info: Option<&C>,
+    taker_callback_info: Option<&C>,
+) {
+    let mut fields: Vec<&[u8]> = vec![bytemuck::bytes_of(event)];
+    fields.extend(
+        maker_callback_info
+            .into_iter()
+            .chain(taker_callback_info)
+            .map(bytemuck::bytes_of),
+    );
+    sol_log_data(&fields);
+}
+
+impl<'queue, C: Pod> EventQueue<'queue, C> {
     pub(crate) fn push_back<Ev: Event>(
         &mut self,
         mut event: Ev,
@@ -173,38 +814,195 @@ impl<'queue, C: Clone> EventQueue<'queue, C> {
         taker_callback_info: Option<&C>,
     ) -> Result<(), Ev> {
         if self.full() {
-            return Err(event);
+            match self.header.overflow_policy() {
+                OverflowPolicy::Reject => return Err(event),
+                // The oldest event is dropped along with its share of the accrued crank fees
+                OverflowPolicy::Overwrite => {
+                    self.pop_n(1);
+                }
+                OverflowPolicy::Log => {
+                    log_overflow_event(
+                        &event.to_generic(),
+                        maker_callback_info,
+                        taker_callback_info,
+                    );
+                    return Ok(());
+                }
+            }
         }
         let generic_event = event.to_generic();
         let event_idx =
             (self.header.head as usize + self.header.count as usize) % self.events.len();
-        self.events[event_idx] = *generic_event;
+        self.events[event_idx] = generic_event;
+        self.seq_nums[event_idx] = self.header.tail_seq_num;
+        #[cfg(feature = "event-timestamps")]
+        {
+            self.timestamps[event_idx] = self.current_timestamp;
+        }
 
+        if self.header.count == 0 {
+            self.header.head_seq_num = self.header.tail_seq_num;
+        }
         self.header.count += 1;
+        self.header.tail_seq_num += 1;
+        self.header.accrued_crank_fees = self
+            .header
+            .accrued_crank_fees
+            .saturating_add(self.header.fee_per_event);
 
         if let Some(c) = maker_callback_info {
-            self.callback_infos[event_idx * 2] = c.clone();
+            self.callback_infos[event_idx * 2] = *c;
         }
 
         if let Some(c) = taker_callback_info {
-            self.callback_infos[event_idx * 2 + 1] = c.clone();
+            self.callback_infos[event_idx * 2 + 1] = *c;
         }
 
         Ok(())
     }
+
+    /// Pushes a Fill event, along with the maker's remaining resting size after the fill when the
+    /// `fill-maker-remaining` feature is enabled, see [`EventQueue::maker_remaining_at`].
+    pub(crate) fn push_fill(
+        &mut self,
+        event: FillEvent,
+        maker_callback_info: &C,
+        taker_callback_info: &C,
+        _maker_remaining: u64,
+    ) -> Result<(), FillEvent> {
+        // A logged event isn't written to the queue, so there is no slot to store the remaining size in
+        #[cfg(feature = "fill-maker-remaining")]
+        let logged = self.full() && self.header.overflow_policy() == OverflowPolicy::Log;
+        self.push_back(event, Some(maker_callback_info), Some(taker_callback_info))?;
+        #[cfg(feature = "fill-maker-remaining")]
+        {
+            if !logged {
+                let event_idx = (self.header.head as usize + self.header.count as usize - 1)
+                    % self.events.len();
+                self.maker_remainings[event_idx] = _maker_remaining;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes a keep-alive [`TickEvent`] to the queue, which a caller program can do on each slot so that consumers
+    /// can tell an idle market apart from a stalled one.
+    ///
+    /// The tick's sequence number is the one given to it in the queue, so a consumer which sees a tick after another
+    /// can check that no event was missed in between.
+    pub fn push_tick(&mut self, timestamp: i64) -> Result<(), AoError> {
+        let tick = TickEvent {
+            tag: EventTag::Tick as u8,
+            _padding: 0,
+            version: TickEvent::VERSION,
+            _padding_version: [0; 5],
+            timestamp,
+            seq_num: self.next_event_seq_num(),
+            _padding_end: [0; 40],
+        };
+        self.push_back(tick, None, None)
+            .map_err(|_| AoError::EventQueueFull)
+    }
+
+    /// Appends all of `other`'s pending events to this queue in FIFO order, returning the number of events appended.
+    ///
+    /// The events keep their callback infos and the crank fees they accrued in `other`, which is left untouched, and
+    /// are numbered after this queue's events. Nothing is appended if the queue lacks the capacity for all of them.
+    pub fn append_from(&mut self, other: &EventQueue<'_, C>) -> Result<usize, AoError> {
+        let available = self.events.len() as u64 - self.header.count;
+        if other.header.count > available {
+            return Err(AoError::EventQueueFull);
+        }
+        for index in 0..other.header.count {
+            let other_idx = ((other.header.head + index) as usize) % other.events.len();
+            let event_idx =
+                ((self.header.head + self.header.count + index) as usize) % self.events.len();
+            self.events[event_idx] = other.events[other_idx];
+            self.seq_nums[event_idx] = self.header.tail_seq_num + index;
+            #[cfg(feature = "event-timestamps")]
+            {
+                self.timestamps[event_idx] = other.timestamps[other_idx];
+            }
+            #[cfg(feature = "fill-maker-remaining")]
+            {
+                self.maker_remainings[event_idx] = other.maker_remainings[other_idx];
+            }
+            self.callback_infos[2 * event_idx] = other.callback_infos[2 * other_idx];
+            self.callback_infos[2 * event_idx + 1] = other.callback_infos[2 * other_idx + 1];
+        }
+        if self.header.count == 0 {
+            self.header.head_seq_num = self.header.tail_seq_num;
+        }
+        self.header.count += other.header.count;
+        self.header.tail_seq_num += other.header.count;
+        self.header.accrued_crank_fees = self
+            .header
+            .accrued_crank_fees
+            .saturating_add(other.header.accrued_crank_fees);
+        Ok(other.header.count as usize)
+    }
+
+    /// Moves the queue to `new_buffer`, an uninitialized account which is usually smaller, such as to reclaim the
+    /// rent of an event queue which was sized for a period of high volume. The new queue is returned.
+    ///
+    /// The pending events are compacted to the front of the new queue, keeping their order, sequence numbers and
+    /// accrued crank fees, and the header's configuration and order sequence number are carried over. This queue is
+    /// left untouched, so that its account can be closed once the market points to the new one. Fails with
+    /// [`AoError::WontFit`] if the new account can't hold the pending events, in which case it isn't modified.
+    pub fn shrink<'b>(&self, new_buffer: &'b mut [u8]) -> Result<EventQueue<'b, C>, ProgramError> {
+        if new_buffer.len()
+            < Self::compute_allocation_size(std::cmp::max(self.header.count, 1) as usize)
+        {
+            return Err(AoError::WontFit.into());
+        }
+        let mut new_queue = EventQueue::from_buffer(new_buffer, AccountTag::Uninitialized)?;
+        *new_queue.header = *self.header;
+        new_queue.header.head = 0;
+        new_queue.header.count = 0;
+        new_queue.header.accrued_crank_fees = 0;
+        new_queue.append_from(self)?;
+        for index in 0..self.header.count {
+            let event_idx = ((self.header.head + index) as usize) % self.events.len();
+            new_queue.seq_nums[index as usize] = self.seq_nums[event_idx];
+        }
+        new_queue.header.head_seq_num = self.header.head_seq_num;
+        new_queue.header.tail_seq_num = self.header.tail_seq_num;
+        Ok(new_queue)
+    }
+
+    /// Pops up to `max_entries` events like [`EventQueue::consume_with_summary`], also committing to the consumed
+    /// events with the root of the Merkle tree whose leaves are their [`EventRef::merkle_leaf`] hashes.
+    ///
+    /// An off-chain consumer can then prove that an event was consumed by this crank with
+    /// [`crate::utils::merkle_proof`].
+    pub fn consume_with_merkle_root(&mut self, max_entries: u64) -> ConsumeSummary {
+        let mut leaves = vec![];
+        let summary = self.consume_with_summary(max_entries, |e| leaves.push(e.merkle_leaf()));
+        ConsumeSummary {
+            merkle_root: merkle_root(&leaves),
+            ..summary
+        }
+    }
 }
 
 impl<'queue, C> EventQueue<'queue, C> {
+    /// The number of bytes taken up by each event slot, including its callback infos
+    pub fn event_size() -> usize {
+        FillEvent::LEN
+            + EVENT_SEQ_NUM_LEN
+            + EVENT_TIMESTAMP_LEN
+            + MAKER_REMAINING_LEN
+            + 2 * std::mem::size_of::<C>()
+    }
+
     /// Compute the allocation size for an event queue of a desired capacity
     pub fn compute_allocation_size(desired_event_capacity: usize) -> usize {
-        desired_event_capacity * (FillEvent::LEN + 2 * std::mem::size_of::<C>())
-            + EventQueueHeader::LEN
-            + 8
+        desired_event_capacity * Self::event_size() + EventQueueHeader::LEN + 8
     }
 
     pub(crate) fn check_buffer_size(buffer: &[u8]) -> ProgramResult {
         const HEADER_OFFSET: usize = EventQueueHeader::LEN + 8;
-        let event_size: usize = FillEvent::LEN + 2 * std::mem::size_of::<C>();
+        let event_size: usize = Self::event_size();
         let account_len = buffer.len();
         if account_len < HEADER_OFFSET + 5 * event_size {
             msg!("The event queue account is too small!");
@@ -212,19 +1010,40 @@ impl<'queue, C> EventQueue<'queue, C> {
         }
         if (account_len - HEADER_OFFSET) % event_size != 0 {
             msg!("Event queue account size is invalid!");
-            return Err(ProgramError::InvalidAccountData);
+            return Err(AoError::EventSizeMismatch.into());
         }
         Ok(())
     }
 
-    pub(crate) fn gen_order_id(&mut self, limit_price: u64, side: Side) -> u128 {
+    /// Generates the id of a new order, which is unique across both sides of the book, see [`OrderId`].
+    ///
+    /// The order's time priority is given by the queue's order sequence number, along with the current timestamp in
+    /// [`OrderIdMode::Timestamped`].
+    pub(crate) fn gen_order_id(&mut self, limit_price: u64, side: Side) -> OrderId {
         let seq_num = self.gen_seq_num();
-        let upper = (limit_price as u128) << 64;
-        let lower = match side {
-            Side::Bid => !seq_num,
-            Side::Ask => seq_num,
+        let seq = match self.header.order_id_mode() {
+            OrderIdMode::Sequence => seq_num,
+            OrderIdMode::Timestamped => {
+                let timestamp = std::cmp::max(self.current_timestamp, 0) as u64;
+                // The embedded timestamp never goes back, so that newer orders never sort ahead of older ones
+                if timestamp > self.header.order_id_timestamp {
+                    self.header.order_id_timestamp = timestamp;
+                    self.header.order_id_timestamp_count = 0;
+                }
+                let rank = self.header.order_id_timestamp_count;
+                self.header.order_id_timestamp_count += 1;
+                timestamped_seq(self.header.order_id_timestamp, rank)
+            }
         };
-        upper | (lower as u128)
+        OrderId::from_parts(limit_price, seq, side)
+    }
+
+    /// Returns the sequence number which will be used for the next order id, without consuming it.
+    ///
+    /// Each generated order id consumes exactly one sequence number, so that consecutive order ids have
+    /// consecutive sequence numbers. Pushing events doesn't consume order sequence numbers.
+    pub fn next_seq_num(&self) -> u64 {
+        self.header.seq_num
     }
 
     fn gen_seq_num(&mut self) -> u64 {
@@ -248,27 +1067,175 @@ impl<'queue, C> EventQueue<'queue, C> {
     }
 
     fn get_event(&self, event_idx: usize) -> EventRef<'_, C> {
-        let event = &self.events[event_idx];
-        match EventTag::from_u8(event.tag).unwrap() {
-            EventTag::Fill => EventRef::Fill(FillEventRef {
-                event,
-                maker_callback_info: &self.callback_infos[2 * event_idx],
-                taker_callback_info: &self.callback_infos[2 * event_idx + 1],
-            }),
-            EventTag::Out => EventRef::Out(OutEventRef {
-                event: bytemuck::cast_ref(event),
-                callback_info: &self.callback_infos[2 * event_idx],
-            }),
-        }
-    }
-
-    /// Pop n entries from the event queue
-    pub fn pop_n(&mut self, number_of_entries_to_pop: u64) {
+        event_ref(self.events, self.callback_infos, event_idx)
+    }
+
+    /// Sets the current unix timestamp, which is embedded in the ids of new orders in [`OrderIdMode::Timestamped`].
+    ///
+    /// With the `event-timestamps` feature, it is also recorded for the events pushed from now on.
+    pub fn set_timestamp(&mut self, timestamp: i64) {
+        self.current_timestamp = timestamp;
+    }
+
+    /// Retrieves the unix timestamp at which the event at position `index` in the queue was pushed.
+    #[cfg(feature = "event-timestamps")]
+    pub fn timestamp_at(&self, index: u64) -> Option<i64> {
+        if self.header.count <= index {
+            return None;
+        }
+        let event_idx = (self.header.head.checked_add(index).unwrap() as usize) % self.events.len();
+        Some(self.timestamps[event_idx])
+    }
+
+    /// Retrieves the maker's remaining resting size after the Fill event at position `index` in the queue, or
+    /// `None` if there is no such event or if it isn't a Fill event.
+    #[cfg(feature = "fill-maker-remaining")]
+    pub fn maker_remaining_at(&self, index: u64) -> Option<u64> {
+        match self.peek_at(index)? {
+            EventRef::Fill(_) => {}
+            _ => return None,
+        }
+        let event_idx = (self.header.head.checked_add(index).unwrap() as usize) % self.events.len();
+        Some(self.maker_remainings[event_idx])
+    }
+
+    /// Checks the integrity of the whole queue: the header must be within the bounds of the circular buffer,
+    /// and every pending event must have a known tag and a version which isn't newer than this program's.
+    pub fn validate(&self) -> Result<(), AoError> {
+        validate_events(self.header, self.events)
+    }
+
+    /// Returns the crank fee owed for consuming the first n entries of the event queue.
+    ///
+    /// The accrued fees are split proportionally between the events currently in the queue.
+    pub fn owed_crank_fees(&self, number_of_entries: u64) -> u64 {
+        let capped_number_of_entries = std::cmp::min(self.header.count, number_of_entries);
+        if capped_number_of_entries == 0 {
+            return 0;
+        }
+        ((self.header.accrued_crank_fees as u128) * (capped_number_of_entries as u128)
+            / (self.header.count as u128)) as u64
+    }
+
+    /// Pop n entries from the event queue, returning the crank fee owed for them
+    pub fn pop_n(&mut self, number_of_entries_to_pop: u64) -> u64 {
         let capped_number_of_entries_to_pop =
             std::cmp::min(self.header.count, number_of_entries_to_pop);
+        let owed_crank_fees = self.owed_crank_fees(capped_number_of_entries_to_pop);
+        self.header.accrued_crank_fees -= owed_crank_fees;
         self.header.count -= capped_number_of_entries_to_pop;
+        // head is lower than the capacity and at most count entries are popped, so the sum can't overflow
+        // and wraps around at most once
         self.header.head =
             (self.header.head + capped_number_of_entries_to_pop) % (self.events.len() as u64);
+        self.header.head_seq_num = self.seq_num_at(0).unwrap_or(self.header.tail_seq_num);
+        owed_crank_fees
+    }
+
+    /// Pops the oldest event off the queue and returns a view of it, borrowed from the queue's buffer.
+    ///
+    /// The event's slot is only reused by a later push, which the returned borrow rules out, so crankers can
+    /// consume the queue one event at a time without copying or allocating. The crank fee owed for the event is
+    /// released as with [`EventQueue::pop_n`]; use [`EventQueue::owed_crank_fees`] beforehand to account for it.
+    pub fn pop_front_ref(&mut self) -> Result<EventRef<'_, C>, AoError> {
+        if self.header.count == 0 {
+            return Err(AoError::EventQueueEmpty);
+        }
+        let event_idx = self.header.head as usize;
+        self.pop_n(1);
+        Ok(self.get_event(event_idx))
+    }
+
+    /// Retrieves the most recently pushed event in the queue
+    pub fn peek_back(&self) -> Option<EventRef<'_, C>> {
+        self.peek_at(self.header.count.checked_sub(1)?)
+    }
+
+    /// Pops the most recently pushed event off the queue, returning the crank fee owed for it.
+    ///
+    /// This lets consumers which handle the newest events first process the queue in LIFO order, along with
+    /// [`EventQueue::peek_back`]. The popped event's sequence number isn't given out again: the next event pushed
+    /// is numbered from [`EventQueueHeader::tail_seq_num`], leaving a gap in the sequence.
+    pub fn pop_back(&mut self) -> u64 {
+        if self.header.count == 0 {
+            return 0;
+        }
+        let owed_crank_fees = self.owed_crank_fees(1);
+        self.header.accrued_crank_fees -= owed_crank_fees;
+        // The head is left untouched, the tail slot is derived from the head and the count
+        self.header.count -= 1;
+        if self.header.count == 0 {
+            self.header.head_seq_num = self.header.tail_seq_num;
+        }
+        owed_crank_fees
+    }
+
+    /// Moves the pending events to the front of the circular buffer, so that they are stored contiguously from the
+    /// first slot on in FIFO order and the head is 0.
+    ///
+    /// Only the placement of the events changes: their order, sequence numbers and accrued crank fees are preserved.
+    pub fn compact(&mut self) {
+        let head = self.header.head as usize;
+        self.events.rotate_left(head);
+        self.seq_nums.rotate_left(head);
+        #[cfg(feature = "event-timestamps")]
+        self.timestamps.rotate_left(head);
+        #[cfg(feature = "fill-maker-remaining")]
+        self.maker_remainings.rotate_left(head);
+        self.callback_infos.rotate_left(2 * head);
+        self.header.head = 0;
+    }
+
+    /// Pops up to `max_entries` events off the queue, calling `f` on each of them in order before they are popped,
+    /// and returns the summary of the consumed events.
+    pub fn consume_with_summary<F: FnMut(EventRef<'_, C>)>(
+        &mut self,
+        max_entries: u64,
+        mut f: F,
+    ) -> ConsumeSummary {
+        let events_consumed = std::cmp::min(self.header.count, max_entries);
+        self.iter().take(events_consumed as usize).for_each(&mut f);
+        let crank_fee = self.pop_n(events_consumed);
+        ConsumeSummary {
+            crank_fee,
+            events_consumed,
+            merkle_root: None,
+        }
+    }
+
+    /// Pop all events whose sequence number is lower than or equal to `target_seq`, returning the crank fee owed for them
+    pub fn consume_until_seq(&mut self, target_seq: u64) -> u64 {
+        // Sequence numbers grow from the head to the tail of the queue
+        let number_of_entries_to_pop = (0..self.header.count)
+            .take_while(|&index| self.seq_num_at(index).unwrap() <= target_seq)
+            .count();
+        self.pop_n(number_of_entries_to_pop as u64)
+    }
+
+    /// Returns the sequence number which will be given to the next event pushed to the queue
+    pub fn next_event_seq_num(&self) -> u64 {
+        self.header.tail_seq_num
+    }
+
+    /// Returns the price of the most recent Fill event still in the queue whose sequence number is at least
+    /// `min_seq_num`
+    pub fn last_fill_price(&self, min_seq_num: u64) -> Option<u64> {
+        (0..self.header.count)
+            .rev()
+            .take_while(|&index| self.seq_num_at(index).unwrap() >= min_seq_num)
+            .find_map(|index| match self.peek_at(index) {
+                Some(EventRef::Fill(f)) => f.event.price(),
+                _ => None,
+            })
+    }
+
+    /// Returns the sequence number of the event at position `index` in the queue
+    pub fn seq_num_at(&self, index: u64) -> Option<u64> {
+        if self.header.count <= index {
+            return None;
+        }
+        let event_idx = ((self.header.head + index) as usize) % self.events.len();
+        Some(self.seq_nums[event_idx])
     }
 
     /// Returns an iterator over all the queue's events
@@ -342,10 +1309,13 @@ mod tests {
                         FillEvent {
                             tag: EventTag::Fill as u8,
                             taker_side: Side::Ask as u8,
-                            _padding: [0; 6],
+                            version: FillEvent::VERSION,
+                            _padding: [0; 5],
                             quote_size: seq_gen.next().unwrap(),
-                            maker_order_id: seq_gen.next().unwrap() as u128,
+                            maker_order_id: OrderId(seq_gen.next().unwrap() as u128),
+                            taker_order_id: OrderId(seq_gen.next().unwrap() as u128),
                             base_size: seq_gen.next().unwrap(),
+                            price: seq_gen.next().unwrap(),
                         },
                         Some(&[seq_gen.next().unwrap() as u8; 32]),
                         Some(&[seq_gen.next().unwrap() as u8; 32]),
@@ -357,9 +1327,11 @@ mod tests {
                         OutEvent {
                             tag: EventTag::Out as u8,
                             side: Side::Ask as u8,
-                            _padding: [0; 14],
+                            version: OutEvent::VERSION,
+                            _padding: [0; 13],
+                            _padding_end: [0; 24],
                             base_size: seq_gen.next().unwrap(),
-                            order_id: seq_gen.next().unwrap() as u128,
+                            order_id: OrderId(seq_gen.next().unwrap() as u128),
                         },
                         Some(&[seq_gen.next().unwrap() as u8; 32]),
                         None,
@@ -370,10 +1342,13 @@ mod tests {
         let extra_event = FillEvent {
             tag: EventTag::Fill as u8,
             taker_side: Side::Ask as u8,
-            _padding: [0; 6],
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
             quote_size: seq_gen.next().unwrap(),
-            maker_order_id: seq_gen.next().unwrap() as u128,
+            maker_order_id: OrderId(seq_gen.next().unwrap() as u128),
+            taker_order_id: OrderId(seq_gen.next().unwrap() as u128),
             base_size: seq_gen.next().unwrap(),
+            price: seq_gen.next().unwrap(),
         };
         assert_eq!(
             extra_event,
@@ -396,9 +1371,11 @@ mod tests {
                             event: &OutEvent {
                                 tag: EventTag::Out as u8,
                                 side: Side::Ask as u8,
-                                _padding: [0; 14],
+                                version: OutEvent::VERSION,
+                                _padding: [0; 13],
+                                _padding_end: [0; 24],
                                 base_size: seq_gen.next().unwrap(),
-                                order_id: seq_gen.next().unwrap() as u128,
+                                order_id: OrderId(seq_gen.next().unwrap() as u128),
                             },
                             callback_info: &[seq_gen.next().unwrap() as u8; 32]
                         }
@@ -412,10 +1389,13 @@ mod tests {
                             event: &FillEvent {
                                 tag: EventTag::Fill as u8,
                                 taker_side: Side::Ask as u8,
-                                _padding: [0; 6],
+                                version: FillEvent::VERSION,
+                                _padding: [0; 5],
                                 quote_size: seq_gen.next().unwrap(),
-                                maker_order_id: seq_gen.next().unwrap() as u128,
+                                maker_order_id: OrderId(seq_gen.next().unwrap() as u128),
+                                taker_order_id: OrderId(seq_gen.next().unwrap() as u128),
                                 base_size: seq_gen.next().unwrap(),
+                                price: seq_gen.next().unwrap(),
                             },
                             maker_callback_info: &[seq_gen.next().unwrap() as u8; 32],
                             taker_callback_info: &[seq_gen.next().unwrap() as u8; 32]
@@ -423,9 +1403,1674 @@ mod tests {
                     );
                     assert_eq!(EventRef::Fill(e), event_queue.peek_at(i as u64).unwrap());
                 }
+                EventRef::Reject(_) | EventRef::Tick(_) => panic!(),
             }
             number_of_events = i + 1;
         }
         assert_eq!(number_of_events, 100);
     }
+
+    #[test]
+    fn test_event_queue_header_builder() {
+        let header = EventQueueHeaderBuilder::new()
+            .fee_per_event(5_000)
+            .overflow_policy(OverflowPolicy::Log)
+            .build();
+        assert_eq!(header.fee_per_event, 5_000);
+        assert_eq!(header.overflow_policy(), OverflowPolicy::Log);
+        assert_eq!((header.head, header.count, header.seq_num), (0, 0, 0));
+
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let event_queue = EventQueueHeaderBuilder::new()
+            .fee_per_event(5_000)
+            .initialize::<[u8; 32]>(&mut buffer)
+            .unwrap();
+        assert_eq!(event_queue.header.fee_per_event, 5_000);
+        assert_eq!(event_queue.header.overflow_policy(), OverflowPolicy::Reject);
+        assert!(event_queue.is_empty());
+        assert_eq!(buffer[..8], (AccountTag::EventQueue as u64).to_le_bytes());
+
+        // An initialized queue can't be initialized again
+        assert_eq!(
+            EventQueueHeaderBuilder::new()
+                .initialize::<[u8; 32]>(&mut buffer)
+                .err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+
+        // The account must hold at least 5 events and a whole number of them
+        let mut too_small = vec![0; EventQueue::<[u8; 32]>::compute_allocation_size(4)];
+        assert_eq!(
+            EventQueueHeaderBuilder::new()
+                .initialize::<[u8; 32]>(&mut too_small)
+                .err(),
+            Some(ProgramError::AccountDataTooSmall)
+        );
+        let mut misaligned = vec![0; allocation_size + 1];
+        assert_eq!(
+            EventQueueHeaderBuilder::new()
+                .initialize::<[u8; 32]>(&mut misaligned)
+                .err(),
+            Some(AoError::EventSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_event_queue_event_size_mismatch() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        assert_ne!(
+            (allocation_size - 8 - EventQueueHeader::LEN) % EventQueue::<[u8; 16]>::event_size(),
+            0
+        );
+        let mut buffer = vec![0; allocation_size];
+        EventQueueHeaderBuilder::new()
+            .initialize::<[u8; 32]>(&mut buffer)
+            .unwrap();
+
+        // The queue can't be loaded or decoded with another callback info length
+        assert_eq!(
+            EventQueue::<[u8; 16]>::from_buffer(&mut buffer, AccountTag::EventQueue).err(),
+            Some(AoError::EventSizeMismatch.into())
+        );
+        assert!(matches!(
+            iter_events::<[u8; 16]>(&buffer),
+            Err(AoError::EventSizeMismatch)
+        ));
+        assert!(EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).is_ok());
+        assert!(iter_events::<[u8; 32]>(&buffer).is_ok());
+
+        // Nor can it be initialized as such
+        let mut buffer = vec![0; allocation_size];
+        assert_eq!(
+            EventQueueHeaderBuilder::new()
+                .initialize::<[u8; 16]>(&mut buffer)
+                .err(),
+            Some(AoError::EventSizeMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_event_queue_header_layout() {
+        let mut header = EventQueueHeader {
+            head: 1,
+            count: 0x0203,
+            seq_num: 0x0405_0607,
+            fee_per_event: 5_000,
+            accrued_crank_fees: u64::MAX - 1,
+            head_seq_num: 0x0102_0304_0506_0708,
+            overflow_policy: 0,
+            order_id_mode: 0,
+            tail_seq_num: 0x0102_0304_0506_0709,
+            order_id_timestamp: 1_000,
+            order_id_timestamp_count: 2,
+        };
+        header.set_overflow_policy(OverflowPolicy::Overwrite);
+        header.set_order_id_mode(OrderIdMode::Timestamped);
+
+        #[rustfmt::skip]
+        let expected: [u8; EventQueueHeader::LEN] = [
+            1, 0, 0, 0, 0, 0, 0, 0,
+            3, 2, 0, 0, 0, 0, 0, 0,
+            7, 6, 5, 4, 0, 0, 0, 0,
+            0x88, 0x13, 0, 0, 0, 0, 0, 0,
+            0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            8, 7, 6, 5, 4, 3, 2, 1,
+            1, 0, 0, 0, 0, 0, 0, 0,
+            1, 0, 0, 0, 0, 0, 0, 0,
+            9, 7, 6, 5, 4, 3, 2, 1,
+            0xe8, 0x03, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        // The header is read from accounts as is and through borsh by clients, both must yield the same bytes
+        assert_eq!(bytemuck::bytes_of(&header), &expected);
+        assert_eq!(header.try_to_vec().unwrap(), expected);
+        let deserialized = EventQueueHeader::try_from_slice(&expected).unwrap();
+        assert_eq!(deserialized.head_seq_num, header.head_seq_num);
+        assert_eq!(deserialized.tail_seq_num, header.tail_seq_num);
+        assert_eq!(deserialized.overflow_policy(), OverflowPolicy::Overwrite);
+        assert_eq!(deserialized.order_id_mode(), OrderIdMode::Timestamped);
+    }
+
+    #[test]
+    fn test_event_queue_header_in_place() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 5_000;
+        event_queue
+            .header
+            .set_overflow_policy(OverflowPolicy::Overwrite);
+        event_queue.gen_order_id(1 << 32, Side::Bid);
+
+        // Header changes are visible in the account's raw bytes without any explicit write back
+        let header =
+            EventQueueHeader::try_from_slice(&buffer[8..8 + EventQueueHeader::LEN]).unwrap();
+        assert_eq!(header.fee_per_event, 5_000);
+        assert_eq!(header.overflow_policy(), OverflowPolicy::Overwrite);
+        assert_eq!(header.seq_num, 1);
+    }
+
+    #[test]
+    fn test_event_queue_crank_fees() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(100);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 5_000;
+
+        let n = 42;
+        for i in 0..n {
+            let event = OutEvent {
+                tag: EventTag::Out as u8,
+                side: Side::Ask as u8,
+                version: OutEvent::VERSION,
+                _padding: [0; 13],
+                _padding_end: [0; 24],
+                order_id: OrderId(i as u128),
+                base_size: 1,
+            };
+            event_queue.push_back(event, Some(&[0; 32]), None).unwrap();
+        }
+        assert_eq!(event_queue.header.accrued_crank_fees, n * 5_000);
+        assert_eq!(event_queue.owed_crank_fees(n), n * 5_000);
+        assert_eq!(event_queue.owed_crank_fees(n + 10), n * 5_000);
+
+        assert_eq!(event_queue.pop_n(10), 10 * 5_000);
+        assert_eq!(event_queue.pop_n(u64::MAX), (n - 10) * 5_000);
+        assert_eq!(event_queue.header.accrued_crank_fees, 0);
+        assert_eq!(event_queue.pop_n(1), 0);
+    }
+
+    #[test]
+    fn test_event_queue_consume_with_summary() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 5_000;
+        for i in 0..7 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+
+        let mut consumed = vec![];
+        let summary = event_queue.consume_with_summary(4, |e| match e {
+            EventRef::Out(o) => consumed.push((o.event.base_size, *o.callback_info)),
+            _ => panic!(),
+        });
+        assert_eq!(
+            summary,
+            ConsumeSummary {
+                crank_fee: 4 * 5_000,
+                events_consumed: 4,
+                merkle_root: None,
+            }
+        );
+        assert_eq!(
+            consumed,
+            (0..4).map(|i| (i, [i as u8; 32])).collect::<Vec<_>>()
+        );
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.header.head_seq_num, 4);
+
+        // The summary only accounts for the events which were left in the queue
+        let mut remaining = 0;
+        let summary = event_queue.consume_with_summary(u64::MAX, |_| remaining += 1);
+        assert_eq!(remaining, 3);
+        assert_eq!(summary.events_consumed, 3);
+        assert_eq!(summary.crank_fee, 3 * 5_000);
+        assert!(event_queue.is_empty());
+
+        // The crank fee comes first in the register
+        let mut register = [0u8; 17];
+        summary
+            .serialize(&mut (&mut register as &mut [u8]))
+            .unwrap();
+        assert_eq!(register[..8], (3u64 * 5_000).to_le_bytes());
+    }
+
+    #[test]
+    fn test_event_queue_consume_with_merkle_root() {
+        use crate::processor::InstructionOutput;
+        use crate::utils::{
+            merkle_proof, read_register_at, verify_merkle_proof, write_register_output,
+            INSTRUCTION_OUTPUT_SIZE,
+        };
+
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        for i in 0..4 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 10,
+            maker_order_id: OrderId(1),
+            taker_order_id: OrderId(2),
+            base_size: 5,
+            price: 2 << 32,
+        };
+        event_queue
+            .push_back(fill, Some(&[5; 32]), Some(&[6; 32]))
+            .unwrap();
+        event_queue.push_tick(42).unwrap();
+        event_queue
+            .push_back(out_event(7), Some(&[7; 32]), None)
+            .unwrap();
+
+        // The consumer keeps the leaves of the events it expects to be consumed
+        let leaves = event_queue
+            .iter()
+            .take(6)
+            .map(|e| e.merkle_leaf())
+            .collect::<Vec<_>>();
+        let summary = event_queue.consume_with_merkle_root(6);
+        assert_eq!(summary.events_consumed, 6);
+        assert_eq!(event_queue.len(), 1);
+
+        // The root is credited to the register and read back from it
+        let mut register = vec![0u8; INSTRUCTION_OUTPUT_SIZE];
+        let register_before = register.clone();
+        write_register_output(
+            &mut register,
+            &register_before,
+            &InstructionOutput::ConsumeSummary(summary),
+        )
+        .unwrap();
+        let read = read_register_at::<ConsumeSummary>(&register, 0).unwrap();
+        assert_eq!(read, summary);
+        let root = read.merkle_root.unwrap();
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = merkle_proof(&leaves, index).unwrap();
+            assert!(verify_merkle_proof(
+                &root,
+                leaf,
+                index,
+                leaves.len(),
+                &proof
+            ));
+        }
+
+        // The fill's callback infos are committed to along with the event
+        let proof = merkle_proof(&leaves, 4).unwrap();
+        let mut forged_buffer = vec![0; allocation_size];
+        let mut forged_queue =
+            EventQueueTest::from_buffer(&mut forged_buffer, AccountTag::Uninitialized).unwrap();
+        forged_queue
+            .push_back(fill, Some(&[5; 32]), Some(&[9; 32]))
+            .unwrap();
+        let forged_leaf = forged_queue.peek_at(0).unwrap().merkle_leaf();
+        assert!(!verify_merkle_proof(
+            &root,
+            &forged_leaf,
+            4,
+            leaves.len(),
+            &proof
+        ));
+
+        // The event left in the queue isn't part of the crank's tree
+        let remaining_leaf = event_queue.peek_at(0).unwrap().merkle_leaf();
+        assert!(!leaves.contains(&remaining_leaf));
+
+        // Nothing to consume, nothing to commit to
+        let summary = event_queue.consume_with_merkle_root(0);
+        assert_eq!(summary.events_consumed, 0);
+        assert_eq!(summary.merkle_root, None);
+    }
+
+    #[test]
+    fn test_event_queue_consume_until_seq() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let push_events = |event_queue: &mut EventQueueTest, range: std::ops::Range<u64>| {
+            for i in range {
+                let event = OutEvent {
+                    tag: EventTag::Out as u8,
+                    side: Side::Bid as u8,
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
+                    order_id: OrderId(i as u128),
+                    base_size: i,
+                };
+                event_queue.push_back(event, Some(&[0; 32]), None).unwrap();
+            }
+        };
+
+        // Events 0..8 are pushed
+        push_events(&mut event_queue, 0..8);
+        assert_eq!(event_queue.seq_num_at(0), Some(0));
+        assert_eq!(event_queue.seq_num_at(7), Some(7));
+        assert_eq!(event_queue.seq_num_at(8), None);
+
+        event_queue.consume_until_seq(4);
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.header.head_seq_num, 5);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => assert_eq!(o.event.base_size, 5),
+            _ => panic!(),
+        }
+
+        // Consuming up to an already consumed sequence number is a no-op
+        event_queue.consume_until_seq(2);
+        assert_eq!(event_queue.len(), 3);
+
+        // The queue wraps around
+        push_events(&mut event_queue, 8..15);
+        assert_eq!(event_queue.seq_num_at(9), Some(14));
+        event_queue.consume_until_seq(11);
+        assert_eq!(event_queue.len(), 3);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => assert_eq!(o.event.base_size, 12),
+            _ => panic!(),
+        }
+
+        event_queue.consume_until_seq(u64::MAX);
+        assert!(event_queue.is_empty());
+        assert_eq!(event_queue.header.head_seq_num, 15);
+    }
+
+    fn out_event(i: u64) -> OutEvent {
+        OutEvent {
+            tag: EventTag::Out as u8,
+            side: Side::Bid as u8,
+            version: OutEvent::VERSION,
+            _padding: [0; 13],
+            _padding_end: [0; 24],
+            order_id: OrderId(i as u128),
+            base_size: i,
+        }
+    }
+
+    #[test]
+    fn test_event_queue_overflow_reject() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert_eq!(event_queue.header.overflow_policy(), OverflowPolicy::Reject);
+
+        for i in 0..5 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+        assert!(event_queue.full());
+        assert_eq!(
+            event_queue.push_back(out_event(5), None, None).unwrap_err(),
+            out_event(5)
+        );
+        assert_eq!(event_queue.len(), 5);
+        assert_eq!(event_queue.header.head_seq_num, 0);
+    }
+
+    #[test]
+    fn test_event_queue_overflow_overwrite() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue
+            .header
+            .set_overflow_policy(OverflowPolicy::Overwrite);
+
+        for i in 0..8 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        assert!(event_queue.full());
+        assert_eq!(event_queue.header.head_seq_num, 3);
+        let remaining = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                    o.event.base_size
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_event_queue_overflow_log() {
+        use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+        use std::cell::RefCell;
+
+        // The stubs are global, so each test thread records its own logs
+        thread_local! {
+            static LOGGED: RefCell<Vec<Vec<Vec<u8>>>> = const { RefCell::new(Vec::new()) };
+        }
+        struct MockLogger;
+        impl SyscallStubs for MockLogger {
+            fn sol_log_data(&self, fields: &[&[u8]]) {
+                let fields = fields.iter().map(|f| f.to_vec()).collect();
+                LOGGED.with(|logged| logged.borrow_mut().push(fields));
+            }
+        }
+        let _stubs_guard = crate::test_harness::lock_syscall_stubs();
+        let previous_stubs = set_syscall_stubs(Box::new(MockLogger));
+
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(3);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.set_overflow_policy(OverflowPolicy::Log);
+        event_queue.header.fee_per_event = 1;
+
+        for i in 0..5 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 10,
+            maker_order_id: OrderId(1),
+            taker_order_id: OrderId(2),
+            base_size: 5,
+            price: 2 << 32,
+        };
+        event_queue
+            .push_back(fill, Some(&[5; 32]), Some(&[6; 32]))
+            .unwrap();
+        set_syscall_stubs(previous_stubs);
+
+        // The queue keeps its first events, and neither its length nor its crank fees grow past capacity
+        assert_eq!(event_queue.len(), 3);
+        assert_eq!(event_queue.header.accrued_crank_fees, 3);
+        let queued = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => o.event.base_size,
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec![0, 1, 2]);
+
+        // The overflow events are logged with their callback infos instead
+        let logged = LOGGED.with(|logged| logged.take());
+        assert_eq!(logged.len(), 3);
+        for (i, fields) in (3..5).zip(logged.iter()) {
+            let mut event = out_event(i);
+            assert_eq!(fields.len(), 2);
+            assert_eq!(fields[0], bytemuck::bytes_of(&event.to_generic()));
+            assert_eq!(fields[1], [i as u8; 32]);
+        }
+        assert_eq!(
+            logged[2],
+            vec![bytemuck::bytes_of(&fill).to_vec(), vec![5; 32], vec![6; 32]]
+        );
+    }
+
+    #[test]
+    fn test_event_queue_append_from() {
+        let mut small_buffer = vec![0; EventQueueTest::compute_allocation_size(6)];
+        let mut small_queue =
+            EventQueueTest::from_buffer(&mut small_buffer, AccountTag::Uninitialized).unwrap();
+        small_queue.header.fee_per_event = 10;
+        // The pending events wrap around the end of the small queue's buffer
+        for i in 0..5 {
+            small_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        small_queue.pop_n(4);
+        for i in 5..8 {
+            small_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(small_queue.header.head, 4);
+        assert_eq!(small_queue.len(), 4);
+
+        let mut large_buffer = vec![0; EventQueueTest::compute_allocation_size(20)];
+        let mut large_queue =
+            EventQueueTest::from_buffer(&mut large_buffer, AccountTag::Uninitialized).unwrap();
+        large_queue
+            .push_back(out_event(42), Some(&[42; 32]), None)
+            .unwrap();
+        assert_eq!(large_queue.append_from(&small_queue).unwrap(), 4);
+
+        let merged = large_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                    o.event.base_size
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(merged, vec![42, 4, 5, 6, 7]);
+        assert_eq!(large_queue.header.accrued_crank_fees, 4 * 10);
+        assert_eq!(small_queue.len(), 4);
+
+        // Nothing is appended to a queue which can't hold all the events
+        let mut tiny_buffer = vec![0; EventQueueTest::compute_allocation_size(3)];
+        let mut tiny_queue =
+            EventQueueTest::from_buffer(&mut tiny_buffer, AccountTag::Uninitialized).unwrap();
+        tiny_queue.push_back(out_event(0), None, None).unwrap();
+        assert!(matches!(
+            tiny_queue.append_from(&small_queue),
+            Err(AoError::EventQueueFull)
+        ));
+        assert_eq!(tiny_queue.len(), 1);
+    }
+
+    #[test]
+    fn test_event_queue_zero_sized_callback_info() {
+        // A market which doesn't attach any metadata to its orders
+        assert_eq!(
+            EventQueue::<[u8; 0]>::event_size(),
+            FillEvent::LEN + EVENT_SEQ_NUM_LEN + EVENT_TIMESTAMP_LEN + MAKER_REMAINING_LEN
+        );
+        let allocation_size = EventQueue::<[u8; 0]>::compute_allocation_size(6);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueue::<[u8; 0]>::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 10,
+            maker_order_id: OrderId(1),
+            taker_order_id: OrderId(2),
+            base_size: 5,
+            price: 2 << 32,
+        };
+        // The queue wraps around its end
+        for i in 0..5 {
+            event_queue.push_back(fill, Some(&[]), Some(&[])).unwrap();
+            event_queue
+                .push_back(out_event(i), Some(&[]), None)
+                .unwrap();
+            event_queue.pop_n(1);
+        }
+        assert_eq!(event_queue.len(), 5);
+        assert!(event_queue.validate().is_ok());
+
+        let mut consumed = vec![];
+        let summary = event_queue.consume_with_summary(3, |e| consumed.push(e.to_owned()));
+        assert_eq!(summary.events_consumed, 3);
+        assert_eq!(
+            consumed[..2],
+            [
+                OwnedEvent::Out {
+                    event: out_event(2),
+                    callback_info: [],
+                },
+                OwnedEvent::Fill {
+                    event: fill,
+                    maker_callback_info: [],
+                    taker_callback_info: [],
+                },
+            ]
+        );
+
+        // The raw account decodes the same way
+        drop(event_queue);
+        let decoded = iter_events::<[u8; 0]>(&buffer)
+            .unwrap()
+            .map(|e| e.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(
+            decoded[1],
+            OwnedEvent::Out {
+                event: out_event(4),
+                callback_info: [],
+            }
+        );
+
+        let mut event_queue =
+            EventQueue::<[u8; 0]>::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        let summary = event_queue.consume_with_merkle_root(u64::MAX);
+        assert_eq!(summary.events_consumed, 2);
+        assert!(summary.merkle_root.is_some());
+        assert!(event_queue.is_empty());
+    }
+
+    #[test]
+    fn test_event_queue_shrink() {
+        let mut buffer = vec![0; EventQueueTest::compute_allocation_size(20)];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 10;
+        event_queue
+            .header
+            .set_overflow_policy(OverflowPolicy::Overwrite);
+        // The pending events wrap around the end of the buffer
+        for i in 0..23 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+            if i < 19 {
+                event_queue.pop_n(1);
+            }
+        }
+        event_queue.gen_order_id(10 << 32, Side::Bid);
+        assert_eq!(event_queue.header.head, 19);
+        assert_eq!(event_queue.len(), 4);
+
+        // Too small for the pending events
+        let mut tiny_buffer = vec![0; EventQueueTest::compute_allocation_size(3)];
+        assert_eq!(
+            event_queue.shrink(&mut tiny_buffer).err(),
+            Some(AoError::WontFit.into())
+        );
+        assert!(tiny_buffer.iter().all(|&b| b == 0));
+
+        let mut small_buffer = vec![0; EventQueueTest::compute_allocation_size(5)];
+        let small_queue = event_queue.shrink(&mut small_buffer).unwrap();
+        assert_eq!(small_queue.header.head, 0);
+        assert_eq!(small_queue.len(), 4);
+        assert_eq!(small_queue.seq_num_at(0), event_queue.seq_num_at(0));
+        assert_eq!(small_queue.next_seq_num(), event_queue.next_seq_num());
+        assert_eq!(
+            small_queue.header.accrued_crank_fees,
+            event_queue.header.accrued_crank_fees
+        );
+        assert_eq!(small_queue.header.fee_per_event, 10);
+        assert_eq!(
+            small_queue.header.overflow_policy(),
+            OverflowPolicy::Overwrite
+        );
+        let shrunk = small_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                    o.event.base_size
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(shrunk, vec![19, 20, 21, 22]);
+
+        // The new account must be uninitialized
+        assert!(event_queue.shrink(&mut small_buffer).is_err());
+    }
+
+    #[test]
+    fn test_event_queue_seq_num_monotonic() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(100);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let mut order_seqs = vec![];
+        let mut order_ids = vec![];
+        let mut event_seqs = vec![];
+        for i in 0..50u64 {
+            let expected_seq = event_queue.next_seq_num();
+            assert_eq!(event_queue.next_seq_num(), expected_seq);
+            let side = if i % 3 == 0 { Side::Bid } else { Side::Ask };
+            let order_id = event_queue.gen_order_id(42 << 32, side);
+            assert_eq!(order_id.seq(), expected_seq);
+            assert_eq!(order_id.side(), side);
+            order_seqs.push(order_id.seq());
+            order_ids.push(order_id);
+
+            for _ in 0..(i % 3) {
+                event_queue.push_back(out_event(i), None, None).unwrap();
+                event_seqs.push(event_queue.seq_num_at(event_queue.len() - 1).unwrap());
+            }
+        }
+
+        // Order sequence numbers are spaced by exactly one, regardless of the pushed events
+        assert!(order_seqs.windows(2).all(|w| w[1] == w[0] + 1));
+        assert_eq!(event_queue.next_seq_num(), 50);
+        // Bids and asks at the same price get distinct order ids
+        order_ids.sort();
+        order_ids.dedup();
+        assert_eq!(order_ids.len(), 50);
+        // Event sequence numbers are spaced by exactly one, regardless of the generated order ids
+        assert!(event_seqs.windows(2).all(|w| w[1] == w[0] + 1));
+        assert_eq!(event_seqs[0], 0);
+    }
+
+    /// A Fill event as written by the original 48-byte layout
+    #[rustfmt::skip]
+    const V0_FILL: [u8; 48] = [
+        0, 1, 0, 0, 0, 0, 0, 0, // tag, taker_side, padding
+        20, 0, 0, 0, 0, 0, 0, 0, // quote_size
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // maker_order_id
+        2, 0, 0, 0, 0, 0, 0, 0, // base_size
+    ];
+
+    /// An Out event as written by the original 48-byte layout
+    #[rustfmt::skip]
+    const V0_OUT: [u8; 48] = [
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // tag, side, padding
+        3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // order_id
+        4, 0, 0, 0, 0, 0, 0, 0, // base_size
+    ];
+
+    #[test]
+    fn test_event_queue_timestamped_order_ids() {
+        use crate::state::TIMESTAMPED_SEQ_BITS;
+
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue
+            .header
+            .set_order_id_mode(OrderIdMode::Timestamped);
+
+        // The order sequence number crosses a multiple of 2^TIMESTAMPED_SEQ_BITS within the second
+        event_queue.header.seq_num = (1 << TIMESTAMPED_SEQ_BITS) - 2;
+        event_queue.set_timestamp(1_000);
+        let mut asks = vec![];
+        let mut bids = vec![];
+        for _ in 0..4 {
+            asks.push(event_queue.gen_order_id(10 << 32, Side::Ask));
+            bids.push(event_queue.gen_order_id(10 << 32, Side::Bid));
+        }
+        assert_eq!(asks[0].seq(), timestamped_seq(1_000, 0));
+        assert_eq!(bids[3].seq(), timestamped_seq(1_000, 7));
+        assert_eq!(event_queue.next_seq_num(), (1 << TIMESTAMPED_SEQ_BITS) + 6);
+
+        // A clock which goes back doesn't let newer orders jump ahead
+        event_queue.set_timestamp(999);
+        asks.push(event_queue.gen_order_id(10 << 32, Side::Ask));
+        bids.push(event_queue.gen_order_id(10 << 32, Side::Bid));
+        assert_eq!(asks[4].seq(), timestamped_seq(1_000, 8));
+
+        // The rank starts over with each new second
+        event_queue.set_timestamp(1_001);
+        asks.push(event_queue.gen_order_id(10 << 32, Side::Ask));
+        bids.push(event_queue.gen_order_id(10 << 32, Side::Bid));
+        assert_eq!(asks[5].seq(), timestamped_seq(1_001, 0));
+
+        // Asks are matched from the lowest order id up and bids from the highest down, oldest first in both cases
+        assert!(asks.windows(2).all(|w| w[0] < w[1]));
+        assert!(bids.windows(2).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn test_event_queue_fill_versions() {
+        let legacy_fill = FillEvent::from_bytes(&V0_FILL).unwrap();
+        assert_eq!(legacy_fill.version, 0);
+        assert_eq!(legacy_fill.taker_side, Side::Ask as u8);
+        assert_eq!(legacy_fill.quote_size, 20);
+        assert_eq!(legacy_fill.maker_order_id, OrderId(1));
+        assert_eq!(legacy_fill.base_size, 2);
+        assert_eq!(legacy_fill.price(), None);
+        assert_eq!(legacy_fill.taker_order_id(), None);
+
+        let legacy_out = OutEvent::from_bytes(&V0_OUT).unwrap();
+        assert_eq!(legacy_out.version, 0);
+        assert_eq!(legacy_out.side, Side::Bid as u8);
+        assert_eq!(legacy_out.order_id, OrderId(3));
+        assert_eq!(legacy_out.base_size, 4);
+
+        // Truncated, mistagged and unknown version events are rejected
+        assert!(matches!(
+            FillEvent::from_bytes(&V0_FILL[..40]),
+            Err(AoError::InvalidEvent)
+        ));
+        assert!(matches!(
+            FillEvent::from_bytes(&V0_OUT),
+            Err(AoError::InvalidEvent)
+        ));
+        let mut future_fill = V0_FILL;
+        future_fill[2] = FillEvent::VERSION + 1;
+        assert!(matches!(
+            FillEvent::from_bytes(&future_fill),
+            Err(AoError::InvalidEvent)
+        ));
+
+        // Version 1 fills carry the taker order id but no price
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: 1,
+            _padding: [0; 5],
+            quote_size: 20,
+            maker_order_id: OrderId(1),
+            base_size: 2,
+            price: 0,
+            taker_order_id: OrderId(5),
+        };
+        let v1_fill = FillEvent::from_bytes(bytemuck::bytes_of(&fill)).unwrap();
+        assert_eq!(v1_fill.price(), None);
+        assert_eq!(v1_fill.taker_order_id(), Some(OrderId(5)));
+        // Events past version 0 need the full layout
+        assert!(matches!(
+            FillEvent::from_bytes(&bytemuck::bytes_of(&fill)[..48]),
+            Err(AoError::InvalidEvent)
+        ));
+
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let fill = FillEvent {
+            version: FillEvent::VERSION,
+            price: 10 << 32,
+            ..fill
+        };
+        event_queue.push_back(fill, None, None).unwrap();
+        assert_eq!(
+            FillEvent::from_bytes(bytemuck::bytes_of(&event_queue.events[0])).unwrap(),
+            fill
+        );
+        // A legacy event carried over into the queue's buffer as is
+        event_queue.push_back(legacy_fill, None, None).unwrap();
+        event_queue.events[1] = legacy_fill;
+        event_queue.validate().unwrap();
+
+        let fields = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Fill(f) => {
+                    assert_eq!(f.event.quote_size, 20);
+                    assert_eq!(f.event.base_size, 2);
+                    (f.event.price(), f.event.taker_order_id())
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![(Some(10 << 32), Some(OrderId(5))), (None, None)]
+        );
+        assert_eq!(
+            event_queue.peek_at(1).unwrap(),
+            event_queue.iter().nth(1).unwrap()
+        );
+        assert_eq!(FillEvent::LEN, std::mem::size_of::<OutEvent>());
+        assert_eq!(FillEvent::LEN, std::mem::size_of::<RejectEvent>());
+    }
+
+    #[test]
+    fn test_event_queue_taker_order_id() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 30,
+            maker_order_id: OrderId(1),
+            base_size: 3,
+            price: 10 << 32,
+            taker_order_id: OrderId(u128::MAX - 7),
+        };
+        event_queue
+            .push_back(fill, Some(&[1; 32]), Some(&[2; 32]))
+            .unwrap();
+        event_queue
+            .push_back(out_event(5), Some(&[3; 32]), None)
+            .unwrap();
+
+        // The fields which predate the taker order id keep their offsets
+        let fill_bytes = bytemuck::bytes_of(&event_queue.events[0]);
+        assert_eq!(fill_bytes[8..16], 30u64.to_le_bytes());
+        assert_eq!(fill_bytes[16..32], 1u128.to_le_bytes());
+        assert_eq!(fill_bytes[32..40], 3u64.to_le_bytes());
+        assert_eq!(fill_bytes[48..64], (u128::MAX - 7).to_le_bytes());
+        let out_bytes = bytemuck::bytes_of(&event_queue.events[1]);
+        assert_eq!(out_bytes[16..32], 5u128.to_le_bytes());
+
+        match event_queue.iter().next().unwrap() {
+            EventRef::Fill(f) => {
+                assert_eq!(f.event, &fill);
+                assert_eq!(f.event.taker_order_id, OrderId(u128::MAX - 7));
+                assert_eq!(f.maker_callback_info, &[1; 32]);
+                assert_eq!(f.taker_callback_info, &[2; 32]);
+            }
+            _ => panic!(),
+        }
+        match event_queue.iter().nth(1).unwrap() {
+            EventRef::Out(o) => assert_eq!(o.event, &out_event(5)),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "quick-test"))]
+    fn simulate_operations() {
+        use rand::distributions::WeightedIndex;
+        use rand::prelude::*;
+        use std::collections::VecDeque;
+
+        #[derive(Copy, Clone, Debug)]
+        enum Op {
+            Push,
+            PopN(u64),
+        }
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let weights = [
+            (Op::Push, 6),
+            (Op::PopN(1), 2),
+            (Op::PopN(3), 1),
+            (Op::PopN(9), 1),
+        ];
+        let dist = WeightedIndex::new(weights.iter().map(|(_op, wt)| wt)).unwrap();
+
+        for &overflow_policy in &[
+            OverflowPolicy::Reject,
+            OverflowPolicy::Overwrite,
+            OverflowPolicy::Log,
+        ] {
+            // An odd capacity makes sure that the wrap-around happens at every possible head position
+            let capacity = 7;
+            let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(capacity);
+            let mut buffer = vec![0; allocation_size];
+            let mut event_queue =
+                EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+            event_queue.header.set_overflow_policy(overflow_policy);
+            event_queue.header.fee_per_event = 1;
+
+            let mut model = VecDeque::new();
+            let mut popped = 0;
+            let mut next_event = 0u64;
+            for _ in 0..10_000 {
+                let op = weights[dist.sample(&mut rng)].0;
+                match op {
+                    Op::Push => {
+                        let res = event_queue.push_back(
+                            out_event(next_event),
+                            Some(&[next_event as u8; 32]),
+                            None,
+                        );
+                        match (res, overflow_policy) {
+                            (Ok(()), OverflowPolicy::Log) if model.len() == capacity => {}
+                            (Ok(()), _) => {
+                                if model.len() == capacity {
+                                    model.pop_front();
+                                    popped += 1;
+                                }
+                                model.push_back(next_event);
+                            }
+                            (Err(e), OverflowPolicy::Reject) => {
+                                assert_eq!(model.len(), capacity);
+                                assert_eq!(e, out_event(next_event));
+                            }
+                            (Err(_), _) => {
+                                panic!("Pushing should only fail when rejecting")
+                            }
+                        }
+                        next_event += 1;
+                    }
+                    Op::PopN(n) => {
+                        let n = std::cmp::min(n, model.len() as u64);
+                        assert_eq!(event_queue.pop_n(n), n);
+                        model.drain(..n as usize);
+                        popped += n;
+                    }
+                }
+
+                // The queue's bounds are respected
+                assert!(event_queue.header.count as usize <= capacity);
+                assert!((event_queue.header.head as usize) < capacity);
+                assert_eq!(event_queue.full(), model.len() == capacity);
+                assert_eq!(event_queue.header.accrued_crank_fees, model.len() as u64);
+                assert_eq!(event_queue.header.head_seq_num, popped);
+
+                // Events come out in the order in which they were pushed
+                let events = event_queue
+                    .iter()
+                    .map(|e| match e {
+                        EventRef::Out(o) => {
+                            assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                            o.event.base_size
+                        }
+                        _ => panic!("Only Out events are pushed"),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(events, model.iter().copied().collect::<Vec<_>>());
+
+                // The fields which aren't touched by push_back and pop_n are left unchanged
+                assert_eq!(event_queue.next_seq_num(), 0);
+                assert_eq!(event_queue.header.fee_per_event, 1);
+                assert_eq!(event_queue.header.overflow_policy(), overflow_policy);
+            }
+
+            // The account tag which precedes the header is left unchanged
+            assert_eq!(buffer[..8], (AccountTag::EventQueue as u64).to_le_bytes());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "event-timestamps")]
+    fn test_event_queue_timestamps() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        assert_eq!(
+            allocation_size,
+            8 + EventQueueHeader::LEN + 5 * (FillEvent::LEN + 8 + 2 * 32)
+        );
+        let mut buffer = vec![0; allocation_size];
+        {
+            let mut event_queue =
+                EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+            for i in 0..7 {
+                event_queue.set_timestamp(1_000 + i as i64);
+                if event_queue.full() {
+                    event_queue.pop_n(1);
+                }
+                event_queue
+                    .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                    .unwrap();
+            }
+        }
+
+        // The timestamps are read back along with the events they belong to
+        let event_queue = EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!(event_queue.len(), 5);
+        for (index, event) in event_queue.iter().enumerate() {
+            let base_size = match event {
+                EventRef::Out(o) => {
+                    assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                    o.event.base_size
+                }
+                _ => panic!(),
+            };
+            assert_eq!(base_size, index as u64 + 2);
+            assert_eq!(
+                event_queue.timestamp_at(index as u64),
+                Some(1_000 + base_size as i64)
+            );
+        }
+        assert_eq!(event_queue.timestamp_at(5), None);
+    }
+
+    #[test]
+    fn test_event_queue_corrupt_header() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        {
+            let mut event_queue =
+                EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+            for i in 0..3 {
+                event_queue.push_back(out_event(i), None, None).unwrap();
+            }
+        }
+
+        let corrupt = |buffer: &[u8], head: u64, count: u64| {
+            let mut buffer = buffer.to_vec();
+            let header: &mut EventQueueHeader =
+                bytemuck::from_bytes_mut(&mut buffer[8..8 + EventQueueHeader::LEN]);
+            header.head = head;
+            header.count = count;
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).err()
+        };
+        assert_eq!(corrupt(&buffer, 4, 5), None);
+        assert_eq!(corrupt(&buffer, 5, 0), Some(AoError::CorruptQueue.into()));
+        assert_eq!(
+            corrupt(&buffer, u64::MAX, 3),
+            Some(AoError::CorruptQueue.into())
+        );
+        assert_eq!(corrupt(&buffer, 0, 6), Some(AoError::CorruptQueue.into()));
+
+        // The untouched queue can still be read
+        let event_queue = EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!(event_queue.iter().count(), 3);
+    }
+
+    #[test]
+    fn test_event_queue_too_small() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(1);
+        for &len in &[0, 7, 8 + EventQueueHeader::LEN, allocation_size - 1] {
+            let mut buffer = vec![0; len];
+            assert_eq!(
+                EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).err(),
+                Some(AoError::AccountTooSmall.into())
+            );
+        }
+        let mut buffer = vec![0; allocation_size];
+        let event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert_eq!(event_queue.events.len(), 1);
+    }
+
+    #[test]
+    fn test_event_queue_head_wrap() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+
+        let mut next_event = 0;
+        let mut push = |event_queue: &mut EventQueueTest, n: u64| {
+            for _ in 0..n {
+                event_queue
+                    .push_back(out_event(next_event), None, None)
+                    .unwrap();
+                next_event += 1;
+            }
+        };
+        let front = |event_queue: &EventQueueTest| match event_queue.peek_at(0) {
+            Some(EventRef::Out(o)) => Some(o.event.base_size),
+            _ => None,
+        };
+
+        // head and count are both counted in events: pushing leaves head unchanged and popping advances it
+        // by one per event, wrapping around at the capacity
+        push(&mut event_queue, 4);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (0, 4));
+        event_queue.pop_n(1);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (1, 3));
+        assert_eq!(front(&event_queue), Some(1));
+        event_queue.pop_n(2);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (3, 1));
+        push(&mut event_queue, 4);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (3, 5));
+        assert_eq!(event_queue.events[2].base_size, 7);
+        event_queue.pop_n(3);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (1, 2));
+        assert_eq!(front(&event_queue), Some(6));
+
+        // Popping more than count is capped
+        event_queue.pop_n(u64::MAX);
+        assert_eq!((event_queue.header.head, event_queue.header.count), (3, 0));
+        assert_eq!(front(&event_queue), None);
+        assert_eq!(event_queue.header.head_seq_num, 8);
+    }
+
+    #[test]
+    fn test_event_queue_compact() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 10;
+
+        // Wrap the pending events around the end of the circular buffer
+        for i in 0..3 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        event_queue.pop_n(3);
+        for i in 3..7 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+        }
+        assert_eq!(event_queue.header.head, 3);
+
+        event_queue.compact();
+        assert_eq!(event_queue.header.head, 0);
+        assert_eq!(event_queue.len(), 4);
+        assert_eq!(event_queue.header.accrued_crank_fees, 40);
+        assert_eq!(event_queue.next_event_seq_num(), 7);
+        let pending = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => (o.event.base_size, o.callback_info[0]),
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(pending, vec![(3, 3), (4, 4), (5, 5), (6, 6)]);
+
+        // The events are now stored contiguously from the first slot on
+        for slot in 0..4 {
+            match event_ref(event_queue.events, event_queue.callback_infos, slot) {
+                EventRef::Out(o) => assert_eq!(o.event.base_size, slot as u64 + 3),
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_event_queue_lifo() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.header.fee_per_event = 10;
+        let back = |event_queue: &EventQueueTest| match event_queue.peek_back() {
+            Some(EventRef::Out(o)) => Some(o.event.base_size),
+            _ => None,
+        };
+
+        // Wrap the tail around the end of the circular buffer
+        for i in 0..3 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+        event_queue.pop_n(3);
+        for i in 3..8 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+
+        let mut popped = vec![];
+        while let Some(base_size) = back(&event_queue) {
+            assert_eq!(event_queue.pop_back(), 10);
+            popped.push(base_size);
+        }
+        assert_eq!(popped, vec![7, 6, 5, 4, 3]);
+        assert!(event_queue.is_empty());
+        assert_eq!(event_queue.header.accrued_crank_fees, 0);
+        assert_eq!(event_queue.pop_back(), 0);
+
+        // Popping from the back and the front can be mixed
+        for i in 8..11 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+        event_queue.pop_back();
+        event_queue.pop_n(1);
+        assert_eq!(back(&event_queue), Some(9));
+        assert_eq!(event_queue.len(), 1);
+        assert_eq!(event_queue.seq_num_at(0), Some(9));
+        assert_eq!(event_queue.header.head_seq_num, 9);
+
+        // The sequence numbers of the events popped from the back aren't given out again
+        assert_eq!(event_queue.next_event_seq_num(), 11);
+        event_queue.push_back(out_event(11), None, None).unwrap();
+        event_queue.pop_back();
+        event_queue.push_back(out_event(12), None, None).unwrap();
+        event_queue.push_tick(0).unwrap();
+        let seq_nums = (0..event_queue.len() as u64)
+            .map(|index| event_queue.seq_num_at(index).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(seq_nums, vec![9, 12, 13]);
+        match event_queue.peek_back() {
+            Some(EventRef::Tick(t)) => assert_eq!(t.seq_num, 13),
+            _ => panic!(),
+        }
+
+        // Watermarks which fall into a gap pop the events numbered before them
+        event_queue.consume_until_seq(11);
+        assert_eq!(event_queue.len(), 2);
+        assert_eq!(event_queue.header.head_seq_num, 12);
+        event_queue.consume_until_seq(13);
+        assert!(event_queue.is_empty());
+        assert_eq!(event_queue.header.head_seq_num, 14);
+        assert_eq!(event_queue.next_event_seq_num(), 14);
+    }
+
+    #[test]
+    fn test_event_queue_reload() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        {
+            let mut event_queue =
+                EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+            for i in 0..4 {
+                event_queue
+                    .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                    .unwrap();
+            }
+            event_queue.pop_n(3);
+            event_queue
+                .push_back(out_event(4), Some(&[4; 32]), None)
+                .unwrap();
+        }
+
+        // A later instruction picks up the queue where the previous one left it
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!((event_queue.header.head, event_queue.header.count), (3, 2));
+        assert_eq!(event_queue.header.head_seq_num, 3);
+        assert_eq!(event_queue.next_event_seq_num(), 5);
+        assert!(matches!(
+            event_queue.peek_at(1),
+            Some(EventRef::Out(o)) if o.event.base_size == 4 && *o.callback_info == [4; 32]
+        ));
+
+        event_queue.pop_n(1);
+        drop(event_queue);
+        let event_queue = EventQueueTest::from_buffer(&mut buffer, AccountTag::EventQueue).unwrap();
+        assert_eq!((event_queue.header.head, event_queue.header.count), (4, 1));
+    }
+
+    #[test]
+    fn test_event_queue_validate() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        assert!(event_queue.validate().is_ok());
+
+        for i in 0..4 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+        event_queue.pop_n(2);
+        for i in 4..7 {
+            event_queue.push_back(out_event(i), None, None).unwrap();
+        }
+        assert!(event_queue.validate().is_ok());
+
+        let header = *event_queue.header;
+        event_queue.header.head = 5;
+        assert!(matches!(event_queue.validate(), Err(AoError::CorruptQueue)));
+        *event_queue.header = header;
+        event_queue.header.count = 6;
+        assert!(matches!(event_queue.validate(), Err(AoError::CorruptQueue)));
+        *event_queue.header = header;
+
+        // Unknown tags and versions are reported, wherever the event is in the circular buffer
+        event_queue.events[0].tag = 3;
+        assert!(matches!(event_queue.validate(), Err(AoError::InvalidEvent)));
+        event_queue.events[0].tag = EventTag::Out as u8;
+        event_queue.events[4].version = OutEvent::VERSION + 1;
+        assert!(matches!(event_queue.validate(), Err(AoError::InvalidEvent)));
+        event_queue.events[4].version = OutEvent::VERSION;
+        assert!(event_queue.validate().is_ok());
+
+        // Slots which don't hold pending events aren't checked
+        event_queue.pop_n(1);
+        event_queue.events[2].tag = 2;
+        assert!(event_queue.validate().is_ok());
+    }
+
+    #[test]
+    fn test_event_queue_last_fill_price() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let fill = |price| FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Bid as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 0,
+            maker_order_id: OrderId(0),
+            taker_order_id: OrderId(1),
+            base_size: 0,
+            price,
+        };
+
+        event_queue.push_back(fill(10), None, None).unwrap();
+        event_queue.pop_n(1);
+        let first_event_seq_num = event_queue.next_event_seq_num();
+        assert_eq!(first_event_seq_num, 1);
+        assert_eq!(event_queue.last_fill_price(0), None);
+
+        event_queue.push_back(fill(11), None, None).unwrap();
+        event_queue.push_back(fill(12), None, None).unwrap();
+        event_queue.push_back(out_event(0), None, None).unwrap();
+        assert_eq!(event_queue.last_fill_price(first_event_seq_num), Some(12));
+        // Only the events pushed from the given sequence number onwards are considered
+        assert_eq!(event_queue.last_fill_price(3), None);
+        assert_eq!(event_queue.next_event_seq_num(), 4);
+    }
+
+    #[test]
+    fn test_iter_events() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        assert!(matches!(
+            iter_events::<[u8; 32]>(&buffer).err(),
+            Some(AoError::WrongAccountTag)
+        ));
+        assert!(matches!(
+            iter_events::<[u8; 32]>(&buffer[..100]).err(),
+            Some(AoError::AccountTooSmall)
+        ));
+
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        // Wrap around the end of the circular buffer
+        for i in 0..7 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+            if i < 4 {
+                event_queue.pop_n(1);
+            }
+        }
+        assert_eq!(event_queue.header.head, 4);
+
+        let events = iter_events::<[u8; 32]>(&buffer)
+            .unwrap()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                    o.event.base_size
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![4, 5, 6]);
+
+        // The same account data one byte into a larger buffer can't be read in place
+        let mut unaligned = vec![0; allocation_size + 1];
+        unaligned[1..].copy_from_slice(&buffer);
+        assert!(matches!(
+            iter_events::<[u8; 32]>(&unaligned[1..]).err(),
+            Some(AoError::UnalignedAccountData)
+        ));
+
+        // Corrupt events are reported before any event is yielded
+        buffer[8 + EventQueueHeader::LEN + 4 * FillEvent::LEN] = 7;
+        assert!(matches!(
+            iter_events::<[u8; 32]>(&buffer).err(),
+            Some(AoError::InvalidEvent)
+        ));
+    }
+
+    #[test]
+    fn test_iter_events_rev() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        assert!(matches!(
+            iter_events_rev::<[u8; 32]>(&buffer).err(),
+            Some(AoError::WrongAccountTag)
+        ));
+
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        // Wrap around the end of the circular buffer
+        for i in 0..8 {
+            event_queue
+                .push_back(out_event(i), Some(&[i as u8; 32]), None)
+                .unwrap();
+            if i < 3 {
+                event_queue.pop_n(1);
+            }
+        }
+        assert_eq!(event_queue.header.head, 3);
+
+        let base_size = |e: EventRef<'_, [u8; 32]>| match e {
+            EventRef::Out(o) => {
+                assert_eq!(o.callback_info, &[o.event.base_size as u8; 32]);
+                o.event.base_size
+            }
+            _ => panic!(),
+        };
+        let mut forward = iter_events::<[u8; 32]>(&buffer)
+            .unwrap()
+            .map(base_size)
+            .collect::<Vec<_>>();
+        let reverse = iter_events_rev::<[u8; 32]>(&buffer)
+            .unwrap()
+            .map(base_size)
+            .collect::<Vec<_>>();
+        assert_eq!(reverse, vec![7, 6, 5, 4, 3]);
+        forward.reverse();
+        assert_eq!(reverse, forward);
+    }
+
+    #[test]
+    fn test_group_by_maker() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(10);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let fill = |i: u64| FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Bid as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 0,
+            maker_order_id: OrderId(i as u128),
+            taker_order_id: OrderId(100),
+            base_size: i,
+            price: 0,
+        };
+        let (alice, bob, carol) = ([1; 32], [2; 32], [3; 32]);
+
+        event_queue
+            .push_back(fill(0), Some(&alice), Some(&bob))
+            .unwrap();
+        event_queue
+            .push_back(fill(1), Some(&bob), Some(&carol))
+            .unwrap();
+        event_queue
+            .push_back(out_event(2), Some(&alice), None)
+            .unwrap();
+        event_queue
+            .push_back(fill(3), Some(&alice), Some(&carol))
+            .unwrap();
+
+        let groups = group_by_maker(event_queue.iter());
+        let base_sizes = |owner| {
+            groups[&owner]
+                .iter()
+                .map(|e| match e {
+                    EventRef::Fill(f) => f.event.base_size,
+                    EventRef::Out(o) => o.event.base_size,
+                    EventRef::Reject(_) | EventRef::Tick(_) => panic!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(groups.len(), 2);
+        assert_eq!(base_sizes(alice), vec![0, 2, 3]);
+        assert_eq!(base_sizes(bob), vec![1]);
+        // Takers aren't grouped
+        assert!(!groups.contains_key(&carol));
+    }
+
+    #[test]
+    fn test_event_queue_tick() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        event_queue.push_tick(1_000).unwrap();
+        event_queue
+            .push_back(out_event(1), Some(&[1; 32]), None)
+            .unwrap();
+        event_queue.pop_n(1);
+        event_queue.push_tick(1_001).unwrap();
+        assert!(event_queue.validate().is_ok());
+
+        let tick = match event_queue.peek_at(1) {
+            Some(EventRef::Tick(t)) => *t,
+            _ => panic!(),
+        };
+        assert_eq!(tick.tag, EventTag::Tick as u8);
+        assert_eq!(tick.version, TickEvent::VERSION);
+        assert_eq!((tick.timestamp, tick.seq_num), (1_001, 2));
+        assert_eq!(
+            event_queue.peek_at(1).unwrap().to_owned(),
+            OwnedEvent::Tick { event: tick }
+        );
+
+        // Ticks are decoded from the raw account data as well
+        drop(event_queue);
+        let events = iter_events::<[u8; 32]>(&buffer)
+            .unwrap()
+            .map(|e| e.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                OwnedEvent::Out {
+                    event: out_event(1),
+                    callback_info: [1; 32]
+                },
+                OwnedEvent::Tick { event: tick }
+            ]
+        );
+        assert_eq!(std::mem::size_of::<TickEvent>(), FillEvent::LEN);
+    }
+
+    #[test]
+    fn test_event_ref_to_owned() {
+        let allocation_size = EventQueue::<[u8; 32]>::compute_allocation_size(5);
+        let mut buffer = vec![0; allocation_size];
+        let mut event_queue =
+            EventQueueTest::from_buffer(&mut buffer, AccountTag::Uninitialized).unwrap();
+        let fill = FillEvent {
+            tag: EventTag::Fill as u8,
+            taker_side: Side::Ask as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+            quote_size: 20,
+            maker_order_id: OrderId(1),
+            taker_order_id: OrderId(2),
+            base_size: 2,
+            price: 10 << 32,
+        };
+        event_queue
+            .push_back(fill, Some(&[1; 32]), Some(&[2; 32]))
+            .unwrap();
+        event_queue
+            .push_back(out_event(3), Some(&[3; 32]), None)
+            .unwrap();
+
+        let owned = event_queue.iter().map(|e| e.to_owned()).collect::<Vec<_>>();
+        for (event_ref, owned) in event_queue.iter().zip(owned.iter()) {
+            match (event_ref, owned) {
+                (
+                    EventRef::Fill(f),
+                    OwnedEvent::Fill {
+                        event,
+                        maker_callback_info,
+                        taker_callback_info,
+                    },
+                ) => {
+                    assert_eq!(f.event, event);
+                    assert_eq!(f.maker_callback_info, maker_callback_info);
+                    assert_eq!(f.taker_callback_info, taker_callback_info);
+                }
+                (
+                    EventRef::Out(o),
+                    OwnedEvent::Out {
+                        event,
+                        callback_info,
+                    },
+                ) => {
+                    assert_eq!(o.event, event);
+                    assert_eq!(o.callback_info, callback_info);
+                }
+                _ => panic!(),
+            }
+        }
+        assert_eq!(
+            owned,
+            vec![
+                OwnedEvent::Fill {
+                    event: fill,
+                    maker_callback_info: [1; 32],
+                    taker_callback_info: [2; 32],
+                },
+                OwnedEvent::Out {
+                    event: out_event(3),
+                    callback_info: [3; 32],
+                },
+            ]
+        );
+    }
 }
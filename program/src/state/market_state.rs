@@ -1,14 +1,18 @@
 //! The market state struct tracks metadata and security information about the agnostic orderbook system and its
 //! relevant accounts
 use bytemuck::{Pod, Zeroable};
-use solana_program::{entrypoint::ProgramResult, msg, program_error::ProgramError, pubkey::Pubkey};
-use std::mem::size_of;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::{convert::TryInto, mem::size_of};
 
 pub use crate::state::orderbook::{OrderSummary, ORDER_SUMMARY_SIZE};
 #[cfg(feature = "no-entrypoint")]
 pub use crate::utils::get_spread;
 
-use super::AccountTag;
+use super::{critbit::Slab, event_queue::EventQueue, AccountTag, SelfTradeMatch, Side};
+use crate::error::AoError;
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
@@ -24,11 +28,207 @@ pub struct MarketState {
     pub min_base_order_size: u64,
     /// Tick size (FP32)
     pub tick_size: u64,
+    /// The base size of every order must be a multiple of the base lot size.
+    ///
+    /// A value of 0 disables the check.
+    pub base_lot_size: u64,
+    /// The public key of the account which is allowed to change the market's configuration
+    pub authority: Pubkey,
+    /// The account to which the caller program routes the taker fees, see [`MarketState::taker_fee_bps`]
+    pub fee_recipient: Pubkey,
+    /// The maker price of the market's most recent fill (FP32), which new orders' limit prices are checked against.
+    ///
+    /// This is 0 until the first trade, see [`MarketState::last_trade_price`].
+    pub last_trade_price: u64,
+    /// The maximum number of price levels on each side of the book. Orders can always join an existing level.
+    ///
+    /// A value of 0 disables the limit.
+    pub max_levels_per_side: u32,
+    /// The maximum deviation of a new order's limit price from the last trade price, in basis points.
+    ///
+    /// A value of 0 disables the check.
+    pub price_band_bps: u16,
+    /// The market's boolean settings
+    pub flags: MarketFlags,
+    /// The maximum number of resting orders a single owner can have in the orderbook.
+    ///
+    /// A value of 0 disables the limit.
+    pub max_orders_per_owner: u16,
+    /// Share of the price improvement given to the maker when a taker's limit price is better than the maker's
+    /// price, in basis points.
+    ///
+    /// A value of 0 fills orders at the maker's price.
+    pub maker_price_improvement_bps: u16,
+    /// The fee charged to takers on the quote size of each fill, in basis points.
+    ///
+    /// The AOB doesn't move any funds: the fees are reported in [`OrderSummary::total_fee`] and can be computed for
+    /// each fill with [`compute_taker_fee`][`crate::utils::compute_taker_fee`], so that the caller program can charge them and route them to the fee
+    /// recipient.
+    pub taker_fee_bps: u16,
+    pub(crate) self_trade_match_offset: u16,
+    pub(crate) self_trade_match_len: u16,
+    pub(crate) self_trade_match_kind: u8,
+    pub(crate) _padding: [u8; 5],
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
+#[repr(transparent)]
+/// A compact set of boolean market settings, each of which is stored as one bit of a u16.
+///
+/// New settings should be added as a named bit here rather than as a new field of [`MarketState`].
+pub struct MarketFlags(u16);
+
+impl MarketFlags {
+    /// Set when new bids are rejected
+    pub const BIDS_PAUSED: u16 = 1 << 0;
+    /// Set when new asks are rejected
+    pub const ASKS_PAUSED: u16 = 1 << 1;
+    /// Set when each match is logged with `sol_log_data`, see [`OrderBookState::log_matches`]
+    pub const LOG_MATCHES: u16 = 1 << 2;
+    /// Set when the unfilled remainder of an immediate-or-cancel order doesn't push an Out event, see
+    /// [`OrderBookState::fills_only`]
+    pub const FILLS_ONLY: u16 = 1 << 3;
+
+    /// Instantiates a set of flags from its raw bits
+    pub fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// The raw bits of the set of flags
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether all of the given flag bits are set
+    pub fn get(&self, flag: u16) -> bool {
+        self.0 & flag == flag
+    }
+
+    /// Sets the given flag bits
+    pub fn set(&mut self, flag: u16) {
+        self.0 |= flag;
+    }
+
+    /// Clears the given flag bits
+    pub fn clear(&mut self, flag: u16) {
+        self.0 &= !flag;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The sizes in bytes of the four accounts which make up a market, see [`MarketState::compute_account_sizes`].
+///
+/// Each account must be allocated with exactly this size and funded with `Rent::minimum_balance(size)` lamports.
+pub struct MarketAccountSizes {
+    /// The size of the market account
+    pub market: usize,
+    /// The size of the event queue account
+    pub event_queue: usize,
+    /// The size of the bids account
+    pub bids: usize,
+    /// The size of the asks account
+    pub asks: usize,
 }
 
 impl MarketState {
     /// Expected size in bytes of MarketState
     pub const LEN: usize = size_of::<Self>();
+    /// Size in bytes of the explicit encoding of MarketState, see [`MarketState::to_bytes`]
+    pub const SERIALIZED_LEN: usize = 5 * 32 + 4 * 8 + 4 + 7 * 2 + 1;
+
+    /// Encodes the market state with an explicit layout which doesn't depend on the struct's in-memory
+    /// representation, so that it can be parsed from any language.
+    ///
+    /// The fields are written in the following order, with no padding. Integers are little endian, and public
+    /// keys are written as their 32 raw bytes.
+    ///
+    /// | Offset | Size | Field                    |
+    /// |--------|------|--------------------------|
+    /// | 0      | 32   | `event_queue`            |
+    /// | 32     | 32   | `bids`                   |
+    /// | 64     | 32   | `asks`                   |
+    /// | 96     | 8    | `min_base_order_size`    |
+    /// | 104    | 8    | `tick_size`              |
+    /// | 112    | 8    | `base_lot_size`          |
+    /// | 120    | 32   | `authority`              |
+    /// | 152    | 8    | `last_trade_price`       |
+    /// | 160    | 2    | `price_band_bps`         |
+    /// | 162    | 2    | `flags`                  |
+    /// | 164    | 2    | `max_orders_per_owner`   |
+    /// | 166    | 2    | self trade match offset  |
+    /// | 168    | 2    | self trade match length  |
+    /// | 170    | 1    | self trade match kind    |
+    /// | 171    | 2    | `maker_price_improvement_bps` |
+    /// | 173    | 4    | `max_levels_per_side`    |
+    /// | 177    | 32   | `fee_recipient`          |
+    /// | 209    | 2    | `taker_fee_bps`          |
+    ///
+    /// The self trade match kind is 0 for [`SelfTradeMatch::CallbackId`], 1 for [`SelfTradeMatch::Prefix`] and 2 for
+    /// [`SelfTradeMatch::Full`]. The offset and length are only meaningful for [`SelfTradeMatch::Prefix`].
+    pub fn to_bytes(&self) -> [u8; Self::SERIALIZED_LEN] {
+        let fields: [&[u8]; 18] = [
+            self.event_queue.as_ref(),
+            self.bids.as_ref(),
+            self.asks.as_ref(),
+            &self.min_base_order_size.to_le_bytes(),
+            &self.tick_size.to_le_bytes(),
+            &self.base_lot_size.to_le_bytes(),
+            self.authority.as_ref(),
+            &self.last_trade_price.to_le_bytes(),
+            &self.price_band_bps.to_le_bytes(),
+            &self.flags.bits().to_le_bytes(),
+            &self.max_orders_per_owner.to_le_bytes(),
+            &self.self_trade_match_offset.to_le_bytes(),
+            &self.self_trade_match_len.to_le_bytes(),
+            &[self.self_trade_match_kind],
+            &self.maker_price_improvement_bps.to_le_bytes(),
+            &self.max_levels_per_side.to_le_bytes(),
+            self.fee_recipient.as_ref(),
+            &self.taker_fee_bps.to_le_bytes(),
+        ];
+        let mut bytes = [0; Self::SERIALIZED_LEN];
+        let mut offset = 0;
+        for field in fields.iter() {
+            bytes[offset..offset + field.len()].copy_from_slice(field);
+            offset += field.len();
+        }
+        bytes
+    }
+
+    /// Decodes a market state which was encoded by [`MarketState::to_bytes`], or returns `None` if `bytes` doesn't
+    /// have the expected length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SERIALIZED_LEN {
+            return None;
+        }
+        let mut remaining = bytes;
+        let mut take = |len: usize| {
+            let (field, rest) = remaining.split_at(len);
+            remaining = rest;
+            field
+        };
+        Some(Self {
+            event_queue: Pubkey::new_from_array(take(32).try_into().unwrap()),
+            bids: Pubkey::new_from_array(take(32).try_into().unwrap()),
+            asks: Pubkey::new_from_array(take(32).try_into().unwrap()),
+            min_base_order_size: u64::from_le_bytes(take(8).try_into().unwrap()),
+            tick_size: u64::from_le_bytes(take(8).try_into().unwrap()),
+            base_lot_size: u64::from_le_bytes(take(8).try_into().unwrap()),
+            authority: Pubkey::new_from_array(take(32).try_into().unwrap()),
+            last_trade_price: u64::from_le_bytes(take(8).try_into().unwrap()),
+            price_band_bps: u16::from_le_bytes(take(2).try_into().unwrap()),
+            flags: MarketFlags::from_bits(u16::from_le_bytes(take(2).try_into().unwrap())),
+            max_orders_per_owner: u16::from_le_bytes(take(2).try_into().unwrap()),
+            self_trade_match_offset: u16::from_le_bytes(take(2).try_into().unwrap()),
+            self_trade_match_len: u16::from_le_bytes(take(2).try_into().unwrap()),
+            self_trade_match_kind: take(1)[0],
+            maker_price_improvement_bps: u16::from_le_bytes(take(2).try_into().unwrap()),
+            max_levels_per_side: u32::from_le_bytes(take(4).try_into().unwrap()),
+            fee_recipient: Pubkey::new_from_array(take(32).try_into().unwrap()),
+            taker_fee_bps: u16::from_le_bytes(take(2).try_into().unwrap()),
+            _padding: [0; 5],
+        })
+    }
     #[allow(missing_docs)]
     pub fn from_buffer(
         account_data: &mut [u8],
@@ -45,6 +245,15 @@ impl MarketState {
         Ok(bytemuck::from_bytes_mut(data))
     }
 
+    /// Casts a freshly allocated market account's buffer, rejecting accounts which already hold a live market
+    pub fn initialize(account_data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        if account_data[0..8] == (AccountTag::Market as u64).to_le_bytes() {
+            msg!("The market is already initialized!");
+            return Err(AoError::AlreadyInitialized.into());
+        }
+        Self::from_buffer(account_data, AccountTag::Uninitialized)
+    }
+
     #[allow(missing_docs)]
     pub fn check_buffer_size(account_data: &[u8]) -> ProgramResult {
         if account_data.len() != 8 + MarketState::LEN {
@@ -53,6 +262,116 @@ impl MarketState {
         }
         Ok(())
     }
+
+    /// The maker price of the market's most recent fill (FP32), or `None` if the market hasn't traded yet.
+    pub fn last_trade_price(&self) -> Option<u64> {
+        match self.last_trade_price {
+            0 => None,
+            p => Some(p),
+        }
+    }
+
+    /// Computes the sizes of the accounts required to create a market whose event queue holds `event_capacity`
+    /// events and whose bids and asks each hold `order_capacity` orders, for a callback info type `C`.
+    pub fn compute_account_sizes<C>(
+        event_capacity: usize,
+        order_capacity: usize,
+    ) -> MarketAccountSizes {
+        let slab_size = Slab::<C>::compute_allocation_size(order_capacity);
+        MarketAccountSizes {
+            market: 8 + MarketState::LEN,
+            event_queue: EventQueue::<C>::compute_allocation_size(event_capacity),
+            bids: slab_size,
+            asks: slab_size,
+        }
+    }
+
+    /// Updates the last trade price from the Fill events which were pushed to the event queue from
+    /// `first_event_seq_num` onwards, if any.
+    pub fn record_trades<C>(&mut self, event_queue: &EventQueue<'_, C>, first_event_seq_num: u64) {
+        if let Some(price) = event_queue.last_fill_price(first_event_seq_num) {
+            self.last_trade_price = price;
+        }
+    }
+
+    /// Checks that a limit price is within the market's price band around its last trade price.
+    ///
+    /// Any price is accepted while the price band is disabled or before the market's first trade.
+    pub fn check_price_band(&self, limit_price: u64) -> Result<(), AoError> {
+        let reference_price = match self.last_trade_price() {
+            Some(p) if self.price_band_bps != 0 => p,
+            _ => return Ok(()),
+        };
+        let deviation = limit_price.abs_diff(reference_price);
+        let max_deviation = (reference_price as u128) * (self.price_band_bps as u128) / 10_000;
+        if deviation as u128 > max_deviation {
+            msg!("The limit price is outside of the market's price band!");
+            return Err(AoError::PriceOutOfBand);
+        }
+        Ok(())
+    }
+
+    /// Describes how self trading is detected in the market
+    pub fn self_trade_match(&self) -> SelfTradeMatch {
+        match self.self_trade_match_kind {
+            1 => SelfTradeMatch::Prefix {
+                offset: self.self_trade_match_offset,
+                len: self.self_trade_match_len,
+            },
+            2 => SelfTradeMatch::Full,
+            _ => SelfTradeMatch::CallbackId,
+        }
+    }
+
+    /// Sets how self trading is detected in the market
+    pub fn set_self_trade_match(&mut self, self_trade_match: SelfTradeMatch) {
+        let (kind, offset, len) = match self_trade_match {
+            SelfTradeMatch::CallbackId => (0, 0, 0),
+            SelfTradeMatch::Prefix { offset, len } => (1, offset, len),
+            SelfTradeMatch::Full => (2, 0, 0),
+        };
+        self.self_trade_match_kind = kind;
+        self.self_trade_match_offset = offset;
+        self.self_trade_match_len = len;
+    }
+
+    /// Checks that an order's base size is a multiple of the market's base lot size.
+    pub fn check_lot_size(&self, base_qty: u64) -> Result<(), AoError> {
+        if self.base_lot_size != 0 && base_qty % self.base_lot_size != 0 {
+            msg!("The order's base size isn't a multiple of the base lot size!");
+            return Err(AoError::InvalidLotSize);
+        }
+        Ok(())
+    }
+
+    /// Checks that new orders aren't paused on the given side of the book, see [`MarketFlags::BIDS_PAUSED`] and
+    /// [`MarketFlags::ASKS_PAUSED`].
+    pub fn check_side_not_paused(&self, side: Side) -> Result<(), AoError> {
+        let flag = match side {
+            Side::Bid => MarketFlags::BIDS_PAUSED,
+            Side::Ask => MarketFlags::ASKS_PAUSED,
+        };
+        if self.flags.get(flag) {
+            msg!("New orders are paused on this side of the book!");
+            return Err(AoError::SidePaused);
+        }
+        Ok(())
+    }
+
+    /// Checks that the provided account is the market authority and that it has signed the transaction.
+    ///
+    /// All instructions which modify the market's configuration should go through this check.
+    pub fn require_authority(&self, signer: &AccountInfo) -> ProgramResult {
+        if signer.key != &self.authority {
+            msg!("Invalid market authority!");
+            return Err(AoError::WrongMarketAuthority.into());
+        }
+        if !signer.is_signer {
+            msg!("The market authority should be a signer!");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Ok(())
+    }
 }
 
 #[test]
@@ -62,3 +381,252 @@ fn market_cast() {
     assert!(r.is_err());
     assert_eq!(r.unwrap_err(), ProgramError::InvalidAccountData)
 }
+
+#[test]
+fn market_initialize_twice() {
+    let mut buffer = [0u8; MarketState::LEN + 8];
+    let market_state = MarketState::initialize(&mut buffer).unwrap();
+    market_state.tick_size = 1;
+
+    let r = MarketState::initialize(&mut buffer);
+    assert_eq!(r.unwrap_err(), AoError::AlreadyInitialized.into());
+    let market_state = MarketState::from_buffer(&mut buffer, AccountTag::Market).unwrap();
+    assert_eq!(market_state.tick_size, 1);
+}
+
+#[test]
+fn market_require_authority() {
+    let authority = Pubkey::new_unique();
+    let other = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let market_state = MarketState {
+        authority,
+        ..Zeroable::zeroed()
+    };
+
+    let (mut lamports, mut data) = (0, []);
+    let signer = AccountInfo::new(
+        &authority,
+        true,
+        false,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    assert!(market_state.require_authority(&signer).is_ok());
+
+    let (mut lamports, mut data) = (0, []);
+    let non_signer = AccountInfo::new(
+        &authority,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    assert_eq!(
+        market_state.require_authority(&non_signer).unwrap_err(),
+        ProgramError::MissingRequiredSignature
+    );
+
+    let (mut lamports, mut data) = (0, []);
+    let wrong_signer = AccountInfo::new(
+        &other,
+        true,
+        false,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+    assert_eq!(
+        market_state.require_authority(&wrong_signer).unwrap_err(),
+        AoError::WrongMarketAuthority.into()
+    );
+}
+
+#[test]
+fn market_check_price_band() {
+    let mut market_state = MarketState {
+        last_trade_price: 100 << 32,
+        price_band_bps: 500,
+        ..Zeroable::zeroed()
+    };
+    for &price in &[95, 100, 105] {
+        assert!(market_state.check_price_band(price << 32).is_ok());
+    }
+    for &price in &[0, 94, 106, u64::MAX >> 32] {
+        assert!(matches!(
+            market_state.check_price_band(price << 32),
+            Err(AoError::PriceOutOfBand)
+        ));
+    }
+
+    // The band is disabled before the first trade or when set to 0
+    market_state.last_trade_price = 0;
+    assert!(market_state.check_price_band(1 << 32).is_ok());
+    market_state.last_trade_price = 100 << 32;
+    market_state.price_band_bps = 0;
+    assert!(market_state.check_price_band(1 << 32).is_ok());
+}
+
+#[test]
+fn market_flags() {
+    let mut flags = MarketFlags::default();
+    for bit in 0..16 {
+        let flag = 1 << bit;
+        assert!(!flags.get(flag));
+        flags.set(flag);
+        assert!(flags.get(flag));
+        // Setting a flag doesn't touch the other bits
+        assert_eq!(flags.bits(), flag);
+        flags.clear(flag);
+        assert!(!flags.get(flag));
+        assert_eq!(flags.bits(), 0);
+    }
+
+    flags.set(0b101);
+    assert!(flags.get(0b1) && flags.get(0b100) && flags.get(0b101));
+    assert!(!flags.get(0b10) && !flags.get(0b11));
+    flags.clear(0b1);
+    assert_eq!(flags, MarketFlags::from_bits(0b100));
+
+    // The flags are stored compactly within the market state
+    let market_state = MarketState {
+        flags: MarketFlags::from_bits(0xa5c3),
+        ..Zeroable::zeroed()
+    };
+    let bytes = bytemuck::bytes_of(&market_state);
+    let offset = MarketState::LEN - 5 - 1 - 2 - 2 - 2 - 2 - 2 - 2;
+    assert_eq!(&bytes[offset..offset + 2], &0xa5c3u16.to_le_bytes());
+}
+
+#[test]
+fn market_compute_account_sizes() {
+    type C = [u8; 32];
+    for &(event_capacity, order_capacity) in &[(5, 1), (10, 100), (1000, 10_000)] {
+        let sizes = MarketState::compute_account_sizes::<C>(event_capacity, order_capacity);
+        assert_eq!(sizes.bids, sizes.asks);
+
+        assert!(MarketState::check_buffer_size(&vec![0; sizes.market]).is_ok());
+        assert!(MarketState::check_buffer_size(&vec![0; sizes.market + 1]).is_err());
+
+        assert!(EventQueue::<C>::check_buffer_size(&vec![0; sizes.event_queue]).is_ok());
+        assert_eq!(
+            sizes.event_queue,
+            EventQueue::<C>::compute_allocation_size(1)
+                + (event_capacity - 1) * EventQueue::<C>::event_size()
+        );
+
+        let mut buf = vec![0; sizes.bids];
+        buf[0] = AccountTag::Bids as u8;
+        let slab = Slab::<C>::from_buffer(&mut buf, AccountTag::Bids).unwrap();
+        assert_eq!(slab.leaf_nodes.len(), order_capacity);
+        assert_eq!(slab.callback_infos.len(), order_capacity);
+    }
+}
+
+#[test]
+fn market_check_side_not_paused() {
+    let mut market_state: MarketState = Zeroable::zeroed();
+    assert!(market_state.check_side_not_paused(Side::Bid).is_ok());
+    assert!(market_state.check_side_not_paused(Side::Ask).is_ok());
+
+    market_state.flags.set(MarketFlags::BIDS_PAUSED);
+    assert!(matches!(
+        market_state.check_side_not_paused(Side::Bid),
+        Err(AoError::SidePaused)
+    ));
+    assert!(market_state.check_side_not_paused(Side::Ask).is_ok());
+
+    market_state.flags.set(MarketFlags::ASKS_PAUSED);
+    market_state.flags.clear(MarketFlags::BIDS_PAUSED);
+    assert!(market_state.check_side_not_paused(Side::Bid).is_ok());
+    assert!(matches!(
+        market_state.check_side_not_paused(Side::Ask),
+        Err(AoError::SidePaused)
+    ));
+}
+
+#[test]
+fn market_check_lot_size() {
+    let mut market_state: MarketState = Zeroable::zeroed();
+    assert!(market_state.check_lot_size(7).is_ok());
+
+    market_state.base_lot_size = 100;
+    for &base_qty in &[0, 100, 300, 1_000_000] {
+        assert!(market_state.check_lot_size(base_qty).is_ok());
+    }
+    for &base_qty in &[1, 99, 101, 350] {
+        assert!(matches!(
+            market_state.check_lot_size(base_qty),
+            Err(AoError::InvalidLotSize)
+        ));
+    }
+}
+
+#[test]
+fn market_self_trade_match() {
+    let mut market_state: MarketState = Zeroable::zeroed();
+    assert_eq!(market_state.self_trade_match(), SelfTradeMatch::CallbackId);
+    for self_trade_match in [
+        SelfTradeMatch::Prefix { offset: 3, len: 7 },
+        SelfTradeMatch::Full,
+        SelfTradeMatch::CallbackId,
+    ] {
+        market_state.set_self_trade_match(self_trade_match);
+        assert_eq!(market_state.self_trade_match(), self_trade_match);
+    }
+}
+
+#[test]
+fn market_explicit_bytes() {
+    let mut market_state = MarketState {
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        min_base_order_size: 1,
+        tick_size: 2 << 32,
+        base_lot_size: 3,
+        authority: Pubkey::new_unique(),
+        last_trade_price: 4 << 32,
+        price_band_bps: 5,
+        flags: MarketFlags::from_bits(MarketFlags::ASKS_PAUSED),
+        max_orders_per_owner: 6,
+        maker_price_improvement_bps: 9,
+        max_levels_per_side: 10,
+        fee_recipient: Pubkey::new_unique(),
+        taker_fee_bps: 11,
+        ..Zeroable::zeroed()
+    };
+    market_state.set_self_trade_match(SelfTradeMatch::Prefix { offset: 7, len: 8 });
+
+    let bytes = market_state.to_bytes();
+    assert_eq!(&bytes[32..64], market_state.bids.as_ref());
+    assert_eq!(&bytes[104..112], &(2u64 << 32).to_le_bytes());
+    assert_eq!(&bytes[120..152], market_state.authority.as_ref());
+    assert_eq!(&bytes[162..164], &MarketFlags::ASKS_PAUSED.to_le_bytes());
+    assert_eq!(bytes[170], 1);
+    assert_eq!(&bytes[171..173], &9u16.to_le_bytes());
+    assert_eq!(&bytes[173..177], &10u32.to_le_bytes());
+    assert_eq!(&bytes[177..209], market_state.fee_recipient.as_ref());
+    assert_eq!(&bytes[209..211], &11u16.to_le_bytes());
+
+    let decoded = MarketState::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        bytemuck::bytes_of(&decoded),
+        bytemuck::bytes_of(&market_state)
+    );
+    assert_eq!(
+        decoded.self_trade_match(),
+        SelfTradeMatch::Prefix { offset: 7, len: 8 }
+    );
+
+    assert!(MarketState::from_bytes(&bytes[1..]).is_none());
+    assert!(MarketState::from_bytes(&[0; MarketState::SERIALIZED_LEN + 1]).is_none());
+}
@@ -5,17 +5,24 @@
 //! object, in order to benefit from the AOB's included security checks.
 use crate::{
     error::AoError,
-    processor::new_order,
+    processor::{new_order, post_orders_batch},
     state::{
-        critbit::{LeafNode, NodeHandle, Slab},
-        event_queue::{EventQueue, EventTag, FillEvent, OutEvent},
-        AccountTag, SelfTradeBehavior, Side,
+        critbit::{LeafNode, NodeHandle, RemovedOrder, Slab},
+        event_queue::{EventQueue, EventTag, FillEvent, OutEvent, RejectEvent, RejectReason},
+        AccountTag, OrderId, PostOnly, SelfTradeBehavior, SelfTradeMatch, Side,
     },
+    utils::{compute_fill_price, compute_quote_size, compute_taker_fee},
 };
-use bonfida_utils::fp_math::{fp32_div, fp32_mul_ceil, fp32_mul_floor};
+use bonfida_utils::fp_math::fp32_div;
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::Pod;
-use solana_program::{msg, program_error::ProgramError};
+use solana_program::{
+    hash::{hashv, Hasher},
+    log::sol_log_data,
+    msg,
+    program_error::ProgramError,
+};
+use std::{collections::HashMap, convert::TryFrom};
 
 /// This struct is written back into the event queue's register after new_order or cancel_order.
 ///
@@ -23,16 +30,20 @@ use solana_program::{msg, program_error::ProgramError};
 /// were either matched against other orders or written into the orderbook.
 ///
 /// In the case of an order cancellation, the quantities describe what was left of the order in the orderbook.
-#[derive(Debug, BorshDeserialize, BorshSerialize)]
+#[derive(Debug, PartialEq, BorshDeserialize, BorshSerialize)]
 pub struct OrderSummary {
     /// When applicable, the order id of the newly created order.
-    pub posted_order_id: Option<u128>,
+    pub posted_order_id: Option<OrderId>,
     #[allow(missing_docs)]
     pub total_base_qty: u64,
     #[allow(missing_docs)]
     pub total_quote_qty: u64,
     #[allow(missing_docs)]
     pub total_base_qty_posted: u64,
+    /// The taker fee owed for the order's fills, in quote units.
+    ///
+    /// This is the sum of the fees of each fill, see [`compute_taker_fee`], and is 0 for order cancellations.
+    pub total_fee: u64,
 }
 
 /// This trait defines a subobject which can be used to compare two callback information object to determine
@@ -54,12 +65,110 @@ impl CallbackInfo for [u8; 32] {
 }
 
 /// The serialized size of an OrderSummary object.
-pub const ORDER_SUMMARY_SIZE: u32 = 41;
+pub const ORDER_SUMMARY_SIZE: u32 = 49;
+
+/// The aggregated resting orders at one price of the orderbook.
+#[derive(Debug, Clone, Copy, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Level {
+    /// The price of the level (FP32)
+    pub price: u64,
+    /// The total base quantity of the orders resting at this price
+    pub base_quantity: u64,
+    /// The number of orders resting at this price
+    pub order_count: u64,
+}
+
+/// The estimated outcome of a taker order, as returned by [`OrderBookState::simulate_taker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillPreview {
+    /// The base quantity which would be filled
+    pub filled_base: u64,
+    /// The quote quantity which would be exchanged for the filled base quantity
+    pub filled_quote: u64,
+    /// The volume-weighted average fill price (FP32), rounded down, or `None` if nothing would be filled
+    pub avg_price: Option<u64>,
+    /// The base quantity which would be left unmatched
+    pub remaining: u64,
+}
+
+/// A view of both sides of the orderbook, as returned by [`OrderBookState::snapshot`].
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct BookSnapshot {
+    /// The bid levels, by descending price
+    pub bids: Vec<Level>,
+    /// The ask levels, by ascending price
+    pub asks: Vec<Level>,
+    /// The event queue's next order sequence number at the time of the snapshot.
+    ///
+    /// Since every new order consumes a sequence number, clients can compare it to detect stale snapshots.
+    pub seq_num: u64,
+}
+
+/// An index of the resting orders by owner, where the owner of an order is a range of bytes of its callback info,
+/// such as the prefix compared by [`SelfTradeMatch::Prefix`].
+///
+/// Once enabled with [`OrderBookState::enable_owner_index`], the index is kept up to date as orders are posted,
+/// filled and removed through the [`OrderBookState`], so that per-owner queries don't have to scan both slabs.
+/// Building it takes a full scan of the book and heap storage for every resting order, which is why it is opt-in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnerIndex {
+    offset: usize,
+    len: usize,
+    orders: HashMap<Vec<u8>, Vec<OrderId>>,
+}
+
+impl OwnerIndex {
+    fn owner<'c, C: Pod>(&self, callback_info: &'c C) -> &'c [u8] {
+        &bytemuck::bytes_of(callback_info)[self.offset..self.offset + self.len]
+    }
+
+    fn insert<C: Pod>(&mut self, callback_info: &C, order_id: OrderId) {
+        let owner = self.owner(callback_info).to_vec();
+        self.orders.entry(owner).or_default().push(order_id);
+    }
+
+    fn remove<C: Pod>(&mut self, callback_info: &C, order_id: OrderId) {
+        let owner = self.owner(callback_info);
+        if let Some(order_ids) = self.orders.get_mut(owner) {
+            order_ids.retain(|&id| id != order_id);
+            if order_ids.is_empty() {
+                self.orders.remove(owner);
+            }
+        }
+    }
+
+    /// The ids of the resting orders of `owner`, across both sides of the book and in no particular order
+    pub fn orders(&self, owner: &[u8]) -> &[OrderId] {
+        self.orders.get(owner).map_or(&[], |order_ids| order_ids)
+    }
+}
 
 #[doc(hidden)]
 pub struct OrderBookState<'a, C> {
     pub bids: Slab<'a, C>,
     pub asks: Slab<'a, C>,
+    /// Describes how self trading is detected when matching new orders
+    pub self_trade_match: SelfTradeMatch,
+    /// Share of the price improvement given to the maker when a taker's limit price is better than the maker's
+    /// price, in basis points
+    pub maker_price_improvement_bps: u16,
+    /// When set, each match is logged with `sol_log_data` so that indexers can reconstruct trades from the
+    /// transaction logs.
+    ///
+    /// The logged fields are the maker order id (16 bytes), the fill price (FP32) and the filled base size, all
+    /// little-endian.
+    pub log_matches: bool,
+    /// The fee charged to takers on the quote size of each fill, in basis points
+    pub taker_fee_bps: u16,
+    /// When set, the unfilled remainder of an immediate-or-cancel taker order doesn't push an Out event, so that
+    /// matching only pushes Fill events.
+    ///
+    /// This is meant for caller programs which settle the taker's remainder synchronously, from the instruction's
+    /// [`OrderSummary`]. Resting orders removed on their owner's behalf, such as expired, self-trading or booted
+    /// makers, are reported nowhere else and always push an Out event.
+    pub fills_only: bool,
+    /// The index of the resting orders by owner, if enabled, see [`OrderBookState::enable_owner_index`]
+    pub owner_index: Option<OwnerIndex>,
 }
 
 // pub type OrderBookStateRef<'slab, C> = OrderBookState<Slab<'slab, C>>;
@@ -71,7 +180,67 @@ impl<'slab, C: Pod + Copy> OrderBookState<'slab, C> {
     ) -> Result<Self, ProgramError> {
         let bids = Slab::from_buffer(bids_account, AccountTag::Bids)?;
         let asks = Slab::from_buffer(asks_account, AccountTag::Asks)?;
-        Ok(Self { bids, asks })
+        Ok(Self {
+            bids,
+            asks,
+            self_trade_match: SelfTradeMatch::CallbackId,
+            maker_price_improvement_bps: 0,
+            log_matches: false,
+            taker_fee_bps: 0,
+            fills_only: false,
+            owner_index: None,
+        })
+    }
+
+    /// Builds the index of the resting orders by owner, where the owner of an order is the `len` bytes of its
+    /// callback info starting at `offset`. Fails with [`AoError::InvalidCallbackInfo`] if this range isn't
+    /// within the callback info.
+    ///
+    /// From then on, the index is maintained as orders are posted and removed through this object, see
+    /// [`OrderBookState::orders_by_owner`].
+    pub fn enable_owner_index(&mut self, offset: usize, len: usize) -> Result<(), AoError> {
+        if len == 0 || offset + len > std::mem::size_of::<C>() {
+            return Err(AoError::InvalidCallbackInfo);
+        }
+        let mut owner_index = OwnerIndex {
+            offset,
+            len,
+            orders: HashMap::new(),
+        };
+        for slab in [&self.bids, &self.asks].iter() {
+            for (leaf, callback_info) in slab.iter(true) {
+                owner_index.insert(callback_info, leaf.order_id());
+            }
+        }
+        self.owner_index = Some(owner_index);
+        Ok(())
+    }
+
+    /// The ids of the resting orders of `owner` across both sides of the book, in no particular order, or `None`
+    /// if the owner index isn't enabled.
+    pub fn orders_by_owner(&self, owner: &[u8]) -> Option<&[OrderId]> {
+        self.owner_index.as_ref().map(|index| index.orders(owner))
+    }
+
+    /// Inserts an order into its side of the book along with its callback info, keeping the owner index up to date
+    fn insert_order(&mut self, leaf: &LeafNode, callback_info: C) -> Result<NodeHandle, AoError> {
+        let order_id = leaf.order_id();
+        let slab = self.get_tree(order_id.side());
+        let h = slab.insert_leaf(leaf)?;
+        *slab.get_callback_info_mut(h) = callback_info;
+        if let Some(owner_index) = &mut self.owner_index {
+            owner_index.insert(&callback_info, order_id);
+        }
+        Ok(h)
+    }
+
+    /// Removes an order from its side of the book, keeping the owner index up to date
+    fn remove_order(&mut self, order_id: OrderId) -> Option<RemovedOrder<C>> {
+        let removed = self.get_tree(order_id.side()).remove(order_id)?;
+        if let Some(owner_index) = &mut self.owner_index {
+            owner_index.remove(&removed.callback_info, order_id);
+        }
+        Some(removed)
     }
 }
 
@@ -95,6 +264,57 @@ impl<'a, C> OrderBookState<'a, C> {
         (best_bid_price, best_ask_price)
     }
 
+    /// Returns the best bid and ask prices, or `None` if either side is empty or if the book is crossed.
+    fn best_prices(&self) -> Option<(u64, u64)> {
+        match self.get_spread() {
+            (Some(best_bid_price), Some(best_ask_price)) if best_bid_price < best_ask_price => {
+                Some((best_bid_price, best_ask_price))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the difference between the best ask and best bid prices (FP32).
+    ///
+    /// This is `None` if either side of the book is empty, or if the book is crossed.
+    pub fn spread(&self) -> Option<u64> {
+        self.best_prices()
+            .map(|(best_bid_price, best_ask_price)| best_ask_price - best_bid_price)
+    }
+
+    /// Returns the price halfway between the best bid and best ask prices (FP32), rounded down.
+    ///
+    /// This is `None` if either side of the book is empty, or if the book is crossed.
+    pub fn mid_price(&self) -> Option<u64> {
+        self.best_prices().map(|(best_bid_price, best_ask_price)| {
+            best_bid_price + (best_ask_price - best_bid_price) / 2
+        })
+    }
+
+    /// Returns the spread in basis points of the mid price, rounded down, see [`OrderBookState::spread`] and
+    /// [`OrderBookState::mid_price`].
+    ///
+    /// This is `None` if either side of the book is empty, if the book is crossed, or if the mid price rounds down
+    /// to 0. Spreads too wide to be represented saturate to `u16::MAX`, although the mid price being at least a third
+    /// of the spread bounds the value by 30_000.
+    pub fn spread_bps(&self) -> Option<u16> {
+        let (spread, mid_price) = (self.spread()?, self.mid_price()?);
+        if mid_price == 0 {
+            return None;
+        }
+        Some(u16::try_from(spread as u128 * 10_000 / mid_price as u128).unwrap_or(u16::MAX))
+    }
+
+    /// Checks that no resting bid is priced at or above a resting ask, which always holds after a proper match.
+    pub fn assert_not_crossed(&self) -> Result<(), AoError> {
+        match self.get_spread() {
+            (Some(best_bid_price), Some(best_ask_price)) if best_bid_price >= best_ask_price => {
+                Err(AoError::BookCrossed)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn get_tree(&mut self, side: Side) -> &mut Slab<'a, C> {
         match side {
             Side::Bid => &mut self.bids,
@@ -105,37 +325,547 @@ impl<'a, C> OrderBookState<'a, C> {
     pub fn is_empty(&self) -> bool {
         self.asks.header.leaf_count == 0 && self.bids.header.leaf_count == 0
     }
+
+    /// Iterates over the resting orders of one side of the book in price-time priority, yielding
+    /// `(order_id, price, base_size, callback_info)` for each order.
+    ///
+    /// Bids are walked by descending price and asks by ascending price, with older orders first within a price level.
+    pub fn iter_orders(&self, side: Side) -> impl Iterator<Item = (OrderId, u64, u64, &C)> + '_ {
+        let slab_iter = match side {
+            Side::Bid => self.bids.iter(false),
+            Side::Ask => self.asks.iter(true),
+        };
+        slab_iter.map(|(leaf, callback_info)| {
+            (
+                leaf.order_id(),
+                leaf.price(),
+                leaf.base_quantity,
+                callback_info,
+            )
+        })
+    }
+
+    /// Returns the limit price at which a [`PostOnly::Slide`] order can be posted without crossing the spread.
+    ///
+    /// A crossing bid is moved to one tick below the best ask, and a crossing ask to one tick above the best bid.
+    /// Other limit prices are returned unchanged.
+    pub fn slide_limit_price(&self, side: Side, limit_price: u64, tick_size: u64) -> u64 {
+        let (best_bid_price, best_ask_price) = self.get_spread();
+        match (side, best_bid_price, best_ask_price) {
+            (Side::Bid, _, Some(best_ask_price)) if limit_price >= best_ask_price => {
+                best_ask_price.saturating_sub(tick_size)
+            }
+            (Side::Ask, Some(best_bid_price), _) if limit_price <= best_bid_price => {
+                best_bid_price.saturating_add(tick_size)
+            }
+            _ => limit_price,
+        }
+    }
+
+    /// Aggregates the resting orders of one side of the book into price levels, best price first.
+    pub fn levels(&self, side: Side) -> Vec<Level> {
+        let mut levels: Vec<Level> = Vec::new();
+        for (_, price, base_quantity, _) in self.iter_orders(side) {
+            match levels.last_mut() {
+                Some(level) if level.price == price => {
+                    level.base_quantity = level.base_quantity.saturating_add(base_quantity);
+                    level.order_count += 1;
+                }
+                _ => levels.push(Level {
+                    price,
+                    base_quantity,
+                    order_count: 1,
+                }),
+            }
+        }
+        levels
+    }
+
+    /// Returns the total base quantity of the orders resting at `price` on one side of the book, saturating at
+    /// `u64::MAX`.
+    ///
+    /// Unlike [`OrderBookState::levels`], this reads the quantities cached in the slab's inner nodes and doesn't
+    /// visit the orders.
+    pub fn level_base_quantity(&self, side: Side, price: u64) -> u64 {
+        let slab = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        u64::try_from(slab.level_base_quantity(price)).unwrap_or(u64::MAX)
+    }
+
+    /// Checks whether any order rests at `price` on one side of the book, descending the slab to the price without
+    /// visiting the orders.
+    pub fn has_level(&self, side: Side, price: u64) -> bool {
+        let slab = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        slab.find_level(price).is_some()
+    }
+
+    /// Returns the number of orders resting at `price` on one side of the book, only visiting the orders of that
+    /// level. The level's base quantity is given by [`OrderBookState::level_base_quantity`].
+    pub fn level_size(&self, side: Side, price: u64) -> u64 {
+        let slab = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        slab.level_order_count(price)
+    }
+
+    /// Returns the total base quantity of the orders on one side of the book at `price` or better, that is the bids
+    /// at or above `price` or the asks at or below it, saturating at `u64::MAX`.
+    ///
+    /// Like [`OrderBookState::level_base_quantity`], this doesn't visit the orders.
+    pub fn cumulative_depth(&self, side: Side, price: u64) -> u64 {
+        let depth = match side {
+            Side::Bid => {
+                self.bids.total_base_quantity()
+                    - self.bids.base_quantity_below(Some((price as u128) << 64))
+            }
+            Side::Ask => self
+                .asks
+                .base_quantity_below(price.checked_add(1).map(|p| (p as u128) << 64)),
+        };
+        u64::try_from(depth).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the volume-weighted average price (FP32) at which `depth_base` base units would be filled against one
+    /// side of the book, best price first, rounded down.
+    ///
+    /// Returns `None` if `depth_base` is 0 or if the side holds less than `depth_base` base units.
+    pub fn vwap(&self, side: Side, depth_base: u64) -> Option<u64> {
+        if depth_base == 0 {
+            return None;
+        }
+        let mut base_remaining = depth_base;
+        // The filled base sizes add up to at most depth_base, so the sum is below 2^64 * 2^64 and can't overflow
+        let mut weighted_price_sum = 0u128;
+        for (_, price, base_quantity, _) in self.iter_orders(side) {
+            let base_filled = base_quantity.min(base_remaining);
+            weighted_price_sum += (base_filled as u128) * (price as u128);
+            base_remaining -= base_filled;
+            if base_remaining == 0 {
+                return Some((weighted_price_sum / (depth_base as u128)) as u64);
+            }
+        }
+        None
+    }
+
+    /// Previews the outcome of a taker order of `size` base units at `limit_price` (FP32) against the current
+    /// book, without modifying it.
+    ///
+    /// The resting orders are matched in price-time priority, with the same crossing rule and fill price as
+    /// [`OrderBookState::new_order`]. Expired orders, self trading and the match limit aren't taken into account.
+    pub fn simulate_taker(
+        &self,
+        side: Side,
+        size: u64,
+        limit_price: u64,
+    ) -> Result<FillPreview, AoError> {
+        let mut preview = FillPreview {
+            filled_base: 0,
+            filled_quote: 0,
+            avg_price: None,
+            remaining: size,
+        };
+        // The filled base sizes add up to at most size, so the sum is below 2^64 * 2^64 and can't overflow
+        let mut weighted_price_sum = 0u128;
+        for (_, maker_price, base_quantity, _) in self.iter_orders(side.opposite()) {
+            if preview.remaining == 0 || !crosses(side, limit_price, maker_price) {
+                break;
+            }
+            let trade_price = compute_fill_price(
+                maker_price,
+                limit_price,
+                side,
+                self.maker_price_improvement_bps,
+            );
+            let base_trade_qty = base_quantity.min(preview.remaining);
+            preview.filled_quote = preview
+                .filled_quote
+                .checked_add(compute_quote_size(base_trade_qty, trade_price, side)?)
+                .ok_or(AoError::QuoteOverflow)?;
+            weighted_price_sum += (base_trade_qty as u128) * (trade_price as u128);
+            preview.filled_base += base_trade_qty;
+            preview.remaining -= base_trade_qty;
+        }
+        if preview.filled_base != 0 {
+            preview.avg_price = Some((weighted_price_sum / (preview.filled_base as u128)) as u64);
+        }
+        Ok(preview)
+    }
+
+    /// Checks that the freshly posted orders `new_order_ids` didn't open new price levels on one side of the book
+    /// beyond `max_levels`, failing with [`AoError::TooManyLevels`] otherwise. A `max_levels` of 0 disables the check.
+    ///
+    /// A level opened by the new orders is one which holds no other order. Orders joining an existing level are
+    /// always accepted, even when the side already holds more than `max_levels` levels.
+    pub fn check_new_levels(
+        &self,
+        side: Side,
+        new_order_ids: &[OrderId],
+        max_levels: u32,
+    ) -> Result<(), AoError> {
+        if max_levels == 0 {
+            return Ok(());
+        }
+        let mut level_count = 0;
+        let mut level_price = None;
+        let mut level_is_new = false;
+        let mut opened_level = false;
+        for (order_id, price, _, _) in self.iter_orders(side) {
+            if level_price != Some(price) {
+                opened_level |= level_is_new;
+                level_count += 1;
+                level_price = Some(price);
+                level_is_new = true;
+            }
+            level_is_new &= new_order_ids.contains(&order_id);
+        }
+        opened_level |= level_is_new;
+        if opened_level && level_count > max_levels {
+            return Err(AoError::TooManyLevels);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of resting orders which are ahead of an order in time priority at its price level, or
+    /// `None` if the order isn't in the book.
+    ///
+    /// The orders of the better priced levels are walked through as well, so this costs a traversal of the book up
+    /// to the order's level.
+    pub fn order_rank(&self, order_id: OrderId) -> Option<u32> {
+        let price = order_id.price();
+        let rank = self
+            .iter_orders(order_id.side())
+            .skip_while(|&(_, p, _, _)| p != price)
+            .take_while(|&(_, p, _, _)| p == price)
+            .position(|(id, _, _, _)| id == order_id)?;
+        Some(rank as u32)
+    }
+
+    /// Removes the order with the given id from the book, and returns its data. Fails with
+    /// [`AoError::OrderNotFound`] if the order isn't in the book.
+    ///
+    /// Like the cancel_order instruction, this doesn't push an Out event to the event queue.
+    pub fn cancel_order(&mut self, order_id: OrderId) -> Result<RemovedOrder<C>, AoError>
+    where
+        C: Pod,
+    {
+        self.remove_order(order_id).ok_or(AoError::OrderNotFound)
+    }
+
+    /// Removes the order at rank `n` from the best price of one side of the book, in price-time priority (0 being
+    /// the best order), and returns its data. Fails with [`AoError::OrderNotFound`] if the side holds `n` orders
+    /// or fewer.
+    ///
+    /// Like the cancel_order instruction, this doesn't push an Out event to the event queue.
+    pub fn cancel_nth(&mut self, side: Side, n: usize) -> Result<RemovedOrder<C>, AoError>
+    where
+        C: Pod,
+    {
+        let (order_id, _, _, _) = self
+            .iter_orders(side)
+            .nth(n)
+            .ok_or(AoError::OrderNotFound)?;
+        self.remove_order(order_id).ok_or(AoError::OrderNotFound)
+    }
+
+    /// Removes the best resting order whose callback info matches `predicate`, looking through the bids and then the
+    /// asks in price-time priority, and returns its data. Fails with [`AoError::OrderNotFound`] if no order matches.
+    ///
+    /// This lets callers which store their own order reference in the callback info, along with the owner, cancel an
+    /// order without tracking its order id. The whole book may be scanned.
+    ///
+    /// Like the cancel_order instruction, this doesn't push an Out event to the event queue.
+    pub fn cancel_by_callback_info<F: FnMut(&C) -> bool>(
+        &mut self,
+        mut predicate: F,
+    ) -> Result<RemovedOrder<C>, AoError>
+    where
+        C: Pod,
+    {
+        let (order_id, _, _, _) = self
+            .iter_orders(Side::Bid)
+            .chain(self.iter_orders(Side::Ask))
+            .find(|(_, _, _, callback_info)| predicate(callback_info))
+            .ok_or(AoError::OrderNotFound)?;
+        self.remove_order(order_id).ok_or(AoError::OrderNotFound)
+    }
+
+    /// Reads both sides of the book along with the event queue's sequence number.
+    pub fn snapshot(&self, event_queue: &EventQueue<'_, C>) -> BookSnapshot {
+        BookSnapshot {
+            bids: self.levels(Side::Bid),
+            asks: self.levels(Side::Ask),
+            seq_num: event_queue.next_seq_num(),
+        }
+    }
+
+    /// Hashes the resting orders of one side of the book in price-time priority, so that light clients can check the
+    /// side against an expected hash without downloading its orders.
+    ///
+    /// Each order contributes its leaf, that is its order id, base quantity and expiry, followed by its callback info.
+    /// The side comes first, so that an empty bid side and an empty ask side don't share a hash.
+    pub fn book_hash(&self, side: Side) -> [u8; 32]
+    where
+        C: Pod,
+    {
+        let slab_iter = match side {
+            Side::Bid => self.bids.iter(false),
+            Side::Ask => self.asks.iter(true),
+        };
+        let mut hasher = Hasher::default();
+        hasher.hash(&[side as u8]);
+        for (leaf, callback_info) in slab_iter {
+            hasher.hash(bytemuck::bytes_of(leaf));
+            hasher.hash(bytemuck::bytes_of(callback_info));
+        }
+        hasher.result().to_bytes()
+    }
+
+    /// Combines the hashes of both sides of the book, see [`OrderBookState::book_hash`].
+    pub fn market_hash(&self) -> [u8; 32]
+    where
+        C: Pod,
+    {
+        hashv(&[&self.book_hash(Side::Bid), &self.book_hash(Side::Ask)]).to_bytes()
+    }
 }
 
 impl<'a, C: CallbackInfo> OrderBookState<'a, C>
 where
     <C as CallbackInfo>::CallbackId: PartialEq,
 {
+    /// Sums the base size and quote value of all the resting orders whose callback id is `callback_id`, across
+    /// both sides of the book, returning `(base_exposure, quote_exposure)`.
+    ///
+    /// Since every resting order has to be visited, this fails with [`AoError::TraversalLimitExceeded`] when the
+    /// book holds more than `max_orders` orders.
+    pub fn exposure(
+        &self,
+        callback_id: &C::CallbackId,
+        max_orders: u64,
+    ) -> Result<(u64, u64), AoError> {
+        let order_count =
+            u64::from(self.bids.header.leaf_count) + u64::from(self.asks.header.leaf_count);
+        if order_count > max_orders {
+            return Err(AoError::TraversalLimitExceeded);
+        }
+        let mut base_exposure = 0u64;
+        let mut quote_exposure = 0u64;
+        for &side in &[Side::Bid, Side::Ask] {
+            for (_, price, base_size, callback_info) in self.iter_orders(side) {
+                if callback_info.as_callback_id() != callback_id {
+                    continue;
+                }
+                let quote_size = compute_quote_size(base_size, price, side.opposite())?;
+                base_exposure = base_exposure
+                    .checked_add(base_size)
+                    .ok_or(AoError::NumericalOverflow)?;
+                quote_exposure = quote_exposure
+                    .checked_add(quote_size)
+                    .ok_or(AoError::QuoteOverflow)?;
+            }
+        }
+        Ok((base_exposure, quote_exposure))
+    }
+
+    /// Checks that the orders whose callback id is `callback_id` don't exceed `max_orders` across both sides of the
+    /// book, failing with [`AoError::TooManyOrders`] otherwise. A `max_orders` of 0 disables the check.
+    ///
+    /// The count stops as soon as the limit is exceeded, so the cost is bounded by the number of resting orders.
+    pub fn check_orders_per_owner(
+        &self,
+        callback_id: &C::CallbackId,
+        max_orders: u16,
+    ) -> Result<(), AoError> {
+        if max_orders == 0 {
+            return Ok(());
+        }
+        let exceeded = self
+            .iter_orders(Side::Bid)
+            .chain(self.iter_orders(Side::Ask))
+            .filter(|(_, _, _, callback_info)| callback_info.as_callback_id() == callback_id)
+            .nth(max_orders as usize)
+            .is_some();
+        if exceeded {
+            return Err(AoError::TooManyOrders);
+        }
+        Ok(())
+    }
+
+    /// Removes an order from the orderbook and pushes the corresponding Out event to the event queue
+    fn remove_order_with_out_event(
+        &mut self,
+        order_id: OrderId,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<RemovedOrder<C>, AoError> {
+        let removed = self.remove_order(order_id).ok_or(AoError::OrderNotFound)?;
+        let out = OutEvent {
+            side: order_id.side() as u8,
+            order_id,
+            base_size: removed.base_quantity,
+            tag: EventTag::Out as u8,
+            version: OutEvent::VERSION,
+            _padding: [0; 13],
+            _padding_end: [0; 24],
+        };
+        event_queue
+            .push_back(out, Some(&removed.callback_info), None)
+            .map_err(|_| AoError::EventQueueFull)?;
+        Ok(removed)
+    }
+
+    /// Removes a maker order which has become smaller than the minimum order size after being matched.
+    ///
+    /// A fully filled order is reported by its Fill events alone, while the unfilled remainder of a partially filled
+    /// order is cancelled with an Out event.
+    fn remove_matched_order(
+        &mut self,
+        order_id: OrderId,
+        base_quantity: u64,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<(), AoError> {
+        if base_quantity == 0 {
+            self.remove_order(order_id).ok_or(AoError::OrderNotFound)?;
+        } else {
+            self.remove_order_with_out_event(order_id, event_queue)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn prune_orders(
         &mut self,
         num_orders_to_prune: u64,
         side: Side,
         event_queue: &mut EventQueue<'a, C>,
     ) -> Result<(), AoError> {
-        let slab = self.get_tree(side);
         for _ in 0..num_orders_to_prune {
+            let slab = self.get_tree(side);
             let boot_candidate = slab.find_min().expect("Should be a bid/ask there");
-            let boot_candidate_key = slab.leaf_nodes[boot_candidate as usize].key;
-            let (order, callback_info_booted) = slab.remove_by_key(boot_candidate_key).unwrap();
-            let out = OutEvent {
-                side: side as u8,
-                order_id: order.order_id(),
-                base_size: order.base_quantity,
-                tag: EventTag::Out as u8,
-                _padding: [0; 14],
-            };
-            event_queue
-                .push_back(out, Some(callback_info_booted), None)
-                .map_err(|_| AoError::EventQueueFull)?;
+            let boot_candidate_id = slab.leaf_nodes[boot_candidate as usize].order_id();
+            self.remove_order_with_out_event(boot_candidate_id, event_queue)?;
         }
         Ok(())
     }
 
+    /// Removes up to `max` orders from the orderbook regardless of their owner, bids being swept before asks, and
+    /// pushes an Out event for each of them. Returns the number of removed orders.
+    ///
+    /// Orders are always taken from the top of each slab, so the book itself acts as the cursor: a sweep which is
+    /// interrupted by `max` resumes where it stopped on the next call.
+    pub(crate) fn cancel_all(
+        &mut self,
+        max: u32,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<u32, AoError> {
+        let mut removed = 0;
+        for &side in &[Side::Bid, Side::Ask] {
+            while removed < max {
+                let slab = self.get_tree(side);
+                let order_id = match slab.find_min() {
+                    Some(h) => slab.leaf_nodes[h as usize].order_id(),
+                    None => break,
+                };
+                self.remove_order_with_out_event(order_id, event_queue)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes up to `max_orders` orders whose max timestamp is lower than `cur_ts` from one side of the orderbook,
+    /// pushing an Out event for each of them. Returns the number of evicted orders.
+    pub(crate) fn evict_expired(
+        &mut self,
+        side: Side,
+        max_orders: u64,
+        cur_ts: u64,
+        event_queue: &mut EventQueue<'a, C>,
+    ) -> Result<u64, AoError> {
+        let expired_ids = self
+            .get_tree(side)
+            .iter(true)
+            .filter(|(leaf, _)| leaf.max_ts < cur_ts)
+            .map(|(leaf, _)| leaf.order_id())
+            .take(max_orders as usize)
+            .collect::<Vec<_>>();
+        for order_id in expired_ids.iter() {
+            self.remove_order_with_out_event(*order_id, event_queue)?;
+        }
+        Ok(expired_ids.len() as u64)
+    }
+
+    /// Matches a taker against the single resting order `maker_order_id`, filling at most `taker_size` base
+    /// at the maker's price. The rest of the book is left untouched.
+    ///
+    /// Orders which have exceeded their max timestamp can't be taken and are reported as not found.
+    pub fn take_order(
+        &mut self,
+        maker_order_id: OrderId,
+        taker_size: u64,
+        taker_callback_info: C,
+        event_queue: &mut EventQueue<'a, C>,
+        min_base_order_size: u64,
+        cur_ts: u64,
+    ) -> Result<OrderSummary, AoError> {
+        let maker_side = maker_order_id.side();
+        let taker_side = maker_side.opposite();
+        let slab = self.get_tree(maker_side);
+        let maker_h = slab
+            .find_by_key(maker_order_id.into())
+            .ok_or(AoError::OrderNotFound)?;
+        let maker_ref = &slab.leaf_nodes[maker_h as usize];
+        if maker_ref.max_ts < cur_ts {
+            return Err(AoError::OrderNotFound);
+        }
+
+        let trade_price = maker_ref.price();
+        let base_trade_qty = maker_ref.base_quantity.min(taker_size);
+        if base_trade_qty == 0 {
+            return Err(AoError::ZeroOrderSize);
+        }
+        let quote_trade_qty = compute_quote_size(base_trade_qty, trade_price, taker_side)?;
+        let taker_order_id = event_queue.gen_order_id(trade_price, taker_side);
+
+        let maker_fill = FillEvent {
+            taker_side: taker_side as u8,
+            maker_order_id,
+            taker_order_id,
+            quote_size: quote_trade_qty,
+            base_size: base_trade_qty,
+            price: trade_price,
+            tag: EventTag::Fill as u8,
+            version: FillEvent::VERSION,
+            _padding: [0; 5],
+        };
+        event_queue
+            .push_fill(
+                maker_fill,
+                &slab.callback_infos[maker_h as usize],
+                &taker_callback_info,
+                maker_ref.base_quantity - base_trade_qty,
+            )
+            .map_err(|_| AoError::EventQueueFull)?;
+
+        slab.decrease_base_quantity(maker_h, base_trade_qty);
+        let maker_remaining = slab.leaf_nodes[maker_h as usize].base_quantity;
+        if maker_remaining < min_base_order_size {
+            self.remove_matched_order(maker_order_id, maker_remaining, event_queue)?;
+        }
+
+        Ok(OrderSummary {
+            posted_order_id: None,
+            total_base_qty: base_trade_qty,
+            total_quote_qty: quote_trade_qty,
+            total_base_qty_posted: 0,
+            total_fee: compute_taker_fee(quote_trade_qty, self.taker_fee_bps)?,
+        })
+    }
+
     pub fn new_order(
         &mut self,
         params: new_order::Params<C>,
@@ -156,8 +886,21 @@ where
             mut match_limit,
         } = params;
 
+        // A zero-size order could neither be matched nor posted
+        if max_base_qty == 0 || max_quote_qty == 0 {
+            return Err(AoError::ZeroOrderSize);
+        }
+
         let mut base_qty_remaining = max_base_qty;
         let mut quote_qty_remaining = max_quote_qty;
+        let self_trade_match = self.self_trade_match;
+        let maker_price_improvement_bps = self.maker_price_improvement_bps;
+        let log_matches = self.log_matches;
+        let taker_fee_bps = self.taker_fee_bps;
+        let mut total_fee = 0;
+
+        // The order id is generated before matching so that the fill events can reference the taker order
+        let taker_order_id = event_queue.gen_order_id(limit_price, side);
 
         // New bid
         let mut crossed = true;
@@ -175,58 +918,41 @@ where
 
             let opposite_slab = self.get_tree(side.opposite());
 
-            let mut best_bo_ref = &mut opposite_slab.leaf_nodes[best_bo_h as usize];
+            let best_bo_ref = &opposite_slab.leaf_nodes[best_bo_h as usize];
 
             // The order on the book has exceeded max ts, we will boot it
             // and continue attempting to match
             if best_bo_ref.max_ts < cur_ts {
                 let best_offer_id = best_bo_ref.order_id();
-                let provide_out_callback_info = &opposite_slab.callback_infos[best_bo_h as usize];
-                let provide_out = OutEvent {
-                    side: side.opposite() as u8,
-                    order_id: best_offer_id,
-                    base_size: best_bo_ref.base_quantity,
-                    tag: EventTag::Out as u8,
-                    _padding: [0; 14],
-                };
-                event_queue
-                    .push_back(provide_out, Some(provide_out_callback_info), None)
-                    .map_err(|_| AoError::EventQueueFull)?;
-
-                self.get_tree(side.opposite())
-                    .remove_by_key(best_offer_id)
-                    .unwrap();
+                self.remove_order_with_out_event(best_offer_id, event_queue)?;
 
                 match_limit -= 1;
 
                 continue;
             }
 
-            let trade_price = best_bo_ref.price();
-            crossed = match side {
-                Side::Bid => limit_price >= trade_price,
-                Side::Ask => limit_price <= trade_price,
-            };
+            let maker_price = best_bo_ref.price();
+            crossed = crosses(side, limit_price, maker_price);
 
-            if post_only || !crossed {
+            if post_only != PostOnly::Disabled || !crossed {
                 break;
             }
 
+            let trade_price =
+                compute_fill_price(maker_price, limit_price, side, maker_price_improvement_bps);
             let offer_size = best_bo_ref.base_quantity;
             let base_trade_qty = offer_size
                 .min(base_qty_remaining)
-                .min(fp32_div(quote_qty_remaining, best_bo_ref.price()).unwrap_or(u64::MAX));
+                .min(fp32_div(quote_qty_remaining, trade_price).unwrap_or(u64::MAX));
 
             if base_trade_qty == 0 {
                 break;
             }
 
-            let quote_maker_qty = match side {
-                Side::Bid => fp32_mul_ceil(base_trade_qty, trade_price),
-                Side::Ask => fp32_mul_floor(base_trade_qty, trade_price),
-            }
-            .map(|q| std::cmp::min(q, quote_qty_remaining))
-            .ok_or(AoError::NumericalOverflow)?;
+            let quote_maker_qty = std::cmp::min(
+                compute_quote_size(base_trade_qty, trade_price, side)?,
+                quote_qty_remaining,
+            );
 
             if quote_maker_qty == 0 {
                 break;
@@ -235,8 +961,10 @@ where
             // The decrement take case can be handled by the caller program on event consumption, so no special logic
             // is needed for it.
             if self_trade_behavior != SelfTradeBehavior::DecrementTake {
-                let order_would_self_trade = callback_info.as_callback_id()
-                    == opposite_slab.callback_infos[best_bo_h as usize].as_callback_id();
+                let order_would_self_trade = self_trade_match.is_self_trade(
+                    &callback_info,
+                    &opposite_slab.callback_infos[best_bo_h as usize],
+                );
                 if order_would_self_trade {
                     let best_offer_id = best_bo_ref.order_id();
 
@@ -244,22 +972,7 @@ where
                         return Err(AoError::WouldSelfTrade);
                     }
                     assert!(self_trade_behavior == SelfTradeBehavior::CancelProvide);
-                    let provide_out_callback_info =
-                        &opposite_slab.callback_infos[best_bo_h as usize];
-                    let provide_out = OutEvent {
-                        side: side.opposite() as u8,
-                        order_id: best_offer_id,
-                        base_size: best_bo_ref.base_quantity,
-                        tag: EventTag::Out as u8,
-                        _padding: [0; 14],
-                    };
-                    event_queue
-                        .push_back(provide_out, Some(provide_out_callback_info), None)
-                        .map_err(|_| AoError::EventQueueFull)?;
-
-                    self.get_tree(side.opposite())
-                        .remove_by_key(best_offer_id)
-                        .unwrap();
+                    self.remove_order_with_out_event(best_offer_id, event_queue)?;
 
                     match_limit -= 1;
 
@@ -272,37 +985,38 @@ where
             let maker_fill = FillEvent {
                 taker_side: side as u8,
                 maker_order_id: best_bo_ref.order_id(),
+                taker_order_id,
                 quote_size: quote_maker_qty,
                 base_size: base_trade_qty,
+                price: trade_price,
                 tag: EventTag::Fill as u8,
-                _padding: [0; 6],
+                version: FillEvent::VERSION,
+                _padding: [0; 5],
             };
             event_queue
-                .push_back(maker_fill, Some(maker_callback_info), Some(&callback_info))
+                .push_fill(
+                    maker_fill,
+                    maker_callback_info,
+                    &callback_info,
+                    best_bo_ref.base_quantity - base_trade_qty,
+                )
                 .map_err(|_| AoError::EventQueueFull)?;
+            if log_matches {
+                sol_log_data(&[
+                    &maker_fill.maker_order_id.0.to_le_bytes(),
+                    &trade_price.to_le_bytes(),
+                    &base_trade_qty.to_le_bytes(),
+                ]);
+            }
 
-            best_bo_ref.base_quantity -= base_trade_qty;
+            opposite_slab.decrease_base_quantity(best_bo_h, base_trade_qty);
             base_qty_remaining -= base_trade_qty;
             quote_qty_remaining -= quote_maker_qty;
+            total_fee += compute_taker_fee(quote_maker_qty, taker_fee_bps)?;
 
-            if best_bo_ref.base_quantity < min_base_order_size {
-                let best_offer_id = best_bo_ref.order_id();
-                let cur_side = side.opposite();
-                let out_event = OutEvent {
-                    side: cur_side as u8,
-                    order_id: best_offer_id,
-                    base_size: best_bo_ref.base_quantity,
-                    tag: EventTag::Out as u8,
-                    _padding: [0; 14],
-                };
-
-                let (_, out_event_callback_info) = self
-                    .get_tree(cur_side)
-                    .remove_by_key(best_offer_id)
-                    .unwrap();
-                event_queue
-                    .push_back(out_event, Some(out_event_callback_info), None)
-                    .map_err(|_| AoError::EventQueueFull)?;
+            let maker_remaining = opposite_slab.leaf_nodes[best_bo_h as usize].base_quantity;
+            if maker_remaining < min_base_order_size {
+                self.remove_matched_order(maker_fill.maker_order_id, maker_remaining, event_queue)?;
             }
 
             match_limit -= 1;
@@ -314,22 +1028,38 @@ where
         );
 
         if crossed || !post_allowed || base_qty_to_post < min_base_order_size {
+            // The unfilled remainder of an immediate-or-cancel order is reported to the caller through an Out event
+            // with the taker's side and callback information so that any reserved funds can be released.
+            if !post_allowed && base_qty_remaining > 0 && !self.fills_only {
+                let taker_out = OutEvent {
+                    side: side as u8,
+                    order_id: taker_order_id,
+                    base_size: base_qty_remaining,
+                    tag: EventTag::Out as u8,
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
+                };
+                event_queue
+                    .push_back(taker_out, Some(&callback_info), None)
+                    .map_err(|_| AoError::EventQueueFull)?;
+            }
             return Ok(OrderSummary {
                 posted_order_id: None,
                 total_base_qty: max_base_qty - base_qty_remaining,
                 total_quote_qty: max_quote_qty - quote_qty_remaining,
                 total_base_qty_posted: 0,
+                total_fee,
             });
         }
 
-        let new_leaf_order_id = event_queue.gen_order_id(limit_price, side);
         let new_leaf = LeafNode {
-            key: new_leaf_order_id,
+            key: taker_order_id.into(),
             base_quantity: base_qty_to_post,
             max_ts,
         };
-        let insert_result = self.get_tree(side).insert_leaf(&new_leaf);
-        let k = if let Err(AoError::SlabOutOfSpace) = insert_result {
+        let insert_result = self.insert_order(&new_leaf, callback_info);
+        if let Err(AoError::SlabOutOfSpace) = insert_result {
             // Boot out the least aggressive orders
             msg!("Orderbook is full! booting least aggressive orders...");
             let slab = self.get_tree(side);
@@ -337,70 +1067,209 @@ where
                 Side::Bid => slab.find_min().unwrap(),
                 Side::Ask => slab.find_max().unwrap(),
             };
-            let boot_candidate_key = slab.leaf_nodes[boot_candidate as usize].key;
-            let boot_candidate_price = LeafNode::price_from_key(boot_candidate_key);
+            let boot_candidate_id = slab.leaf_nodes[boot_candidate as usize].order_id();
             let should_boot = match side {
-                Side::Bid => boot_candidate_price < limit_price,
-                Side::Ask => boot_candidate_price > limit_price,
+                Side::Bid => boot_candidate_id.price() < limit_price,
+                Side::Ask => boot_candidate_id.price() > limit_price,
             };
             if should_boot {
-                let (order, callback_info_booted) = slab.remove_by_key(boot_candidate_key).unwrap();
-                let out = OutEvent {
-                    side: side as u8,
-                    order_id: order.order_id(),
-                    base_size: order.base_quantity,
-                    tag: EventTag::Out as u8,
-                    _padding: [0; 14],
-                };
-                event_queue
-                    .push_back(out, Some(callback_info_booted), None)
-                    .map_err(|_| AoError::EventQueueFull)?;
-                slab.insert_leaf(&new_leaf).unwrap().0
+                self.remove_order_with_out_event(boot_candidate_id, event_queue)?;
+                self.insert_order(&new_leaf, callback_info)?;
             } else {
                 return Ok(OrderSummary {
                     posted_order_id: None,
                     total_base_qty: max_base_qty - base_qty_remaining,
                     total_quote_qty: max_quote_qty - quote_qty_remaining,
                     total_base_qty_posted: 0,
+                    total_fee,
                 });
             }
         } else {
-            insert_result.unwrap().0
-        };
-        *self.get_tree(side).get_callback_info_mut(k) = callback_info;
-        base_qty_remaining -= base_qty_to_post;
-        quote_qty_remaining -= match side {
-            Side::Bid => fp32_mul_ceil(base_qty_to_post, limit_price),
-            Side::Ask => fp32_mul_floor(base_qty_to_post, limit_price),
+            insert_result?;
         }
-        .ok_or(AoError::NumericalOverflow)?;
+        base_qty_remaining -= base_qty_to_post;
+        quote_qty_remaining -= compute_quote_size(base_qty_to_post, limit_price, side)?;
         Ok(OrderSummary {
-            posted_order_id: Some(new_leaf_order_id),
+            posted_order_id: Some(taker_order_id),
             total_base_qty: max_base_qty - base_qty_remaining,
             total_quote_qty: max_quote_qty - quote_qty_remaining,
             total_base_qty_posted: base_qty_to_post,
+            total_fee,
         })
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::state::event_queue::{EventRef, FillEventRef, OutEventRef};
-
-    use super::*;
 
-    type SlabTest<'a> = Slab<'a, [u8; 32]>;
-    type OrderBookStateTest<'a> = OrderBookState<'a, [u8; 32]>;
-    type EventQueueTest<'a> = EventQueue<'a, [u8; 32]>;
+    /// Posts a batch of orders to the orderbook without matching them, returning the ids of the posted orders.
+    ///
+    /// If any of the orders would cross the spread, is too small or doesn't fit in the orderbook, all of the orders
+    /// which were already posted are removed and the whole batch is rejected.
+    pub fn post_orders_batch(
+        &mut self,
+        orders: &[post_orders_batch::BatchOrder<C>],
+        event_queue: &mut EventQueue<'a, C>,
+        min_base_order_size: u64,
+    ) -> Result<Vec<OrderId>, AoError> {
+        if orders.len() > post_orders_batch::MAX_BATCH_SIZE {
+            return Err(AoError::BatchTooLarge);
+        }
+        let mut posted_order_ids = Vec::with_capacity(orders.len());
+        for order in orders {
+            match self.post_batch_order(order, event_queue, min_base_order_size) {
+                Ok(order_id) => posted_order_ids.push(order_id),
+                Err(reason) => {
+                    // Roll back the orders which were already posted
+                    for order_id in posted_order_ids {
+                        self.remove_order(order_id).unwrap();
+                    }
+                    return Err(reason.into());
+                }
+            }
+        }
+        Ok(posted_order_ids)
+    }
 
-    pub struct TestContext {
-        asks_buffer: Vec<u8>,
-        bids_buffer: Vec<u8>,
-        event_queue_buffer: Vec<u8>,
+    /// Posts a batch of orders to the orderbook without matching them, rejecting the orders which can't be posted
+    /// individually instead of the whole batch.
+    ///
+    /// `check_order` is called on each order before posting it. Once posted, an order is removed again if its owner
+    /// now holds more than `max_orders_per_owner` resting orders, or if it opened a price level beyond
+    /// `max_levels_per_side`, see [`OrderBookState::check_orders_per_owner`] and [`OrderBookState::check_new_levels`].
+    ///
+    /// A Reject event carrying the order's callback info is pushed to the event queue for each rejected order.
+    /// Returns the id of each posted order, or `None` for the rejected ones.
+    pub fn post_orders_batch_partial<F>(
+        &mut self,
+        orders: &[post_orders_batch::BatchOrder<C>],
+        event_queue: &mut EventQueue<'a, C>,
+        min_base_order_size: u64,
+        max_orders_per_owner: u16,
+        max_levels_per_side: u32,
+        check_order: F,
+    ) -> Result<Vec<Option<OrderId>>, AoError>
+    where
+        F: Fn(&post_orders_batch::BatchOrder<C>) -> Result<(), RejectReason>,
+    {
+        if orders.len() > post_orders_batch::MAX_BATCH_SIZE {
+            return Err(AoError::BatchTooLarge);
+        }
+        let mut order_ids = Vec::with_capacity(orders.len());
+        for (batch_index, order) in orders.iter().enumerate() {
+            let posted = check_order(order)
+                .and_then(|()| self.post_batch_order(order, event_queue, min_base_order_size))
+                .and_then(|order_id| {
+                    self.check_batch_order_limits(
+                        order_id,
+                        &order.callback_info,
+                        max_orders_per_owner,
+                        max_levels_per_side,
+                    )
+                });
+            match posted {
+                Ok(order_id) => order_ids.push(Some(order_id)),
+                Err(reason) => {
+                    msg!("Rejected batch order {}: {:?}", batch_index, reason);
+                    let reject = RejectEvent {
+                        tag: EventTag::Reject as u8,
+                        side: order.side as u8,
+                        version: RejectEvent::VERSION,
+                        reason: reason as u8,
+                        _padding: [0; 4],
+                        batch_index: batch_index as u64,
+                        limit_price: order.limit_price,
+                        base_size: order.base_qty,
+                        _padding_end: [0; 32],
+                    };
+                    event_queue
+                        .push_back(reject, Some(&order.callback_info), None)
+                        .map_err(|_| AoError::EventQueueFull)?;
+                    order_ids.push(None);
+                }
+            }
+        }
+        Ok(order_ids)
     }
 
-    impl TestContext {
-        pub fn new(order_capacity: usize, event_capacity: usize) -> Self {
+    /// Removes a freshly posted batch order from the book if it exceeds the market's limits on orders per owner or
+    /// levels per side
+    fn check_batch_order_limits(
+        &mut self,
+        order_id: OrderId,
+        callback_info: &C,
+        max_orders_per_owner: u16,
+        max_levels_per_side: u32,
+    ) -> Result<OrderId, RejectReason> {
+        let within_limits = self
+            .check_orders_per_owner(callback_info.as_callback_id(), max_orders_per_owner)
+            .map_err(|_| RejectReason::TooManyOrders)
+            .and_then(|()| {
+                self.check_new_levels(order_id.side(), &[order_id], max_levels_per_side)
+                    .map_err(|_| RejectReason::TooManyLevels)
+            });
+        if let Err(reason) = within_limits {
+            self.remove_order(order_id).unwrap();
+            return Err(reason);
+        }
+        Ok(order_id)
+    }
+
+    fn post_batch_order(
+        &mut self,
+        order: &post_orders_batch::BatchOrder<C>,
+        event_queue: &mut EventQueue<'a, C>,
+        min_base_order_size: u64,
+    ) -> Result<OrderId, RejectReason> {
+        if order.base_qty < min_base_order_size {
+            return Err(RejectReason::TooSmall);
+        }
+        let opposite_best_price = self
+            .find_bbo(order.side.opposite())
+            .map(|h| self.get_tree(order.side.opposite()).leaf_nodes[h as usize].price());
+        let crossed =
+            opposite_best_price.map_or(false, |p| crosses(order.side, order.limit_price, p));
+        if crossed {
+            msg!("A batch order would cross the spread!");
+            return Err(RejectReason::CrossesSpread);
+        }
+        let order_id = event_queue.gen_order_id(order.limit_price, order.side);
+        let leaf = LeafNode {
+            key: order_id.into(),
+            base_quantity: order.base_qty,
+            max_ts: u64::MAX,
+        };
+        self.insert_order(&leaf, order.callback_info)
+            .map_err(|e| match e {
+                AoError::DuplicateOrderId => RejectReason::DuplicateOrderId,
+                _ => RejectReason::BookFull,
+            })?;
+        Ok(order_id)
+    }
+}
+
+/// Checks whether an order on `side` at `limit_price` would match a resting order at `maker_price`
+fn crosses(side: Side, limit_price: u64, maker_price: u64) -> bool {
+    match side {
+        Side::Bid => limit_price >= maker_price,
+        Side::Ask => limit_price <= maker_price,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::event_queue::{EventRef, FillEventRef, OutEventRef};
+
+    use super::*;
+
+    type SlabTest<'a> = Slab<'a, [u8; 32]>;
+    type OrderBookStateTest<'a> = OrderBookState<'a, [u8; 32]>;
+    type EventQueueTest<'a> = EventQueue<'a, [u8; 32]>;
+
+    pub struct TestContext {
+        asks_buffer: Vec<u8>,
+        bids_buffer: Vec<u8>,
+        event_queue_buffer: Vec<u8>,
+    }
+
+    impl TestContext {
+        pub fn new(order_capacity: usize, event_capacity: usize) -> Self {
             let allocation_size = SlabTest::compute_allocation_size(order_capacity);
             let (mut asks_buffer, mut bids_buffer) =
                 (vec![0; allocation_size], vec![0; allocation_size]);
@@ -441,7 +1310,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 0,
                     callback_info: [0; 32],
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: false,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -451,7 +1320,23 @@ mod tests {
                 u64::MIN,
             )
             .unwrap();
-        assert!(event_queue.header.count == 0);
+        assert_eq!(event_queue.header.count, 1);
+        assert_eq!(
+            event_queue.iter().next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent {
+                    tag: EventTag::Out as u8,
+                    side: Side::Ask as u8,
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
+                    base_size: 10,
+                    order_id: OrderId::from_parts(10 << 32, 0, Side::Ask)
+                },
+                callback_info: &[0; 32]
+            })
+        );
+        event_queue.pop_n(1);
 
         // Alice posts a bid order for 1 BTC at 10 USD/BTC
 
@@ -460,6 +1345,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -469,7 +1355,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -490,6 +1376,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -499,7 +1386,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: bob,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -520,6 +1407,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -529,7 +1417,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: bob,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -551,6 +1439,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -560,7 +1449,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -576,7 +1465,8 @@ mod tests {
         assert_eq!(total_base_qty_posted, 250_000);
         assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(14 << 32)));
 
-        assert_eq!(event_queue.header.count, 2);
+        // Bob's order is fully filled, which is reported by the Fill event alone
+        assert_eq!(event_queue.header.count, 1);
         let mut event_queue_iter = event_queue.iter();
         assert_eq!(
             event_queue_iter.next().unwrap(),
@@ -584,31 +1474,21 @@ mod tests {
                 event: &FillEvent {
                     tag: EventTag::Fill as u8,
                     taker_side: Side::Ask as u8,
-                    _padding: [0; 6],
+                    version: FillEvent::VERSION,
+                    _padding: [0; 5],
                     quote_size: 500_000 * 15,
                     maker_order_id: bob_order_id_0.unwrap(),
-                    base_size: 500_000
+                    taker_order_id: alice_order_id_0.unwrap(),
+                    base_size: 500_000,
+                    price: 15 << 32
                 },
                 maker_callback_info: &bob,
                 taker_callback_info: &alice
             })
         );
-
-        assert_eq!(
-            event_queue_iter.next().unwrap(),
-            EventRef::Out(OutEventRef {
-                event: &OutEvent {
-                    tag: EventTag::Out as u8,
-                    side: Side::Bid as u8,
-                    _padding: [0; 14],
-                    base_size: 0,
-                    order_id: bob_order_id_0.unwrap()
-                },
-                callback_info: &bob
-            })
-        );
+        assert_eq!(event_queue_iter.next(), None);
         println!("Event queue head: {}", event_queue.header.head);
-        event_queue.pop_n(2);
+        event_queue.pop_n(1);
         println!("Event queue head: {}", event_queue.header.head);
 
         assert_eq!(event_queue.header.count, 0);
@@ -623,7 +1503,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::AbortTransaction,
                     max_ts: u64::MAX,
@@ -645,6 +1525,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -654,7 +1535,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::CancelProvide,
                     max_ts: u64::MAX,
@@ -677,7 +1558,9 @@ mod tests {
                 event: &OutEvent {
                     tag: EventTag::Out as u8,
                     side: Side::Ask as u8,
-                    _padding: [0; 14],
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
                     base_size: 250_000,
                     order_id: alice_order_id_0.unwrap()
                 },
@@ -696,7 +1579,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: [0; 32],
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: false,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -706,7 +1589,8 @@ mod tests {
                 u64::MIN,
             )
             .unwrap();
-        assert!(event_queue.header.count == 0);
+        // The order could not be matched and is reported back as a taker Out event
+        assert_eq!(event_queue.header.count, 1);
 
         println!("Spread : {:?}", orderbook.get_spread());
     }
@@ -723,6 +1607,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -732,7 +1617,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -754,6 +1639,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -763,7 +1649,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -785,6 +1671,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -794,7 +1681,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -816,7 +1703,9 @@ mod tests {
                 event: &OutEvent {
                     tag: EventTag::Out as u8,
                     side: Side::Ask as u8,
-                    _padding: [0; 14],
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
                     base_size: 6_000_000,
                     order_id: order_id_to_be_booted.unwrap()
                 },
@@ -832,6 +1721,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -841,7 +1731,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -871,6 +1761,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -880,7 +1771,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -907,7 +1798,7 @@ mod tests {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: [0; 32],
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: false,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -917,7 +1808,7 @@ mod tests {
                 u64::MIN,
             )
             .unwrap();
-        assert!(event_queue.header.count == 0);
+        assert_eq!(event_queue.header.count, 1);
     }
 
     #[test]
@@ -932,6 +1823,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -941,7 +1833,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -963,6 +1855,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -972,7 +1865,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -994,6 +1887,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -1003,7 +1897,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -1025,7 +1919,9 @@ mod tests {
                 event: &OutEvent {
                     tag: EventTag::Out as u8,
                     side: Side::Bid as u8,
-                    _padding: [0; 14],
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
                     base_size: 6_000_000,
                     order_id: order_id_to_be_booted.unwrap()
                 },
@@ -1041,6 +1937,7 @@ mod tests {
             total_base_qty,
             total_quote_qty,
             total_base_qty_posted,
+            ..
         } = orderbook
             .new_order(
                 new_order::Params {
@@ -1050,7 +1947,7 @@ mod tests {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: alice,
-                    post_only: false,
+                    post_only: PostOnly::Disabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -1066,4 +1963,2288 @@ mod tests {
         assert_eq!(total_base_qty_posted, 0);
         assert_eq!(event_queue.header.count, 0);
     }
+
+    #[test]
+    fn test_ob_ioc_remainder_out() {
+        let mut test_context = TestContext::new(10, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let alice = [1; 32];
+        let bob = [2; 32];
+
+        // Bob posts an ask order for 1 BTC at 10 USD/BTC
+        let OrderSummary {
+            posted_order_id: bob_order_id,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Ask,
+                    match_limit: 10,
+                    callback_info: bob,
+                    post_only: PostOnly::Disabled,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                u64::MIN,
+            )
+            .unwrap();
+
+        // Alice sends an IOC bid order for 1.5 BTC at 10 USD/BTC, which is only partially filled
+        let OrderSummary {
+            posted_order_id,
+            total_base_qty,
+            total_quote_qty,
+            total_base_qty_posted,
+            ..
+        } = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_500_000,
+                    max_quote_qty: 1_000_000_000,
+                    limit_price: 10 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: alice,
+                    post_only: PostOnly::Disabled,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                u64::MIN,
+            )
+            .unwrap();
+        assert!(posted_order_id.is_none());
+        assert_eq!(total_base_qty, 1_000_000);
+        assert_eq!(total_quote_qty, 10_000_000);
+        assert_eq!(total_base_qty_posted, 0);
+        // Bob's order was assigned the first sequence number
+        let alice_order_id = OrderId::from_parts(10 << 32, 1, Side::Bid);
+
+        let mut event_queue_iter = event_queue.iter();
+        assert_eq!(
+            event_queue_iter.next().unwrap(),
+            EventRef::Fill(FillEventRef {
+                event: &FillEvent {
+                    tag: EventTag::Fill as u8,
+                    taker_side: Side::Bid as u8,
+                    version: FillEvent::VERSION,
+                    _padding: [0; 5],
+                    quote_size: 10_000_000,
+                    maker_order_id: bob_order_id.unwrap(),
+                    taker_order_id: alice_order_id,
+                    base_size: 1_000_000,
+                    price: 10 << 32
+                },
+                maker_callback_info: &bob,
+                taker_callback_info: &alice
+            })
+        );
+        // The maker order is fully filled and removed from the book without an Out event.
+        // The taker's unfilled remainder is reported with the taker's side and callback info
+        assert_eq!(
+            event_queue_iter.next().unwrap(),
+            EventRef::Out(OutEventRef {
+                event: &OutEvent {
+                    tag: EventTag::Out as u8,
+                    side: Side::Bid as u8,
+                    version: OutEvent::VERSION,
+                    _padding: [0; 13],
+                    _padding_end: [0; 24],
+                    base_size: 500_000,
+                    order_id: alice_order_id
+                },
+                callback_info: &alice
+            })
+        );
+        assert!(event_queue_iter.next().is_none());
+    }
+
+    #[test]
+    fn test_ob_iter_orders() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        // (side, price, size, owner)
+        let orders = [
+            (Side::Bid, 10, 1_000, 1),
+            (Side::Bid, 12, 2_000, 2),
+            (Side::Bid, 10, 3_000, 3),
+            (Side::Bid, 11, 4_000, 4),
+            (Side::Bid, 12, 5_000, 5),
+            (Side::Ask, 15, 6_000, 6),
+            (Side::Ask, 14, 7_000, 7),
+            (Side::Ask, 15, 8_000, 8),
+            (Side::Ask, 16, 9_000, 9),
+            (Side::Ask, 14, 10_000, 10),
+        ];
+        let mut order_ids = Vec::new();
+        for &(side, price, size, owner) in orders.iter() {
+            let order_summary = orderbook
+                .new_order(
+                    new_order::Params {
+                        max_base_qty: size,
+                        max_quote_qty: u64::MAX,
+                        limit_price: price << 32,
+                        side,
+                        match_limit: 10,
+                        callback_info: [owner; 32],
+                        post_only: PostOnly::Enabled,
+                        post_allowed: true,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts: u64::MAX,
+                    },
+                    &mut event_queue,
+                    10,
+                    u64::MIN,
+                )
+                .unwrap();
+            order_ids.push(order_summary.posted_order_id.unwrap());
+        }
+
+        let expected = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|&i| {
+                    let (_, price, size, owner) = orders[i];
+                    (order_ids[i], price << 32, size, [owner; 32])
+                })
+                .collect::<Vec<_>>()
+        };
+        let collect = |side| {
+            orderbook
+                .iter_orders(side)
+                .map(|(order_id, price, size, callback_info)| {
+                    (order_id, price, size, *callback_info)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(collect(Side::Bid), expected(&[1, 4, 3, 0, 2]));
+        assert_eq!(collect(Side::Ask), expected(&[6, 9, 5, 7, 8]));
+    }
+
+    fn batch_order(
+        side: Side,
+        price: u64,
+        size: u64,
+        owner: u8,
+    ) -> post_orders_batch::BatchOrder<[u8; 32]> {
+        post_orders_batch::BatchOrder {
+            side,
+            limit_price: price << 32,
+            base_qty: size,
+            callback_info: [owner; 32],
+        }
+    }
+
+    #[test]
+    fn test_ob_post_orders_batch() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 10, 2_000, 1),
+            batch_order(Side::Ask, 11, 3_000, 1),
+            batch_order(Side::Ask, 12, 4_000, 1),
+            batch_order(Side::Ask, 13, 5_000, 1),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        assert_eq!(order_ids.len(), 5);
+        assert!(event_queue.is_empty());
+        assert_eq!(orderbook.get_spread(), (Some(10 << 32), Some(11 << 32)));
+
+        let resting = [Side::Bid, Side::Ask]
+            .iter()
+            .flat_map(|&side| orderbook.iter_orders(side).map(|(id, _, _, _)| id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resting,
+            vec![
+                order_ids[1],
+                order_ids[0],
+                order_ids[2],
+                order_ids[3],
+                order_ids[4]
+            ]
+        );
+
+        // A batch containing an order which crosses the spread is rejected
+        let orders = [
+            batch_order(Side::Bid, 8, 1_000, 2),
+            batch_order(Side::Bid, 11, 1_000, 2),
+        ];
+        assert!(orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .is_err());
+        assert_eq!(orderbook.iter_orders(Side::Bid).count(), 2);
+
+        // Batches are bounded in size
+        let orders =
+            vec![batch_order(Side::Bid, 1, 1_000, 2); post_orders_batch::MAX_BATCH_SIZE + 1];
+        assert!(matches!(
+            orderbook.post_orders_batch(&orders, &mut event_queue, 10),
+            Err(AoError::BatchTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_ob_post_orders_batch_rollback() {
+        let mut test_context = TestContext::new(3, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        // The bids slab only has room for 3 orders, so the 4th insertion fails
+        let orders = [
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 10, 2_000, 1),
+            batch_order(Side::Bid, 11, 3_000, 1),
+            batch_order(Side::Bid, 12, 4_000, 1),
+            batch_order(Side::Bid, 13, 5_000, 1),
+        ];
+        assert!(matches!(
+            orderbook.post_orders_batch(&orders, &mut event_queue, 10),
+            Err(AoError::SlabOutOfSpace)
+        ));
+        assert!(orderbook.is_empty());
+        assert_eq!(orderbook.get_spread(), (None, None));
+
+        // The slab is still usable after the rollback
+        let order_ids = orderbook
+            .post_orders_batch(&orders[..3], &mut event_queue, 10)
+            .unwrap();
+        assert_eq!(order_ids.len(), 3);
+        assert_eq!(orderbook.bids.header.leaf_count, 3);
+    }
+
+    #[test]
+    fn test_ob_evict_expired() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        // (side, price, max_ts)
+        let orders = [
+            (Side::Bid, 10, 100),
+            (Side::Bid, 11, 300),
+            (Side::Bid, 12, 150),
+            (Side::Ask, 20, 50),
+            (Side::Ask, 21, u64::MAX),
+            (Side::Ask, 22, 199),
+        ];
+        let mut order_ids = Vec::new();
+        for (i, &(side, price, max_ts)) in orders.iter().enumerate() {
+            let order_summary = orderbook
+                .new_order(
+                    new_order::Params {
+                        max_base_qty: 1_000,
+                        max_quote_qty: u64::MAX,
+                        limit_price: price << 32,
+                        side,
+                        match_limit: 10,
+                        callback_info: [i as u8; 32],
+                        post_only: PostOnly::Enabled,
+                        post_allowed: true,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts,
+                    },
+                    &mut event_queue,
+                    10,
+                    0,
+                )
+                .unwrap();
+            order_ids.push(order_summary.posted_order_id.unwrap());
+        }
+
+        // Only one of the two expired bids is evicted because of the limit
+        let evicted = orderbook
+            .evict_expired(Side::Bid, 1, 200, &mut event_queue)
+            .unwrap();
+        assert_eq!(evicted, 1);
+        let evicted = orderbook
+            .evict_expired(Side::Bid, 10, 200, &mut event_queue)
+            .unwrap();
+        assert_eq!(evicted, 1);
+        let evicted = orderbook
+            .evict_expired(Side::Ask, 10, 200, &mut event_queue)
+            .unwrap();
+        assert_eq!(evicted, 2);
+        let evicted = orderbook
+            .evict_expired(Side::Ask, 10, 200, &mut event_queue)
+            .unwrap();
+        assert_eq!(evicted, 0);
+
+        let remaining = [Side::Bid, Side::Ask]
+            .iter()
+            .flat_map(|&side| orderbook.iter_orders(side).map(|(id, _, _, _)| id))
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![order_ids[1], order_ids[4]]);
+
+        let evicted = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.event.base_size, 1_000);
+                    (o.event.order_id, *o.callback_info)
+                }
+                _ => panic!("Only Out events should be pushed"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            evicted,
+            vec![
+                (order_ids[0], [0; 32]),
+                (order_ids[2], [2; 32]),
+                (order_ids[3], [3; 32]),
+                (order_ids[5], [5; 32]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ob_cancel_all() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        for i in 0..10u64 {
+            let side = if i % 2 == 0 { Side::Bid } else { Side::Ask };
+            let price = if side == Side::Bid { 10 + i } else { 100 + i };
+            orderbook
+                .new_order(
+                    new_order::Params {
+                        max_base_qty: 1_000,
+                        max_quote_qty: u64::MAX,
+                        limit_price: price << 32,
+                        side,
+                        match_limit: 10,
+                        callback_info: [i as u8; 32],
+                        post_only: PostOnly::Enabled,
+                        post_allowed: true,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts: u64::MAX,
+                    },
+                    &mut event_queue,
+                    10,
+                    0,
+                )
+                .unwrap();
+        }
+
+        // The first sweep empties the bids and stops halfway through the asks
+        assert_eq!(orderbook.cancel_all(7, &mut event_queue).unwrap(), 7);
+        assert_eq!(orderbook.bids.header.leaf_count, 0);
+        assert_eq!(orderbook.asks.header.leaf_count, 3);
+
+        assert_eq!(orderbook.cancel_all(7, &mut event_queue).unwrap(), 3);
+        assert_eq!(orderbook.asks.header.leaf_count, 0);
+        assert_eq!(orderbook.cancel_all(7, &mut event_queue).unwrap(), 0);
+
+        let mut cancelled = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => {
+                    assert_eq!(o.event.base_size, 1_000);
+                    o.callback_info[0]
+                }
+                _ => panic!("Only Out events should be pushed"),
+            })
+            .collect::<Vec<_>>();
+        cancelled.sort_unstable();
+        assert_eq!(cancelled, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ob_fills_only() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        orderbook.fills_only = true;
+
+        let orders = [
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 2),
+            batch_order(Side::Bid, 9, 1_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // The unfilled remainder of the immediate-or-cancel taker doesn't enqueue an Out event
+        let summary = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_500,
+                    max_quote_qty: u64::MAX,
+                    limit_price: 11 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: [4; 32],
+                    post_only: PostOnly::Disabled,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 1_000);
+
+        // Orders removed on their owner's behalf are still reported with Out events
+        assert_eq!(orderbook.cancel_all(10, &mut event_queue).unwrap(), 2);
+        assert!(orderbook.is_empty());
+
+        assert_eq!(event_queue.len(), 3);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Fill(f) => {
+                assert_eq!(f.event.base_size, 1_000);
+                assert_eq!(*f.maker_callback_info, [1; 32]);
+                assert_eq!(*f.taker_callback_info, [4; 32]);
+            }
+            _ => panic!("The taker's remainder shouldn't push an Out event"),
+        }
+        let cancelled = event_queue
+            .iter()
+            .skip(1)
+            .map(|e| match e {
+                EventRef::Out(o) => *o.callback_info,
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cancelled, vec![[3; 32], [2; 32]]);
+    }
+
+    #[test]
+    fn test_ob_fills_only_expired_maker() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        orderbook.fills_only = true;
+
+        let params = new_order::Params {
+            max_base_qty: 1_000,
+            max_quote_qty: u64::MAX,
+            limit_price: 11 << 32,
+            side: Side::Ask,
+            match_limit: 10,
+            callback_info: [1; 32],
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: 5,
+        };
+        let maker_order_id = orderbook
+            .new_order(params.clone(), &mut event_queue, 10, 0)
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+
+        // The taker hits the expired maker, which is removed without being matched
+        let summary = orderbook
+            .new_order(
+                new_order::Params {
+                    side: Side::Bid,
+                    callback_info: [2; 32],
+                    post_allowed: false,
+                    max_ts: u64::MAX,
+                    ..params
+                },
+                &mut event_queue,
+                10,
+                10,
+            )
+            .unwrap();
+        assert_eq!(summary.total_base_qty, 0);
+        assert!(orderbook.is_empty());
+
+        // The maker's removal is reported even though the taker's remainder isn't
+        assert_eq!(event_queue.len(), 1);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => {
+                assert_eq!(o.event.order_id, maker_order_id);
+                assert_eq!(o.event.base_size, 1_000);
+                assert_eq!(*o.callback_info, [1; 32]);
+            }
+            _ => panic!("The expired maker should push an Out event"),
+        }
+    }
+
+    #[test]
+    fn test_ob_take_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Ask, 11, 3_000, 1),
+            batch_order(Side::Ask, 12, 4_000, 2),
+            batch_order(Side::Ask, 13, 5_000, 3),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // Take part of the order which is not at the top of the book
+        let order_summary = orderbook
+            .take_order(order_ids[1], 1_000, [4; 32], &mut event_queue, 10, 0)
+            .unwrap();
+        assert_eq!(order_summary.posted_order_id, None);
+        assert_eq!(order_summary.total_base_qty, 1_000);
+        assert_eq!(order_summary.total_quote_qty, 12_000);
+        assert_eq!(order_summary.total_base_qty_posted, 0);
+
+        // Taking more than what is left fills the order completely and removes it
+        let order_summary = orderbook
+            .take_order(order_ids[1], 10_000, [4; 32], &mut event_queue, 10, 0)
+            .unwrap();
+        assert_eq!(order_summary.total_base_qty, 3_000);
+
+        let resting = orderbook
+            .iter_orders(Side::Ask)
+            .map(|(id, _, size, _)| (id, size))
+            .collect::<Vec<_>>();
+        assert_eq!(resting, vec![(order_ids[0], 3_000), (order_ids[2], 5_000)]);
+        assert_eq!(orderbook.iter_orders(Side::Bid).count(), 0);
+
+        let events = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Fill(f) => {
+                    assert_eq!(f.event.maker_order_id, order_ids[1]);
+                    assert_eq!(f.event.taker_side, Side::Bid as u8);
+                    assert_eq!(f.event.price, 12 << 32);
+                    assert_eq!(*f.maker_callback_info, [2; 32]);
+                    assert_eq!(*f.taker_callback_info, [4; 32]);
+                    f.event.base_size
+                }
+                // The fully filled order is removed without an Out event
+                EventRef::Out(_) | EventRef::Reject(_) | EventRef::Tick(_) => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![1_000, 3_000]);
+
+        assert!(matches!(
+            orderbook.take_order(order_ids[1], 1_000, [4; 32], &mut event_queue, 10, 0),
+            Err(AoError::OrderNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_ob_zero_size_order() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Ask, 10, 1_000, 1)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+
+        for &(max_base_qty, max_quote_qty) in &[(0, u64::MAX), (1_000, 0), (0, 0)] {
+            for &post_only in &[PostOnly::Disabled, PostOnly::Enabled] {
+                let res = orderbook.new_order(
+                    new_order::Params {
+                        max_base_qty,
+                        max_quote_qty,
+                        limit_price: 10 << 32,
+                        side: Side::Bid,
+                        match_limit: 10,
+                        callback_info: [2; 32],
+                        post_only,
+                        post_allowed: true,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts: u64::MAX,
+                    },
+                    &mut event_queue,
+                    10,
+                    0,
+                );
+                assert!(matches!(res, Err(AoError::ZeroOrderSize)));
+            }
+        }
+
+        // Neither a leaf nor an event was created
+        assert_eq!(orderbook.iter_orders(Side::Bid).count(), 0);
+        assert_eq!(orderbook.iter_orders(Side::Ask).count(), 1);
+        assert!(event_queue.is_empty());
+        assert_eq!(event_queue.next_seq_num(), 1);
+    }
+
+    #[test]
+    fn test_ob_self_trade_cancel_provide_callback_info() {
+        #[derive(Clone, Copy, Debug, PartialEq, Pod, bytemuck::Zeroable)]
+        #[repr(C)]
+        struct UserCallbackInfo {
+            user: [u8; 8],
+            account: [u8; 8],
+        }
+
+        impl CallbackInfo for UserCallbackInfo {
+            type CallbackId = [u8; 8];
+
+            fn as_callback_id(&self) -> &Self::CallbackId {
+                &self.user
+            }
+        }
+
+        let allocation_size = Slab::<UserCallbackInfo>::compute_allocation_size(100);
+        let (mut asks_buffer, mut bids_buffer) =
+            (vec![0; allocation_size], vec![0; allocation_size]);
+        Slab::<UserCallbackInfo>::initialize(&mut asks_buffer, &mut bids_buffer).unwrap();
+        let mut event_queue_buffer =
+            vec![0; EventQueue::<UserCallbackInfo>::compute_allocation_size(100)];
+        let mut orderbook =
+            OrderBookState::<UserCallbackInfo>::new_safe(&mut bids_buffer, &mut asks_buffer)
+                .unwrap();
+        let mut event_queue =
+            EventQueue::from_buffer(&mut event_queue_buffer, AccountTag::Uninitialized).unwrap();
+
+        // The same user trades from two different accounts
+        let maker_callback_info = UserCallbackInfo {
+            user: [1; 8],
+            account: [2; 8],
+        };
+        let taker_callback_info = UserCallbackInfo {
+            user: [1; 8],
+            account: [3; 8],
+        };
+        let params = |side, callback_info| new_order::Params {
+            max_base_qty: 1_000,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::CancelProvide,
+            max_ts: u64::MAX,
+        };
+        let maker_order_id = orderbook
+            .new_order(
+                params(Side::Ask, maker_callback_info),
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap()
+            .posted_order_id
+            .unwrap();
+        let order_summary = orderbook
+            .new_order(
+                params(Side::Bid, taker_callback_info),
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+        assert_eq!(order_summary.total_base_qty_posted, 1_000);
+        assert_eq!(orderbook.iter_orders(Side::Ask).count(), 0);
+
+        // The maker is cancelled and its Out event carries the maker's callback info
+        assert_eq!(event_queue.len(), 1);
+        match event_queue.peek_at(0).unwrap() {
+            EventRef::Out(o) => {
+                assert_eq!(o.event.order_id, maker_order_id);
+                assert_eq!(o.event.side, Side::Ask as u8);
+                assert_eq!(o.event.base_size, 1_000);
+                assert_eq!(*o.callback_info, maker_callback_info);
+            }
+            _ => panic!("Expected an Out event"),
+        }
+    }
+
+    #[test]
+    fn test_ob_self_trade_match() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        // The first 8 bytes of the callback info hold the owner, and the rest is specific to each order
+        let callback_info = |owner, account| {
+            let mut callback_info = [account; 32];
+            callback_info[..8].copy_from_slice(&[owner; 8]);
+            callback_info
+        };
+        let params = |side, callback_info| new_order::Params {
+            max_base_qty: 1_000,
+            max_quote_qty: u64::MAX,
+            limit_price: 10 << 32,
+            side,
+            match_limit: 10,
+            callback_info,
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::AbortTransaction,
+            max_ts: u64::MAX,
+        };
+        orderbook
+            .new_order(
+                params(Side::Ask, callback_info(1, 2)),
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+
+        // Comparing owner prefixes detects the same owner trading from another account
+        orderbook.self_trade_match = SelfTradeMatch::Prefix { offset: 0, len: 8 };
+        assert!(matches!(
+            orderbook.new_order(
+                params(Side::Bid, callback_info(1, 3)),
+                &mut event_queue,
+                10,
+                0
+            ),
+            Err(AoError::WouldSelfTrade)
+        ));
+
+        // Comparing full callback infos only detects identical callback infos
+        orderbook.self_trade_match = SelfTradeMatch::Full;
+        assert!(matches!(
+            orderbook.new_order(
+                params(Side::Bid, callback_info(1, 2)),
+                &mut event_queue,
+                10,
+                0
+            ),
+            Err(AoError::WouldSelfTrade)
+        ));
+        let order_summary = orderbook
+            .new_order(
+                params(Side::Bid, callback_info(1, 3)),
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+        assert_eq!(order_summary.total_base_qty, 1_000);
+        assert_eq!(order_summary.posted_order_id, None);
+        assert!(orderbook.is_empty());
+    }
+
+    #[test]
+    fn test_ob_maker_price_improvement() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let params = |side, limit_price, callback_info| new_order::Params {
+            max_base_qty: 1_000,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: [callback_info; 32],
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        };
+        let mut fill_price = |orderbook: &mut OrderBookStateTest, side: Side| {
+            orderbook
+                .new_order(
+                    params(side.opposite(), 10 << 32, 1),
+                    &mut event_queue,
+                    10,
+                    0,
+                )
+                .unwrap();
+            let taker_limit_price = match side {
+                Side::Bid => 14 << 32,
+                Side::Ask => 6 << 32,
+            };
+            let order_summary = orderbook
+                .new_order(params(side, taker_limit_price, 2), &mut event_queue, 10, 0)
+                .unwrap();
+            let fill = event_queue
+                .iter()
+                .filter_map(|e| match e {
+                    EventRef::Fill(f) => Some(*f.event),
+                    _ => None,
+                })
+                .last()
+                .unwrap();
+            assert_eq!(fill.base_size, 1_000);
+            assert_eq!(order_summary.total_quote_qty, fill.quote_size);
+            fill.price
+        };
+
+        // By default, all of the price improvement goes to the taker
+        assert_eq!(fill_price(&mut orderbook, Side::Bid), 10 << 32);
+        assert_eq!(fill_price(&mut orderbook, Side::Ask), 10 << 32);
+
+        // The price improvement can be split between the maker and the taker
+        orderbook.maker_price_improvement_bps = 5_000;
+        assert_eq!(fill_price(&mut orderbook, Side::Bid), 12 << 32);
+        assert_eq!(fill_price(&mut orderbook, Side::Ask), 8 << 32);
+
+        // Or entirely given to the maker
+        orderbook.maker_price_improvement_bps = 10_000;
+        assert_eq!(fill_price(&mut orderbook, Side::Bid), 14 << 32);
+        assert_eq!(fill_price(&mut orderbook, Side::Ask), 6 << 32);
+    }
+
+    #[test]
+    fn test_ob_log_matches() {
+        use solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+        use std::cell::RefCell;
+
+        // The stubs are global, so each test thread records its own logs
+        thread_local! {
+            static LOGGED: RefCell<Vec<Vec<Vec<u8>>>> = const { RefCell::new(Vec::new()) };
+        }
+        struct MockLogger;
+        impl SyscallStubs for MockLogger {
+            fn sol_log_data(&self, fields: &[&[u8]]) {
+                let fields = fields.iter().map(|f| f.to_vec()).collect();
+                LOGGED.with(|logged| logged.borrow_mut().push(fields));
+            }
+        }
+        let _stubs_guard = crate::test_harness::lock_syscall_stubs();
+        let previous_stubs = set_syscall_stubs(Box::new(MockLogger));
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let params = |side, max_base_qty, limit_price| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: [side as u8; 32],
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        };
+        let ask_ids = [10u64, 11, 12]
+            .iter()
+            .map(|price| {
+                orderbook
+                    .new_order(params(Side::Ask, 100, price << 32), &mut event_queue, 10, 0)
+                    .unwrap()
+                    .posted_order_id
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Nothing is logged unless enabled
+        orderbook
+            .new_order(params(Side::Bid, 20, 10 << 32), &mut event_queue, 10, 0)
+            .unwrap();
+        assert!(LOGGED.with(|logged| logged.take()).is_empty());
+
+        orderbook.log_matches = true;
+        orderbook
+            .new_order(params(Side::Bid, 250, 12 << 32), &mut event_queue, 10, 0)
+            .unwrap();
+        set_syscall_stubs(previous_stubs);
+
+        let logged = LOGGED.with(|logged| logged.take());
+        let logged_match = |order_id: OrderId, price: u64, base_size: u64| {
+            vec![
+                order_id.0.to_le_bytes().to_vec(),
+                (price << 32).to_le_bytes().to_vec(),
+                base_size.to_le_bytes().to_vec(),
+            ]
+        };
+        let expected = vec![
+            logged_match(ask_ids[0], 10, 80),
+            logged_match(ask_ids[1], 11, 100),
+            logged_match(ask_ids[2], 12, 70),
+        ];
+        assert_eq!(logged, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "fill-maker-remaining")]
+    fn test_ob_fill_maker_remaining() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let params = |side, max_base_qty, limit_price| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price,
+            side,
+            match_limit: 10,
+            callback_info: [side as u8; 32],
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        };
+        orderbook
+            .new_order(params(Side::Ask, 100, 10 << 32), &mut event_queue, 10, 0)
+            .unwrap();
+        orderbook
+            .new_order(params(Side::Ask, 100, 11 << 32), &mut event_queue, 10, 0)
+            .unwrap();
+
+        // The first maker is filled entirely and the second one partially
+        orderbook
+            .new_order(params(Side::Bid, 130, 11 << 32), &mut event_queue, 10, 0)
+            .unwrap();
+        let remaining = (0..event_queue.len())
+            .filter_map(|index| match event_queue.peek_at(index).unwrap() {
+                EventRef::Fill(f) => Some((
+                    f.event.base_size,
+                    event_queue.maker_remaining_at(index).unwrap(),
+                )),
+                _ => {
+                    assert_eq!(event_queue.maker_remaining_at(index), None);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(remaining, vec![(100, 0), (30, 70)]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum SweepEvent {
+        Fill {
+            maker_order_id: OrderId,
+            base_size: u64,
+            quote_size: u64,
+        },
+        Out {
+            order_id: OrderId,
+            base_size: u64,
+        },
+    }
+
+    /// Sweeps three ask levels of 100 at prices 10, 11 and 12 with a bid taker, returning the taker's summary, the
+    /// events pushed by the taker, the ids of the makers and the base quantity left on the book at each level.
+    fn multi_level_sweep(
+        max_base_qty: u64,
+        max_quote_qty: u64,
+        post_allowed: bool,
+    ) -> (OrderSummary, Vec<SweepEvent>, Vec<OrderId>, Vec<u64>) {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let maker_ids = [10u64, 11, 12]
+            .iter()
+            .map(|&price| {
+                orderbook
+                    .new_order(
+                        new_order::Params {
+                            max_base_qty: 100,
+                            max_quote_qty: u64::MAX,
+                            limit_price: price << 32,
+                            side: Side::Ask,
+                            match_limit: 10,
+                            callback_info: [price as u8; 32],
+                            post_only: PostOnly::Enabled,
+                            post_allowed: true,
+                            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                            max_ts: u64::MAX,
+                        },
+                        &mut event_queue,
+                        1,
+                        0,
+                    )
+                    .unwrap()
+                    .posted_order_id
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(event_queue.is_empty());
+
+        let summary = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty,
+                    max_quote_qty,
+                    limit_price: 12 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: [0; 32],
+                    post_only: PostOnly::Disabled,
+                    post_allowed,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                1,
+                0,
+            )
+            .unwrap();
+        let events = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Fill(f) => {
+                    assert_eq!(f.event.price, f.event.maker_order_id.price());
+                    SweepEvent::Fill {
+                        maker_order_id: f.event.maker_order_id,
+                        base_size: f.event.base_size,
+                        quote_size: f.event.quote_size,
+                    }
+                }
+                EventRef::Out(o) => SweepEvent::Out {
+                    order_id: o.event.order_id,
+                    base_size: o.event.base_size,
+                },
+                _ => panic!("Only Fill and Out events should be pushed"),
+            })
+            .collect();
+        let remaining = orderbook
+            .levels(Side::Ask)
+            .iter()
+            .map(|l| l.base_quantity)
+            .collect();
+        (summary, events, maker_ids, remaining)
+    }
+
+    #[test]
+    fn test_ob_multi_level_partial_sweep() {
+        let fill = |maker_order_id, base_size, price| SweepEvent::Fill {
+            maker_order_id,
+            base_size,
+            quote_size: base_size * price,
+        };
+        let out = |order_id, base_size| SweepEvent::Out {
+            order_id,
+            base_size,
+        };
+
+        // A limit order sized to consume two levels and half of the third one leaves no remainder
+        let (summary, events, maker_ids, remaining) = multi_level_sweep(250, u64::MAX, true);
+        assert_eq!(
+            events,
+            vec![
+                fill(maker_ids[0], 100, 10),
+                fill(maker_ids[1], 100, 11),
+                fill(maker_ids[2], 50, 12),
+            ]
+        );
+        assert_eq!(remaining, vec![50]);
+        assert_eq!(summary.total_base_qty, 250);
+        assert_eq!(summary.total_quote_qty, 1_000 + 1_100 + 600);
+        assert_eq!(summary.posted_order_id, None);
+        assert_eq!(summary.total_base_qty_posted, 0);
+
+        // When the quote budget runs out on the third level, the remainder of a limit order can't be posted since
+        // it would still cross the book, and it is dropped without an event
+        let (summary, events, maker_ids, remaining) = multi_level_sweep(400, 2_700, true);
+        assert_eq!(
+            events,
+            vec![
+                fill(maker_ids[0], 100, 10),
+                fill(maker_ids[1], 100, 11),
+                fill(maker_ids[2], 50, 12),
+            ]
+        );
+        assert_eq!(remaining, vec![50]);
+        assert_eq!(summary.total_base_qty, 250);
+        assert_eq!(summary.total_quote_qty, 2_700);
+        assert_eq!(summary.posted_order_id, None);
+
+        // The same remainder of an immediate-or-cancel order is cancelled with an Out event for the taker
+        let (summary, events, maker_ids, remaining) = multi_level_sweep(400, 2_700, false);
+        let taker_order_id = match events.last() {
+            Some(SweepEvent::Out { order_id, .. }) => *order_id,
+            _ => panic!("The taker's remainder should be cancelled"),
+        };
+        assert_eq!(
+            events,
+            vec![
+                fill(maker_ids[0], 100, 10),
+                fill(maker_ids[1], 100, 11),
+                fill(maker_ids[2], 50, 12),
+                out(taker_order_id, 150),
+            ]
+        );
+        assert_eq!(taker_order_id.side(), Side::Bid);
+        assert_eq!(remaining, vec![50]);
+        assert_eq!(summary.total_base_qty, 250);
+        assert_eq!(summary.posted_order_id, None);
+    }
+
+    #[test]
+    fn test_ob_out_events_after_fills() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let orders = [
+            batch_order(Side::Ask, 10, 1_000, 1),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 1),
+            batch_order(Side::Bid, 5, 1_000, 1),
+        ];
+        let maker_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        let mut take = |orderbook: &mut OrderBookStateTest, max_base_qty, price: u64| {
+            orderbook
+                .new_order(
+                    new_order::Params {
+                        max_base_qty,
+                        max_quote_qty: u64::MAX,
+                        limit_price: price << 32,
+                        side: Side::Bid,
+                        match_limit: 10,
+                        callback_info: [2; 32],
+                        post_only: PostOnly::Disabled,
+                        post_allowed: false,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts: u64::MAX,
+                    },
+                    &mut event_queue,
+                    10,
+                    0,
+                )
+                .unwrap();
+            let events = event_queue
+                .iter()
+                .map(|e| match e {
+                    EventRef::Fill(f) => SweepEvent::Fill {
+                        maker_order_id: f.event.maker_order_id,
+                        base_size: f.event.base_size,
+                        quote_size: f.event.quote_size,
+                    },
+                    EventRef::Out(o) => SweepEvent::Out {
+                        order_id: o.event.order_id,
+                        base_size: o.event.base_size,
+                    },
+                    _ => panic!("Only Fill and Out events should be pushed"),
+                })
+                .collect::<Vec<_>>();
+            event_queue.pop_n(events.len() as u64);
+            events
+        };
+        let fill = |maker_order_id, base_size, price| SweepEvent::Fill {
+            maker_order_id,
+            base_size,
+            quote_size: base_size * price,
+        };
+        let out = |order_id, base_size| SweepEvent::Out {
+            order_id,
+            base_size,
+        };
+
+        // A fully filled maker is only reported by its Fill event
+        assert_eq!(
+            take(&mut orderbook, 1_000, 10),
+            vec![fill(maker_ids[0], 1_000, 10)]
+        );
+
+        // A maker left below the minimum order size has its remainder cancelled after the fill
+        assert_eq!(
+            take(&mut orderbook, 995, 11),
+            vec![fill(maker_ids[1], 995, 11), out(maker_ids[1], 5)]
+        );
+
+        // A partially filled maker which is cancelled later gets an Out event for what's left of it, and an unfilled
+        // maker gets a single Out event for its whole size
+        assert_eq!(
+            take(&mut orderbook, 400, 12),
+            vec![fill(maker_ids[2], 400, 12)]
+        );
+        assert_eq!(orderbook.cancel_all(10, &mut event_queue).unwrap(), 2);
+        let cancelled = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Out(o) => out(o.event.order_id, o.event.base_size),
+                _ => panic!("Only Out events should be pushed"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cancelled,
+            vec![out(maker_ids[3], 1_000), out(maker_ids[2], 600)]
+        );
+    }
+
+    #[test]
+    fn test_order_summary_size() {
+        let order_summary = |posted_order_id| OrderSummary {
+            posted_order_id,
+            total_base_qty: u64::MAX,
+            total_quote_qty: u64::MAX,
+            total_base_qty_posted: u64::MAX,
+            total_fee: u64::MAX,
+        };
+
+        // ORDER_SUMMARY_SIZE is the largest serialized size, reached when an order has been posted
+        let posted = order_summary(Some(OrderId(u128::MAX)))
+            .try_to_vec()
+            .unwrap();
+        assert_eq!(posted.len(), ORDER_SUMMARY_SIZE as usize);
+        let not_posted = order_summary(None).try_to_vec().unwrap();
+        assert!(not_posted.len() < ORDER_SUMMARY_SIZE as usize);
+    }
+
+    #[test]
+    fn test_ob_snapshot() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let snapshot = orderbook.snapshot(&event_queue);
+        assert_eq!(
+            snapshot,
+            BookSnapshot {
+                bids: vec![],
+                asks: vec![],
+                seq_num: 0
+            }
+        );
+
+        let orders = [
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 10, 2_000, 1),
+            batch_order(Side::Bid, 9, 3_000, 2),
+            batch_order(Side::Ask, 12, 4_000, 1),
+            batch_order(Side::Ask, 11, 5_000, 2),
+            batch_order(Side::Ask, 12, 6_000, 3),
+            batch_order(Side::Ask, 12, 7_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        let level = |price: u64, base_quantity, order_count| Level {
+            price: price << 32,
+            base_quantity,
+            order_count,
+        };
+        let snapshot = orderbook.snapshot(&event_queue);
+        assert_eq!(
+            snapshot,
+            BookSnapshot {
+                bids: vec![level(10, 2_000, 1), level(9, 4_000, 2)],
+                asks: vec![level(11, 5_000, 1), level(12, 17_000, 3)],
+                seq_num: 7
+            }
+        );
+    }
+
+    #[test]
+    fn test_ob_vwap() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        assert_eq!(orderbook.vwap(Side::Ask, 1), None);
+
+        let orders = [
+            batch_order(Side::Bid, 10, 2_000, 1),
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 7, 1_000, 2),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 2),
+            batch_order(Side::Ask, 12, 2_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // Within the best level
+        assert_eq!(orderbook.vwap(Side::Bid, 1_500), Some(10 << 32));
+        assert_eq!(orderbook.vwap(Side::Ask, 1_000), Some(11 << 32));
+        // Across levels: (2_000 * 10 + 1_000 * 9 + 1_000 * 7) / 4_000 = 9
+        assert_eq!(orderbook.vwap(Side::Bid, 4_000), Some(9 << 32));
+        // (1_000 * 11 + 1_000 * 12) / 2_000 = 11.5
+        assert_eq!(orderbook.vwap(Side::Ask, 2_000), Some(23 << 31));
+        // Across both orders of the second level: (1_000 * 11 + 3_000 * 12) / 4_000 = 11.75
+        assert_eq!(orderbook.vwap(Side::Ask, 4_000), Some(47 << 30));
+
+        // The book lacks the depth
+        assert_eq!(orderbook.vwap(Side::Bid, 4_001), None);
+        assert_eq!(orderbook.vwap(Side::Ask, 0), None);
+    }
+
+    #[test]
+    fn test_ob_simulate_taker() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let orders = [
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 2),
+            batch_order(Side::Ask, 12, 2_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        let levels = orderbook.levels(Side::Ask);
+
+        // Full fill across levels: 1_000 * 11 + 1_500 * 12 = 29_000
+        assert_eq!(
+            orderbook
+                .simulate_taker(Side::Bid, 2_500, 12 << 32)
+                .unwrap(),
+            FillPreview {
+                filled_base: 2_500,
+                filled_quote: 29_000,
+                avg_price: Some(orderbook.vwap(Side::Ask, 2_500).unwrap()),
+                remaining: 0,
+            }
+        );
+        // Partial fill, limited by the price
+        assert_eq!(
+            orderbook
+                .simulate_taker(Side::Bid, 2_500, 11 << 32)
+                .unwrap(),
+            FillPreview {
+                filled_base: 1_000,
+                filled_quote: 11_000,
+                avg_price: Some(11 << 32),
+                remaining: 1_500,
+            }
+        );
+        // Partial fill, limited by the depth
+        assert_eq!(
+            orderbook.simulate_taker(Side::Ask, 1_500, 0).unwrap(),
+            FillPreview {
+                filled_base: 1_000,
+                filled_quote: 9_000,
+                avg_price: Some(9 << 32),
+                remaining: 500,
+            }
+        );
+        // No fill
+        assert_eq!(
+            orderbook
+                .simulate_taker(Side::Ask, 1_000, 10 << 32)
+                .unwrap(),
+            FillPreview {
+                filled_base: 0,
+                filled_quote: 0,
+                avg_price: None,
+                remaining: 1_000,
+            }
+        );
+
+        // The book is left untouched
+        assert_eq!(orderbook.levels(Side::Ask), levels);
+        assert_eq!(orderbook.levels(Side::Bid).len(), 1);
+    }
+
+    #[test]
+    fn test_ob_cached_depth() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let orders = [
+            batch_order(Side::Bid, 10, 2_000, 1),
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 9, 3_000, 2),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 2),
+            batch_order(Side::Ask, 12, 2_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        let check = |orderbook: &OrderBookStateTest| {
+            for &side in &[Side::Bid, Side::Ask] {
+                let levels = orderbook.levels(side);
+                for price in 8..14 {
+                    let level = levels.iter().find(|l| l.price == price << 32);
+                    assert_eq!(
+                        orderbook.level_base_quantity(side, price << 32),
+                        level.map_or(0, |l| l.base_quantity)
+                    );
+                    let depth = levels
+                        .iter()
+                        .filter(|l| match side {
+                            Side::Bid => l.price >= price << 32,
+                            Side::Ask => l.price <= price << 32,
+                        })
+                        .map(|l| l.base_quantity)
+                        .sum::<u64>();
+                    assert_eq!(orderbook.cumulative_depth(side, price << 32), depth);
+                }
+            }
+        };
+        check(&orderbook);
+        assert_eq!(orderbook.level_base_quantity(Side::Bid, 9 << 32), 4_000);
+        assert_eq!(orderbook.cumulative_depth(Side::Ask, 12 << 32), 4_000);
+
+        // A taker which fills the first ask level and part of the second
+        orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_500,
+                    max_quote_qty: u64::MAX,
+                    limit_price: 12 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: [4; 32],
+                    post_only: PostOnly::Disabled,
+                    post_allowed: false,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+        check(&orderbook);
+        assert_eq!(orderbook.level_base_quantity(Side::Ask, 12 << 32), 2_500);
+
+        // A maker order taken directly
+        let bid_id = orderbook.iter_orders(Side::Bid).nth(1).unwrap().0;
+        orderbook
+            .take_order(bid_id, 400, [5; 32], &mut event_queue, 10, 0)
+            .unwrap();
+        check(&orderbook);
+        assert_eq!(orderbook.level_base_quantity(Side::Bid, 9 << 32), 3_600);
+
+        orderbook.cancel_all(10, &mut event_queue).unwrap();
+        check(&orderbook);
+        assert_eq!(orderbook.cumulative_depth(Side::Bid, 0), 0);
+    }
+
+    #[test]
+    fn test_ob_has_level() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        assert!(!orderbook.has_level(Side::Bid, 10 << 32));
+        assert_eq!(orderbook.level_size(Side::Bid, 10 << 32), 0);
+
+        orderbook
+            .post_orders_batch(
+                &[
+                    batch_order(Side::Bid, 10, 1_000, 1),
+                    batch_order(Side::Bid, 9, 1_000, 1),
+                    batch_order(Side::Bid, 10, 2_000, 2),
+                    batch_order(Side::Bid, 10, 500, 3),
+                    batch_order(Side::Ask, 12, 1_000, 1),
+                ],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        for &side in &[Side::Bid, Side::Ask] {
+            let levels = orderbook.levels(side);
+            for price in 8..14 {
+                let level = levels.iter().find(|l| l.price == price << 32);
+                assert_eq!(orderbook.has_level(side, price << 32), level.is_some());
+                assert_eq!(
+                    orderbook.level_size(side, price << 32),
+                    level.map_or(0, |l| l.order_count)
+                );
+            }
+        }
+        assert_eq!(orderbook.level_size(Side::Bid, 10 << 32), 3);
+        // A price within a level's tick isn't a level
+        assert!(!orderbook.has_level(Side::Bid, (10 << 32) + 1));
+        // Levels are looked up on their own side only
+        assert!(!orderbook.has_level(Side::Ask, 10 << 32));
+
+        // The level disappears along with its last order
+        let ask_id = orderbook.iter_orders(Side::Ask).next().unwrap().0;
+        orderbook.remove_order(ask_id).unwrap();
+        assert!(!orderbook.has_level(Side::Ask, 12 << 32));
+        orderbook.cancel_nth(Side::Bid, 0).unwrap();
+        assert_eq!(orderbook.level_size(Side::Bid, 10 << 32), 2);
+        assert!(orderbook.has_level(Side::Bid, 10 << 32));
+    }
+
+    #[test]
+    fn test_ob_book_hash() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        // Empty sides don't share a hash
+        let empty_market_hash = orderbook.market_hash();
+        assert_ne!(
+            orderbook.book_hash(Side::Bid),
+            orderbook.book_hash(Side::Ask)
+        );
+
+        orderbook
+            .post_orders_batch(
+                &[
+                    batch_order(Side::Bid, 10, 2_000, 1),
+                    batch_order(Side::Bid, 9, 1_000, 2),
+                    batch_order(Side::Ask, 12, 1_000, 1),
+                ],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        let mut hashes = vec![empty_market_hash];
+        let mut record = |orderbook: &OrderBookStateTest| {
+            let market_hash = orderbook.market_hash();
+            // Reading the book doesn't change its hash
+            assert_eq!(orderbook.market_hash(), market_hash);
+            assert!(!hashes.contains(&market_hash));
+            hashes.push(market_hash);
+        };
+        record(&orderbook);
+
+        // A change to one side leaves the other side's hash untouched
+        let ask_hash = orderbook.book_hash(Side::Ask);
+        orderbook
+            .post_orders_batch(&[batch_order(Side::Bid, 9, 500, 3)], &mut event_queue, 10)
+            .unwrap();
+        assert_eq!(orderbook.book_hash(Side::Ask), ask_hash);
+        record(&orderbook);
+
+        // A partial fill only changes the maker's base quantity
+        let bid_id = orderbook.iter_orders(Side::Bid).next().unwrap().0;
+        orderbook
+            .take_order(bid_id, 100, [4; 32], &mut event_queue, 10, 0)
+            .unwrap();
+        record(&orderbook);
+
+        // So does a change to an order's callback info
+        let h = orderbook.find_bbo(Side::Ask).unwrap();
+        *orderbook.asks.get_callback_info_mut(h) = [5; 32];
+        record(&orderbook);
+
+        orderbook.cancel_nth(Side::Bid, 1).unwrap();
+        record(&orderbook);
+
+        // The hash only depends on the accounts' contents
+        let market_hash = orderbook.market_hash();
+        drop(orderbook);
+        let orderbook = OrderBookStateTest::new_safe(
+            &mut test_context.bids_buffer,
+            &mut test_context.asks_buffer,
+        )
+        .unwrap();
+        assert_eq!(orderbook.market_hash(), market_hash);
+    }
+
+    #[test]
+    fn test_ob_mid_price_and_spread() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        // Empty book
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.mid_price(), None);
+
+        // One-sided book
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Bid, 10, 1_000, 1)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.mid_price(), None);
+
+        // Normal book
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Ask, 13, 1_000, 1)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        assert_eq!(orderbook.spread(), Some(3 << 32));
+        assert_eq!(orderbook.mid_price(), Some((23 << 32) / 2));
+
+        // A crossed book, which matching prevents, is reported as having no spread
+        let crossed_bid = LeafNode {
+            key: OrderId::from_parts(14 << 32, 100, Side::Bid).into(),
+            base_quantity: 1_000,
+            max_ts: u64::MAX,
+        };
+        orderbook.bids.insert_leaf(&crossed_bid).unwrap();
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.mid_price(), None);
+    }
+
+    #[test]
+    fn test_ob_timestamped_order_ids() {
+        use crate::state::{event_queue::OrderIdMode, timestamped_seq};
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let mut migrated_event_queue_buffer = test_context.event_queue_buffer.clone();
+        let (mut orderbook, mut event_queue) = test_context.get();
+        event_queue
+            .header
+            .set_order_id_mode(OrderIdMode::Timestamped);
+
+        // Orders posted within the same second are ordered by sequence number
+        event_queue.set_timestamp(1_000);
+        let orders = [
+            batch_order(Side::Bid, 10, 100, 1),
+            batch_order(Side::Ask, 12, 100, 1),
+            batch_order(Side::Bid, 10, 100, 2),
+            batch_order(Side::Ask, 12, 100, 2),
+        ];
+        let mut order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        assert_eq!(
+            order_ids[0],
+            OrderId::from_parts(10 << 32, timestamped_seq(1_000, 0), Side::Bid)
+        );
+
+        // The market moves to a new event queue, whose order sequence number starts over
+        drop(event_queue);
+        let mut event_queue = EventQueueTest::from_buffer(
+            &mut migrated_event_queue_buffer,
+            AccountTag::Uninitialized,
+        )
+        .unwrap();
+        event_queue
+            .header
+            .set_order_id_mode(OrderIdMode::Timestamped);
+        event_queue.set_timestamp(1_001);
+        order_ids.extend(
+            orderbook
+                .post_orders_batch(&orders, &mut event_queue, 10)
+                .unwrap(),
+        );
+        assert_eq!(order_ids[4].seq(), timestamped_seq(1_001, 0));
+
+        // Both sides keep time priority within the level
+        for &side in &[Side::Bid, Side::Ask] {
+            let resting = orderbook
+                .iter_orders(side)
+                .map(|(order_id, _, _, _)| order_id)
+                .collect::<Vec<_>>();
+            let expected = order_ids
+                .iter()
+                .copied()
+                .filter(|order_id| order_id.side() == side)
+                .collect::<Vec<_>>();
+            assert_eq!(resting, expected);
+        }
+    }
+
+    #[test]
+    fn test_ob_spread_bps() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        assert_eq!(orderbook.spread_bps(), None);
+
+        // One-sided book
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Bid, 100, 1_000, 1)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        assert_eq!(orderbook.spread_bps(), None);
+
+        // A one-tick spread: 1 / 100.5
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Ask, 101, 1_000, 2)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        assert_eq!(orderbook.spread_bps(), Some(99));
+
+        // A wider spread once the best ask is gone: 20 / 110
+        orderbook.cancel_nth(Side::Ask, 0).unwrap();
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Ask, 120, 1_000, 2)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+        assert_eq!(orderbook.spread_bps(), Some(1_818));
+
+        // A bid at the lowest price: 2 / 1
+        orderbook.cancel_nth(Side::Bid, 0).unwrap();
+        let lowest_bid = LeafNode {
+            key: OrderId::from_parts(0, 100, Side::Bid).into(),
+            base_quantity: 1_000,
+            max_ts: u64::MAX,
+        };
+        orderbook.bids.insert_leaf(&lowest_bid).unwrap();
+        assert_eq!(orderbook.spread_bps(), Some(20_000));
+
+        // The widest spread relative to a non-zero mid price: 3 / 1
+        orderbook.cancel_nth(Side::Ask, 0).unwrap();
+        let wide_ask = LeafNode {
+            key: OrderId::from_parts(3, 101, Side::Ask).into(),
+            base_quantity: 1_000,
+            max_ts: u64::MAX,
+        };
+        orderbook.asks.insert_leaf(&wide_ask).unwrap();
+        assert_eq!(orderbook.mid_price(), Some(1));
+        assert_eq!(orderbook.spread_bps(), Some(30_000));
+
+        // The mid price rounds down to 0
+        orderbook.cancel_nth(Side::Ask, 0).unwrap();
+        let lowest_ask = LeafNode {
+            key: OrderId::from_parts(1, 101, Side::Ask).into(),
+            base_quantity: 1_000,
+            max_ts: u64::MAX,
+        };
+        orderbook.asks.insert_leaf(&lowest_ask).unwrap();
+        assert_eq!(orderbook.mid_price(), Some(0));
+        assert_eq!(orderbook.spread_bps(), None);
+
+        // Crossed book
+        let crossed_bid = LeafNode {
+            key: OrderId::from_parts(2, 102, Side::Bid).into(),
+            base_quantity: 1_000,
+            max_ts: u64::MAX,
+        };
+        orderbook.bids.insert_leaf(&crossed_bid).unwrap();
+        assert_eq!(orderbook.spread_bps(), None);
+    }
+
+    #[test]
+    fn test_ob_post_only_slide() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let tick_size = 1 << 32;
+
+        // Without an opposing side, nothing slides
+        assert_eq!(
+            orderbook.slide_limit_price(Side::Bid, 15 << 32, tick_size),
+            15 << 32
+        );
+
+        orderbook
+            .post_orders_batch(
+                &[
+                    batch_order(Side::Bid, 10, 1_000, 1),
+                    batch_order(Side::Ask, 12, 1_000, 1),
+                ],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+
+        // Non-crossing prices are left unchanged
+        assert_eq!(
+            orderbook.slide_limit_price(Side::Bid, 11 << 32, tick_size),
+            11 << 32
+        );
+        assert_eq!(
+            orderbook.slide_limit_price(Side::Ask, 11 << 32, tick_size),
+            11 << 32
+        );
+        // Crossing prices slide to one tick inside the best opposing price
+        assert_eq!(
+            orderbook.slide_limit_price(Side::Ask, 9 << 32, tick_size),
+            11 << 32
+        );
+        let limit_price = orderbook.slide_limit_price(Side::Bid, 15 << 32, tick_size);
+        assert_eq!(limit_price, 11 << 32);
+
+        let order_summary = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 1_000,
+                    max_quote_qty: u64::MAX,
+                    limit_price,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: [2; 32],
+                    post_only: PostOnly::Slide,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+
+        // The bid rests one tick below the best ask without matching
+        let posted_order_id = order_summary.posted_order_id.unwrap();
+        assert_eq!(posted_order_id.price(), 11 << 32);
+        assert_eq!(order_summary.total_base_qty_posted, 1_000);
+        assert!(event_queue.is_empty());
+        assert_eq!(orderbook.get_spread(), (Some(11 << 32), Some(12 << 32)));
+    }
+
+    #[test]
+    fn test_ob_exposure() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Bid, 9, 1_000, 1),
+            batch_order(Side::Bid, 10, 2_000, 2),
+            batch_order(Side::Bid, 8, 3_000, 1),
+            batch_order(Side::Ask, 11, 4_000, 1),
+            batch_order(Side::Ask, 12, 5_000, 2),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        assert_eq!(
+            orderbook.exposure(&[1; 32], 5).unwrap(),
+            (8_000, 9 * 1_000 + 8 * 3_000 + 11 * 4_000)
+        );
+        assert_eq!(
+            orderbook.exposure(&[2; 32], 5).unwrap(),
+            (7_000, 10 * 2_000 + 12 * 5_000)
+        );
+        assert_eq!(orderbook.exposure(&[3; 32], 5).unwrap(), (0, 0));
+
+        // The traversal is bounded by the total number of resting orders
+        assert!(matches!(
+            orderbook.exposure(&[1; 32], 4),
+            Err(AoError::TraversalLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn test_ob_last_trade_price() {
+        use crate::state::market_state::MarketState;
+        use bytemuck::Zeroable;
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        let mut market_state = MarketState::zeroed();
+        assert_eq!(market_state.last_trade_price(), None);
+
+        let orders = [
+            batch_order(Side::Ask, 10, 1_000, 1),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 1),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // The last trade price is the maker price of the most recent fill
+        for &(max_base_qty, expected_price) in &[(500, 10), (1_000, 11), (1_000, 12), (1_000, 12)] {
+            let first_event_seq_num = event_queue.next_event_seq_num();
+            orderbook
+                .new_order(
+                    new_order::Params {
+                        max_base_qty,
+                        max_quote_qty: u64::MAX,
+                        limit_price: 20 << 32,
+                        side: Side::Bid,
+                        match_limit: 10,
+                        callback_info: [2; 32],
+                        post_only: PostOnly::Disabled,
+                        post_allowed: false,
+                        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                        max_ts: u64::MAX,
+                    },
+                    &mut event_queue,
+                    10,
+                    0,
+                )
+                .unwrap();
+            market_state.record_trades(&event_queue, first_event_seq_num);
+            // The last order doesn't trade as the book is empty, which leaves the price unchanged
+            assert_eq!(market_state.last_trade_price(), Some(expected_price << 32));
+        }
+    }
+
+    #[test]
+    fn test_ob_order_rank() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Bid, 10, 1_000, 1),
+            batch_order(Side::Bid, 11, 1_000, 1),
+            batch_order(Side::Bid, 10, 1_000, 2),
+            batch_order(Side::Ask, 12, 1_000, 1),
+            batch_order(Side::Bid, 10, 1_000, 3),
+            batch_order(Side::Ask, 12, 1_000, 2),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // Ranks only count the older orders at the same price
+        let ranks = order_ids
+            .iter()
+            .map(|&order_id| orderbook.order_rank(order_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranks,
+            vec![Some(0), Some(0), Some(1), Some(0), Some(2), Some(1)]
+        );
+
+        // Orders move up the queue as the orders ahead of them leave the book
+        orderbook.get_tree(Side::Bid).remove(order_ids[0]).unwrap();
+        assert_eq!(orderbook.order_rank(order_ids[0]), None);
+        assert_eq!(orderbook.order_rank(order_ids[2]), Some(0));
+        assert_eq!(orderbook.order_rank(order_ids[4]), Some(1));
+        assert_eq!(
+            orderbook.order_rank(OrderId::from_parts(10 << 32, 1_000, Side::Bid)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ob_cancel_by_callback_info() {
+        use crate::utils::{read_i64_le, write_i64_le};
+
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        // The callback info holds the owner in its first byte and a client order reference at offset 8
+        let order = |side, price, owner, client_order_id| {
+            let mut order = batch_order(side, price, 1_000, owner);
+            write_i64_le(&mut order.callback_info, 8, client_order_id).unwrap();
+            order
+        };
+        let orders = [
+            order(Side::Bid, 10, 1, 7),
+            order(Side::Ask, 12, 1, 8),
+            order(Side::Ask, 11, 2, 8),
+            order(Side::Ask, 13, 1, 8),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        let client_order = |owner: u8, client_order_id| {
+            move |callback_info: &[u8; 32]| {
+                callback_info[0] == owner && read_i64_le(callback_info, 8) == Some(client_order_id)
+            }
+        };
+
+        let removed = orderbook
+            .cancel_by_callback_info(client_order(1, 8))
+            .unwrap();
+        assert_eq!(removed.order_id, order_ids[1]);
+        assert_eq!(removed.base_quantity, 1_000);
+        let removed = orderbook
+            .cancel_by_callback_info(client_order(1, 7))
+            .unwrap();
+        assert_eq!(removed.order_id, order_ids[0]);
+
+        assert!(matches!(
+            orderbook.cancel_by_callback_info(client_order(2, 7)),
+            Err(AoError::OrderNotFound)
+        ));
+        let asks = orderbook
+            .iter_orders(Side::Ask)
+            .map(|(id, _, _, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(asks, vec![order_ids[2], order_ids[3]]);
+        assert!(orderbook.iter_orders(Side::Bid).next().is_none());
+        assert_eq!(event_queue.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_ob_owner_index() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        // The index is compared to a fresh scan of the book, where the owner is the first byte of the callback info
+        let check_index = |orderbook: &OrderBookStateTest| {
+            let mut expected: HashMap<Vec<u8>, Vec<OrderId>> = HashMap::new();
+            for &side in &[Side::Bid, Side::Ask] {
+                for (order_id, _, _, callback_info) in orderbook.iter_orders(side) {
+                    expected
+                        .entry(callback_info[..1].to_vec())
+                        .or_default()
+                        .push(order_id);
+                }
+            }
+            let mut actual = orderbook.owner_index.as_ref().unwrap().orders.clone();
+            for order_ids in expected.values_mut().chain(actual.values_mut()) {
+                order_ids.sort_unstable();
+            }
+            assert_eq!(actual, expected);
+        };
+
+        let orders = [
+            batch_order(Side::Ask, 10, 1_000, 1),
+            batch_order(Side::Ask, 11, 1_000, 1),
+            batch_order(Side::Ask, 12, 1_000, 2),
+            batch_order(Side::Bid, 5, 1_000, 3),
+        ];
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        assert_eq!(orderbook.orders_by_owner(&[1]), None);
+        for &(offset, len) in &[(0, 0), (32, 1), (31, 2)] {
+            assert!(matches!(
+                orderbook.enable_owner_index(offset, len),
+                Err(AoError::InvalidCallbackInfo)
+            ));
+        }
+        // The orders which were posted before the index was enabled are indexed
+        orderbook.enable_owner_index(0, 1).unwrap();
+        check_index(&orderbook);
+        assert_eq!(orderbook.orders_by_owner(&[1]).unwrap().len(), 2);
+
+        // Posting
+        let params = |side, max_base_qty, price: u64, owner| new_order::Params {
+            max_base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: price << 32,
+            side,
+            match_limit: 10,
+            callback_info: [owner; 32],
+            post_only: PostOnly::Disabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        };
+        orderbook
+            .new_order(params(Side::Bid, 1_000, 6, 2), &mut event_queue, 10, 0)
+            .unwrap();
+        check_index(&orderbook);
+        assert_eq!(orderbook.orders_by_owner(&[2]).unwrap().len(), 2);
+
+        // Fills, the first maker being fully filled and the second one partially
+        orderbook
+            .new_order(params(Side::Bid, 1_500, 11, 3), &mut event_queue, 10, 0)
+            .unwrap();
+        check_index(&orderbook);
+        assert_eq!(orderbook.orders_by_owner(&[1]).unwrap().len(), 1);
+
+        // Cancels
+        orderbook.cancel_by_callback_info(|c| c[0] == 2).unwrap();
+        check_index(&orderbook);
+        orderbook.cancel_nth(Side::Ask, 0).unwrap();
+        check_index(&orderbook);
+
+        // A batch which is rolled back leaves the index untouched
+        let orders = [
+            batch_order(Side::Bid, 7, 1_000, 4),
+            batch_order(Side::Ask, 1, 1_000, 4),
+        ];
+        assert!(orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .is_err());
+        check_index(&orderbook);
+        assert_eq!(orderbook.orders_by_owner(&[4]), Some(&[][..]));
+
+        orderbook.cancel_all(10, &mut event_queue).unwrap();
+        check_index(&orderbook);
+        assert!(orderbook.owner_index.as_ref().unwrap().orders.is_empty());
+    }
+
+    #[test]
+    fn test_ob_cancel_nth() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Ask, 13, 1_000, 1),
+            batch_order(Side::Ask, 11, 2_000, 2),
+            batch_order(Side::Ask, 12, 3_000, 3),
+            batch_order(Side::Ask, 11, 4_000, 4),
+            batch_order(Side::Bid, 10, 5_000, 5),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+
+        // The second best ask is the younger order at the best price
+        let removed = orderbook.cancel_nth(Side::Ask, 1).unwrap();
+        assert_eq!(removed.order_id, order_ids[3]);
+        assert_eq!(removed.price, 11 << 32);
+        assert_eq!(removed.base_quantity, 4_000);
+        assert_eq!(removed.callback_info, [4; 32]);
+
+        let asks = orderbook
+            .iter_orders(Side::Ask)
+            .map(|(id, _, size, _)| (id, size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            asks,
+            vec![
+                (order_ids[1], 2_000),
+                (order_ids[2], 3_000),
+                (order_ids[0], 1_000)
+            ]
+        );
+        let bids = orderbook
+            .iter_orders(Side::Bid)
+            .map(|(id, _, _, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(bids, vec![order_ids[4]]);
+        assert_eq!(event_queue.iter().count(), 0);
+
+        assert!(matches!(
+            orderbook.cancel_nth(Side::Ask, 3),
+            Err(AoError::OrderNotFound)
+        ));
+        assert!(matches!(
+            orderbook.cancel_nth(Side::Bid, 1),
+            Err(AoError::OrderNotFound)
+        ));
+        assert_eq!(
+            orderbook.cancel_nth(Side::Bid, 0).unwrap().order_id,
+            order_ids[4]
+        );
+        assert_eq!(orderbook.iter_orders(Side::Bid).count(), 0);
+    }
+
+    #[test]
+    fn test_ob_not_crossed_after_sweep() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        assert!(orderbook.assert_not_crossed().is_ok());
+
+        let orders = (5..10)
+            .map(|price| batch_order(Side::Bid, price, 1_000, 1))
+            .chain((10..15).map(|price| batch_order(Side::Ask, price, 1_000, 1)))
+            .collect::<Vec<_>>();
+        orderbook
+            .post_orders_batch(&orders, &mut event_queue, 10)
+            .unwrap();
+        assert!(orderbook.assert_not_crossed().is_ok());
+
+        // A bid sweeps the asks up to its limit price, and what's left of it rests at that price
+        let order_summary = orderbook
+            .new_order(
+                new_order::Params {
+                    max_base_qty: 3_500,
+                    max_quote_qty: u64::MAX,
+                    limit_price: 12 << 32,
+                    side: Side::Bid,
+                    match_limit: 10,
+                    callback_info: [2; 32],
+                    post_only: PostOnly::Disabled,
+                    post_allowed: true,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                    max_ts: u64::MAX,
+                },
+                &mut event_queue,
+                10,
+                0,
+            )
+            .unwrap();
+        assert_eq!(order_summary.total_base_qty_posted, 500);
+        assert_eq!(orderbook.get_spread(), (Some(12 << 32), Some(13 << 32)));
+        assert!(orderbook.assert_not_crossed().is_ok());
+
+        // A bid inserted at the best ask price without matching crosses the book
+        let leaf = LeafNode {
+            key: OrderId::from_parts(13 << 32, 1_000, Side::Bid).into(),
+            base_quantity: 1,
+            max_ts: u64::MAX,
+        };
+        orderbook.get_tree(Side::Bid).insert_leaf(&leaf).unwrap();
+        assert!(matches!(
+            orderbook.assert_not_crossed(),
+            Err(AoError::BookCrossed)
+        ));
+    }
+
+    #[test]
+    fn test_ob_post_orders_batch_partial() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+        orderbook
+            .post_orders_batch(
+                &[batch_order(Side::Ask, 12, 1_000, 9)],
+                &mut event_queue,
+                10,
+            )
+            .unwrap();
+
+        let orders = [
+            batch_order(Side::Bid, 10, 1_000, 1),
+            // Too small
+            batch_order(Side::Bid, 10, 5, 2),
+            // Crosses the spread
+            batch_order(Side::Bid, 12, 1_000, 3),
+            // Rejected by the market-level check
+            batch_order(Side::Ask, 50, 1_000, 4),
+            batch_order(Side::Ask, 13, 1_000, 5),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch_partial(&orders, &mut event_queue, 10, 0, 0, |order| {
+                if order.limit_price > 20 << 32 {
+                    return Err(RejectReason::PriceOutOfBand);
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            order_ids.iter().map(Option::is_some).collect::<Vec<_>>(),
+            vec![true, false, false, false, true]
+        );
+        assert_eq!(orderbook.levels(Side::Bid).len(), 1);
+        assert_eq!(orderbook.levels(Side::Ask).len(), 2);
+
+        let rejects = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Reject(r) => {
+                    let order = &orders[r.event.batch_index as usize];
+                    assert_eq!(r.callback_info, &order.callback_info);
+                    assert_eq!(r.event.side, order.side as u8);
+                    assert_eq!(
+                        (r.event.limit_price, r.event.base_size),
+                        (order.limit_price, order.base_qty)
+                    );
+                    (r.event.batch_index, r.event.reason().unwrap())
+                }
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejects,
+            vec![
+                (1, RejectReason::TooSmall),
+                (2, RejectReason::CrossesSpread),
+                (3, RejectReason::PriceOutOfBand),
+            ]
+        );
+        assert!(event_queue.validate().is_ok());
+
+        // The same batch is rejected as a whole when posted atomically
+        assert!(matches!(
+            orderbook.post_orders_batch(&orders[..3], &mut event_queue, 10),
+            Err(AoError::InvalidBaseQuantity)
+        ));
+        assert_eq!(orderbook.levels(Side::Bid)[0].order_count, 1);
+    }
+
+    #[test]
+    fn test_ob_post_orders_batch_partial_limits() {
+        let mut test_context = TestContext::new(1000, 1000);
+        let (mut orderbook, mut event_queue) = test_context.get();
+
+        let orders = [
+            batch_order(Side::Bid, 10, 1_000, 1),
+            batch_order(Side::Bid, 9, 1_000, 1),
+            // A third order for the same owner
+            batch_order(Side::Bid, 10, 1_000, 1),
+            // A third level on the bid side
+            batch_order(Side::Bid, 8, 1_000, 2),
+            // Joining an existing level is still allowed
+            batch_order(Side::Bid, 9, 1_000, 2),
+            batch_order(Side::Ask, 20, 1_000, 2),
+        ];
+        let order_ids = orderbook
+            .post_orders_batch_partial(&orders, &mut event_queue, 10, 2, 2, |_| Ok(()))
+            .unwrap();
+        assert_eq!(
+            order_ids.iter().map(Option::is_some).collect::<Vec<_>>(),
+            vec![true, true, false, false, true, true]
+        );
+        assert_eq!(
+            orderbook
+                .levels(Side::Bid)
+                .iter()
+                .map(|l| l.order_count)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(orderbook.levels(Side::Ask).len(), 1);
+
+        let rejects = event_queue
+            .iter()
+            .map(|e| match e {
+                EventRef::Reject(r) => (r.event.batch_index, r.event.reason().unwrap()),
+                _ => panic!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejects,
+            vec![
+                (2, RejectReason::TooManyOrders),
+                (3, RejectReason::TooManyLevels),
+            ]
+        );
+        assert!(event_queue.validate().is_ok());
+    }
 }
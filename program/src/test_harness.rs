@@ -0,0 +1,447 @@
+//! An in-memory market for unit tests.
+//!
+//! The market, event queue, bids and asks accounts are plain buffers, and the helpers run the same logic as the
+//! corresponding instruction processors once their accounts are loaded, without requiring a Solana runtime.
+use crate::{
+    error::AoError,
+    processor::{cancel_order, new_order},
+    state::{
+        critbit::Slab,
+        event_queue::{ConsumeSummary, EventQueue, OwnedEvent},
+        market_state::{MarketFlags, MarketState},
+        orderbook::{OrderBookState, OrderSummary},
+        AccountTag, OrderId, PostOnly, SelfTradeBehavior, Side,
+    },
+};
+use std::sync::{Mutex, MutexGuard};
+
+/// Installing syscall stubs replaces them for the whole process, so the tests which capture the program logs hold this
+/// lock until they have restored the previous stubs
+static SYSCALL_STUBS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Takes the syscall stubs lock, even if a test panicked while holding it
+pub(crate) fn lock_syscall_stubs() -> MutexGuard<'static, ()> {
+    SYSCALL_STUBS_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The callback info used by the test market, which identifies an order's owner
+pub(crate) type TestCallbackInfo = [u8; 32];
+
+/// An owned copy of an event popped off the test market's event queue
+pub(crate) type TestEvent = OwnedEvent<TestCallbackInfo>;
+
+pub(crate) struct TestMarket {
+    market_buffer: Vec<u8>,
+    bids_buffer: Vec<u8>,
+    asks_buffer: Vec<u8>,
+    event_queue_buffer: Vec<u8>,
+    /// The unix timestamp at which orders are placed, which expires the orders whose `max_ts` is before it
+    pub(crate) cur_ts: u64,
+}
+
+impl TestMarket {
+    /// Creates a market with a tick size of 1 (FP32) and a minimum order size of 1
+    pub(crate) fn new(order_capacity: usize, event_capacity: usize) -> Self {
+        let slab_size = Slab::<TestCallbackInfo>::compute_allocation_size(order_capacity);
+        let mut test_market = Self {
+            market_buffer: vec![0; 8 + MarketState::LEN],
+            bids_buffer: vec![0; slab_size],
+            asks_buffer: vec![0; slab_size],
+            event_queue_buffer: vec![
+                0;
+                EventQueue::<TestCallbackInfo>::compute_allocation_size(
+                    event_capacity
+                )
+            ],
+            cur_ts: 0,
+        };
+        let market_state = MarketState::initialize(&mut test_market.market_buffer).unwrap();
+        market_state.tick_size = 1 << 32;
+        market_state.min_base_order_size = 1;
+        Slab::<TestCallbackInfo>::initialize(
+            &mut test_market.asks_buffer,
+            &mut test_market.bids_buffer,
+        )
+        .unwrap();
+        EventQueue::<TestCallbackInfo>::from_buffer(
+            &mut test_market.event_queue_buffer,
+            AccountTag::Uninitialized,
+        )
+        .unwrap();
+        test_market
+    }
+
+    /// Loads the market's accounts from their buffers
+    pub(crate) fn get(
+        &mut self,
+    ) -> (
+        &mut MarketState,
+        OrderBookState<'_, TestCallbackInfo>,
+        EventQueue<'_, TestCallbackInfo>,
+    ) {
+        (
+            MarketState::from_buffer(&mut self.market_buffer, AccountTag::Market).unwrap(),
+            OrderBookState::new_safe(&mut self.bids_buffer, &mut self.asks_buffer).unwrap(),
+            EventQueue::from_buffer(&mut self.event_queue_buffer, AccountTag::EventQueue).unwrap(),
+        )
+    }
+
+    /// Places an order like the new_order instruction does
+    pub(crate) fn new_order(
+        &mut self,
+        params: new_order::Params<TestCallbackInfo>,
+    ) -> Result<OrderSummary, AoError> {
+        let cur_ts = self.cur_ts;
+        let (market_state, mut order_book, mut event_queue) = self.get();
+        event_queue.set_timestamp(cur_ts as i64);
+        new_order::execute(
+            market_state,
+            &mut order_book,
+            &mut event_queue,
+            params,
+            cur_ts,
+        )
+    }
+
+    /// Posts a post-only order of `base_qty` at `price` (in whole quote units per base unit)
+    pub(crate) fn post(
+        &mut self,
+        side: Side,
+        price: u64,
+        base_qty: u64,
+        owner: TestCallbackInfo,
+    ) -> Result<OrderSummary, AoError> {
+        self.new_order(new_order::Params {
+            max_base_qty: base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: price << 32,
+            side,
+            match_limit: 10,
+            callback_info: owner,
+            post_only: PostOnly::Enabled,
+            post_allowed: true,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        })
+    }
+
+    /// Matches an immediate-or-cancel order of up to `base_qty` against the book, up to `price` (in whole quote
+    /// units per base unit)
+    pub(crate) fn match_order(
+        &mut self,
+        side: Side,
+        price: u64,
+        base_qty: u64,
+        owner: TestCallbackInfo,
+    ) -> Result<OrderSummary, AoError> {
+        self.new_order(new_order::Params {
+            max_base_qty: base_qty,
+            max_quote_qty: u64::MAX,
+            limit_price: price << 32,
+            side,
+            match_limit: 10,
+            callback_info: owner,
+            post_only: PostOnly::Disabled,
+            post_allowed: false,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            max_ts: u64::MAX,
+        })
+    }
+
+    /// Cancels a resting order like the cancel_order instruction does
+    pub(crate) fn cancel(&mut self, order_id: OrderId) -> Result<OrderSummary, AoError> {
+        let (_, mut order_book, _) = self.get();
+        cancel_order::execute(&mut order_book, order_id)
+    }
+
+    /// Consumes up to `max_events` events like the consume_events instruction does, returning them along with the
+    /// crank's summary
+    pub(crate) fn crank(&mut self, max_events: u64) -> (Vec<TestEvent>, ConsumeSummary) {
+        let (_, _, mut event_queue) = self.get();
+        let mut events = vec![];
+        let summary = event_queue.consume_with_summary(max_events, |e| events.push(e.to_owned()));
+        (events, summary)
+    }
+}
+
+#[test]
+fn test_harness_post_match_crank() {
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+
+    let ask_10 = market
+        .post(Side::Ask, 10, 5, alice)
+        .unwrap()
+        .posted_order_id
+        .unwrap();
+    let ask_11 = market
+        .post(Side::Ask, 11, 5, alice)
+        .unwrap()
+        .posted_order_id
+        .unwrap();
+    // Post-only orders are never matched
+    let bid_12 = market.post(Side::Bid, 12, 1, bob).unwrap();
+    assert_eq!(bid_12.posted_order_id, None);
+    assert_eq!(bid_12.total_base_qty, 0);
+
+    // Bob takes the whole first level and part of the second one
+    let summary = market.match_order(Side::Bid, 11, 7, bob).unwrap();
+    assert_eq!(summary.posted_order_id, None);
+    assert_eq!(summary.total_base_qty, 7);
+    assert_eq!(summary.total_quote_qty, 5 * 10 + 2 * 11);
+    {
+        let (market_state, order_book, _) = market.get();
+        assert_eq!(market_state.last_trade_price(), Some(11 << 32));
+        assert_eq!(order_book.order_rank(ask_10), None);
+        assert_eq!(order_book.order_rank(ask_11), Some(0));
+    }
+
+    let (events, crank_summary) = market.crank(10);
+    assert_eq!(crank_summary.events_consumed, events.len() as u64);
+    let fills = events
+        .iter()
+        .filter_map(|e| match e {
+            TestEvent::Fill {
+                event,
+                maker_callback_info,
+                taker_callback_info,
+            } => {
+                assert_eq!((maker_callback_info, taker_callback_info), (&alice, &bob));
+                Some((event.maker_order_id, event.base_size, event.quote_size))
+            }
+            TestEvent::Out { .. } | TestEvent::Reject { .. } | TestEvent::Tick { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(fills, vec![(ask_10, 5, 50), (ask_11, 2, 22)]);
+    // The filled maker order leaves the book without an Out event
+    assert_eq!(events.len(), fills.len());
+    let (_, crank_summary) = market.crank(10);
+    assert_eq!(crank_summary.events_consumed, 0);
+
+    // What is left of the second order can be cancelled once
+    assert_eq!(market.cancel(ask_11).unwrap().total_base_qty, 3);
+    assert!(matches!(market.cancel(ask_11), Err(AoError::OrderNotFound)));
+    assert!(market.get().1.is_empty());
+}
+
+#[test]
+fn test_harness_max_orders_per_owner() {
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+    market.get().0.max_orders_per_owner = 3;
+
+    market.post(Side::Bid, 10, 1, alice).unwrap();
+    market.post(Side::Ask, 20, 1, alice).unwrap();
+    market.post(Side::Ask, 20, 1, alice).unwrap();
+    assert!(matches!(
+        market.post(Side::Bid, 9, 1, alice),
+        Err(AoError::TooManyOrders)
+    ));
+    // Other owners aren't affected, and orders which don't rest in the book are still accepted
+    market.post(Side::Bid, 9, 1, bob).unwrap();
+    assert_eq!(
+        market
+            .match_order(Side::Ask, 9, 1, alice)
+            .unwrap()
+            .total_base_qty,
+        1
+    );
+
+    // A slot frees up once one of the owner's orders leaves the book
+    market.match_order(Side::Bid, 20, 1, bob).unwrap();
+    market.post(Side::Bid, 9, 1, alice).unwrap();
+    assert!(matches!(
+        market.post(Side::Bid, 8, 1, alice),
+        Err(AoError::TooManyOrders)
+    ));
+
+    market.get().0.max_orders_per_owner = 0;
+    market.post(Side::Bid, 8, 1, alice).unwrap();
+}
+
+#[test]
+fn test_harness_side_paused() {
+    let alice = [1; 32];
+    let mut market = TestMarket::new(100, 100);
+    market.get().0.flags.set(MarketFlags::BIDS_PAUSED);
+
+    assert!(matches!(
+        market.post(Side::Bid, 10, 1, alice),
+        Err(AoError::SidePaused)
+    ));
+    assert!(market.get().1.is_empty());
+    market.post(Side::Ask, 20, 1, alice).unwrap();
+    // Taking is paused as well on the paused side
+    assert!(matches!(
+        market.match_order(Side::Bid, 20, 1, alice),
+        Err(AoError::SidePaused)
+    ));
+
+    market.get().0.flags.clear(MarketFlags::BIDS_PAUSED);
+    market.get().0.flags.set(MarketFlags::ASKS_PAUSED);
+    market.post(Side::Bid, 10, 1, alice).unwrap();
+    assert!(matches!(
+        market.post(Side::Ask, 21, 1, alice),
+        Err(AoError::SidePaused)
+    ));
+}
+
+#[test]
+fn test_harness_base_lot_size() {
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+    market.get().0.base_lot_size = 10;
+
+    market.post(Side::Ask, 20, 30, alice).unwrap();
+    assert!(matches!(
+        market.post(Side::Ask, 21, 35, alice),
+        Err(AoError::InvalidLotSize)
+    ));
+    assert!(matches!(
+        market.match_order(Side::Bid, 20, 15, bob),
+        Err(AoError::InvalidLotSize)
+    ));
+    assert_eq!(
+        market
+            .match_order(Side::Bid, 20, 20, bob)
+            .unwrap()
+            .total_base_qty,
+        20
+    );
+    let resting = market
+        .get()
+        .1
+        .iter_orders(Side::Ask)
+        .map(|(_, _, base_size, _)| base_size)
+        .collect::<Vec<_>>();
+    assert_eq!(resting, vec![10]);
+}
+
+#[test]
+fn test_harness_max_levels_per_side() {
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+    market.get().0.max_levels_per_side = 2;
+
+    market.post(Side::Bid, 10, 1, alice).unwrap();
+    market.post(Side::Bid, 9, 1, alice).unwrap();
+    market.post(Side::Ask, 20, 1, alice).unwrap();
+    // Existing levels still accept orders
+    market.post(Side::Bid, 10, 1, bob).unwrap();
+    market.post(Side::Bid, 9, 1, bob).unwrap();
+    market.post(Side::Ask, 21, 1, bob).unwrap();
+    assert!(matches!(
+        market.post(Side::Bid, 11, 1, bob),
+        Err(AoError::TooManyLevels)
+    ));
+    assert!(matches!(
+        market.post(Side::Ask, 22, 1, bob),
+        Err(AoError::TooManyLevels)
+    ));
+
+    // Orders which don't rest in the book are still accepted
+    assert_eq!(
+        market
+            .match_order(Side::Ask, 8, 2, bob)
+            .unwrap()
+            .total_base_qty,
+        2
+    );
+
+    market.get().0.max_levels_per_side = 0;
+    market.post(Side::Ask, 23, 1, alice).unwrap();
+}
+
+#[test]
+fn test_harness_taker_fee() {
+    use crate::utils::compute_taker_fee;
+
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+    market.get().0.taker_fee_bps = 25;
+
+    market.post(Side::Ask, 10, 333, alice).unwrap();
+    market.post(Side::Ask, 11, 333, alice).unwrap();
+    market.post(Side::Ask, 13, 333, alice).unwrap();
+    let summary = market.match_order(Side::Bid, 13, 777, bob).unwrap();
+    assert_eq!(summary.total_quote_qty, 333 * 10 + 333 * 11 + 111 * 13);
+
+    // Each fill's fee is rounded up on its own, so the total can exceed the fee on the total quote size
+    let (events, _) = market.crank(10);
+    let fill_fees = events
+        .iter()
+        .filter_map(|e| match e {
+            TestEvent::Fill { event, .. } => Some(compute_taker_fee(event.quote_size, 25).unwrap()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(fill_fees, vec![9, 10, 4]);
+    assert_eq!(summary.total_fee, fill_fees.iter().sum::<u64>());
+    assert!(summary.total_fee > compute_taker_fee(summary.total_quote_qty, 25).unwrap());
+
+    // Posting and cancelling orders is free
+    let posted = market.post(Side::Bid, 12, 1, bob).unwrap();
+    assert_eq!(posted.total_fee, 0);
+    let cancelled = market.cancel(posted.posted_order_id.unwrap()).unwrap();
+    assert_eq!(cancelled.total_fee, 0);
+}
+
+#[test]
+fn test_harness_slide_and_expiry() {
+    let (alice, bob) = ([1; 32], [2; 32]);
+    let mut market = TestMarket::new(100, 100);
+    let order = |side, price: u64, max_ts, post_only, owner| new_order::Params {
+        max_base_qty: 1,
+        max_quote_qty: u64::MAX,
+        limit_price: price << 32,
+        side,
+        match_limit: 10,
+        callback_info: owner,
+        post_only,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        max_ts,
+    };
+
+    // A sliding bid which would cross the spread is repriced one tick below the best ask
+    let ask_10 = market
+        .new_order(order(Side::Ask, 10, 5, PostOnly::Enabled, alice))
+        .unwrap()
+        .posted_order_id
+        .unwrap();
+    let slid = market
+        .new_order(order(Side::Bid, 12, u64::MAX, PostOnly::Slide, bob))
+        .unwrap()
+        .posted_order_id
+        .unwrap();
+    assert_eq!(slid.price(), 9 << 32);
+
+    // Sliding below the tick size can't be posted
+    market.cancel(slid).unwrap();
+    market.post(Side::Ask, 1, 1, alice).unwrap();
+    assert!(matches!(
+        market.new_order(order(Side::Bid, 1, u64::MAX, PostOnly::Slide, bob)),
+        Err(AoError::InvalidLimitPrice)
+    ));
+    market.crank(10);
+
+    // Once expired, the ask at 10 is removed instead of being matched
+    market.cur_ts = 6;
+    let mut bid = order(Side::Bid, 10, u64::MAX, PostOnly::Disabled, bob);
+    bid.max_base_qty = 2;
+    let summary = market.new_order(bid).unwrap();
+    assert_eq!(summary.total_base_qty, 1);
+    assert_eq!(summary.total_quote_qty, 1);
+    let (events, _) = market.crank(10);
+    let outs = events
+        .iter()
+        .filter_map(|e| match e {
+            TestEvent::Out { event, .. } => Some(event.order_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(outs, vec![ask_10]);
+    assert_eq!(market.get().1.order_rank(ask_10), None);
+}
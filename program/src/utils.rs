@@ -1,6 +1,14 @@
-use crate::{error::AoError, state::Side};
+use crate::{
+    error::AoError,
+    processor::post_orders_batch,
+    state::{market_state::MarketState, AccountTag, Side, ORDER_SUMMARY_SIZE},
+};
 
-use solana_program::{account_info::AccountInfo, pubkey::Pubkey};
+use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::Pod;
+use std::convert::TryFrom;
+
+use solana_program::{account_info::AccountInfo, hash::hashv, pubkey::Pubkey};
 
 use crate::state::orderbook::{CallbackInfo, OrderBookState};
 
@@ -35,6 +43,43 @@ pub(crate) fn check_account_owner(
     Ok(())
 }
 
+/// Checks that an account's data starts with the expected account tag
+pub fn check_tag(account: &AccountInfo, expected: AccountTag) -> Result<(), AoError> {
+    if account.data.borrow().first() != Some(&(expected as u8)) {
+        return Err(AoError::WrongAccountTag);
+    }
+    Ok(())
+}
+
+/// Checks that an account is owned by the given program
+pub fn check_owner(account: &AccountInfo, program_id: &Pubkey) -> Result<(), AoError> {
+    check_account_owner(account, &program_id.to_bytes(), AoError::WrongAccountOwner)
+}
+
+/// Checks that an account was passed as writable to the instruction
+pub fn check_writable(account: &AccountInfo) -> Result<(), AoError> {
+    if !account.is_writable {
+        return Err(AoError::AccountNotWritable);
+    }
+    Ok(())
+}
+
+/// Checks that the bids and asks accounts are the market's own order book accounts and that they haven't been swapped.
+pub(crate) fn check_book_accounts(
+    bids: &AccountInfo,
+    asks: &AccountInfo,
+    market_state: &MarketState,
+) -> Result<(), AoError> {
+    if bids.key != &market_state.bids
+        || asks.key != &market_state.asks
+        || check_tag(bids, AccountTag::Bids).is_err()
+        || check_tag(asks, AccountTag::Asks).is_err()
+    {
+        return Err(AoError::WrongBookAccount);
+    }
+    Ok(())
+}
+
 /// This util is used to return the orderbook's spread (best_bid_price, best_ask_price) with both values in FP32 format
 pub fn get_spread<'ob, 'b: 'ob, C: CallbackInfo + PartialEq>(
     bids_account: &'ob AccountInfo<'b>,
@@ -60,6 +105,326 @@ pub fn round_price(tick_size: u64, limit_price: u64, side: Side) -> u64 {
     }
 }
 
+/// Reads a little-endian signed 64-bit integer from a `callback_info` byte slice at a given byte offset.
+///
+/// Returns `None` if the slice is too short to contain the field.
+pub fn read_i64_le(callback_info: &[u8], offset: usize) -> Option<i64> {
+    let bytes = callback_info.get(offset..offset.checked_add(8)?)?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    Some(i64::from_le_bytes(buf))
+}
+
+/// Writes a signed 64-bit integer in little-endian order to a `callback_info` byte slice at a given byte offset.
+///
+/// Returns `None` and leaves the slice untouched if it is too short to contain the field.
+pub fn write_i64_le(callback_info: &mut [u8], offset: usize, value: i64) -> Option<()> {
+    let bytes = callback_info.get_mut(offset..offset.checked_add(8)?)?;
+    bytes.copy_from_slice(&value.to_le_bytes());
+    Some(())
+}
+
+/// Builds a callback info of the program's callback info type from raw bytes, such as metadata of variable length
+/// supplied by a caller program's user, padding it with zeros.
+///
+/// Events hold callback infos of a fixed size, so bytes longer than the callback info type are rejected with
+/// [`AoError::CallbackInfoLengthMismatch`] rather than truncated.
+pub fn callback_info_from_bytes<C: Pod>(bytes: &[u8]) -> Result<C, AoError> {
+    let mut callback_info = C::zeroed();
+    bytemuck::bytes_of_mut(&mut callback_info)
+        .get_mut(..bytes.len())
+        .ok_or(AoError::CallbackInfoLengthMismatch)?
+        .copy_from_slice(bytes);
+    Ok(callback_info)
+}
+
+/// Casts the bytes holding `len` callback infos of an account to a slice of callback infos.
+///
+/// The callback info type is zero-sized for markets which don't attach any metadata to their orders. bytemuck won't
+/// cast bytes to zero-sized values, whose count can't be derived from the bytes, so `len` of them are built instead.
+/// Zero-sized values don't take up any memory, so this doesn't allocate.
+pub(crate) fn cast_callback_infos_mut<C: Pod>(bytes: &mut [u8], len: usize) -> &mut [C] {
+    if std::mem::size_of::<C>() == 0 {
+        return Box::leak(vec![C::zeroed(); len].into_boxed_slice());
+    }
+    bytemuck::cast_slice_mut(bytes)
+}
+
+/// Read-only counterpart to [`cast_callback_infos_mut`], for bytes which may not be aligned for the callback info type
+pub(crate) fn try_cast_callback_infos<C: Pod>(bytes: &[u8], len: usize) -> Result<&[C], AoError> {
+    if std::mem::size_of::<C>() == 0 {
+        return Ok(Box::leak(vec![C::zeroed(); len].into_boxed_slice()));
+    }
+    bytemuck::try_cast_slice(bytes).map_err(|_| AoError::UnalignedAccountData)
+}
+
+/// Describes how the result of a division is rounded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Rounds towards positive infinity
+    Up,
+    /// Rounds towards zero
+    Down,
+    /// Rounds to the nearest integer, with ties rounded up
+    Nearest,
+}
+
+/// Divides `value_num` by `value_den`, rounding the quotient according to `mode`.
+///
+/// All the quote and fee computations of the AOB go through this, so that they round consistently.
+/// Returns `None` if the rounded quotient doesn't fit in a u64.
+pub fn apply_rounding(value_num: u128, value_den: u128, mode: Rounding) -> Option<u64> {
+    let quotient = value_num / value_den;
+    let remainder = value_num % value_den;
+    // The remainder is lower than the denominator, so neither the comparisons nor the increment can overflow
+    let round_up = match mode {
+        Rounding::Up => remainder != 0,
+        Rounding::Down => false,
+        Rounding::Nearest => remainder >= value_den - remainder,
+    };
+    u64::try_from(quotient + round_up as u128).ok()
+}
+
+/// Computes the quote size of a given base size at a given price (FP32), using a u128 intermediate product.
+///
+/// The result is rounded up for bids and down for asks, so that rounding always favors the maker.
+/// Returns [`AoError::QuoteOverflow`] if the quote size doesn't fit in a u64.
+pub fn compute_quote_size(base_size: u64, price: u64, side: Side) -> Result<u64, AoError> {
+    // (2^64 - 1)^2 < 2^128, this can't overflow
+    let product = (base_size as u128) * (price as u128);
+    let rounding = match side {
+        Side::Bid => Rounding::Up,
+        Side::Ask => Rounding::Down,
+    };
+    apply_rounding(product, 1 << 32, rounding).ok_or(AoError::QuoteOverflow)
+}
+
+/// Computes the price (FP32) at which a taker on `taker_side` with a given limit price fills against a maker order.
+///
+/// When the taker's limit price is better than the maker's price, `maker_price_improvement_bps` basis points of the
+/// difference are given to the maker and the rest to the taker. A value of 0 fills at the maker's price, and values of
+/// 10_000 or more fill at the taker's limit price.
+pub fn compute_fill_price(
+    maker_price: u64,
+    limit_price: u64,
+    taker_side: Side,
+    maker_price_improvement_bps: u16,
+) -> u64 {
+    let maker_price_improvement_bps = std::cmp::min(maker_price_improvement_bps, 10_000);
+    let improvement = match taker_side {
+        Side::Bid => limit_price.saturating_sub(maker_price),
+        Side::Ask => maker_price.saturating_sub(limit_price),
+    };
+    // Fits in a u64 since maker_price_improvement_bps is at most 10_000
+    let maker_share = apply_rounding(
+        (improvement as u128) * (maker_price_improvement_bps as u128),
+        10_000,
+        Rounding::Down,
+    )
+    .unwrap();
+    match taker_side {
+        Side::Bid => maker_price + maker_share,
+        Side::Ask => maker_price - maker_share,
+    }
+}
+
+/// Computes the taker fee owed for a fill of a given quote size, given the market's taker fee in basis points.
+///
+/// The fee is rounded up, so that splitting an order into several fills can't be used to avoid it.
+/// Returns [`AoError::InvalidFeeBps`] if `taker_fee_bps` is greater than 10_000, as the fee would exceed the quote size.
+pub fn compute_taker_fee(quote_size: u64, taker_fee_bps: u16) -> Result<u64, AoError> {
+    if taker_fee_bps > 10_000 {
+        return Err(AoError::InvalidFeeBps);
+    }
+    // The fee is at most quote_size since taker_fee_bps is at most 10_000
+    Ok(apply_rounding(
+        (quote_size as u128) * (taker_fee_bps as u128),
+        10_000,
+        Rounding::Up,
+    )
+    .unwrap())
+}
+
+/// Reads the optional value written to a register account by an instruction, such as an
+/// [`OrderSummary`][`crate::state::OrderSummary`].
+///
+/// Returns `None` rather than panicking when no value was written to the register, as is the case for a freshly
+/// allocated register, or when the register's content can't be deserialized as a `T`.
+pub fn read_register_opt<T: BorshDeserialize>(register_data: &[u8]) -> Option<T> {
+    let mut data = register_data;
+    Option::<T>::deserialize(&mut data).ok().flatten()
+}
+
+/// Zeroes out a register account's data, after which [`read_register_opt`] returns `None` until a new value is written.
+pub fn clear_register(register_data: &mut [u8]) {
+    register_data.iter_mut().for_each(|b| *b = 0);
+}
+
+/// Reads the optional value written to a register account like [`read_register_opt`], then clears the register so
+/// that the same value can't be mistaken for a fresh one by a subsequent read.
+pub fn read_and_clear_register<T: BorshDeserialize>(register_data: &mut [u8]) -> Option<T> {
+    let value = read_register_opt(register_data);
+    clear_register(register_data);
+    value
+}
+
+/// Reads a value written to a register account at a given byte offset by [`write_register_at`].
+///
+/// Returns `None` if the offset is past the end of the register or if the bytes at the offset can't be
+/// deserialized as a `T`.
+pub fn read_register_at<T: BorshDeserialize>(register_data: &[u8], offset: usize) -> Option<T> {
+    let mut data = register_data.get(offset..)?;
+    T::deserialize(&mut data).ok()
+}
+
+/// Writes a value to a register account at a given byte offset, so that several values can share the register.
+///
+/// Returns [`AoError::RegisterOutOfBounds`] and leaves the register untouched if the serialized value doesn't fit
+/// between the offset and the end of the register.
+pub fn write_register_at<T: BorshSerialize>(
+    register_data: &mut [u8],
+    offset: usize,
+    value: &T,
+) -> Result<(), AoError> {
+    let bytes = value
+        .try_to_vec()
+        .map_err(|_| AoError::RegisterOutOfBounds)?;
+    let end = offset
+        .checked_add(bytes.len())
+        .ok_or(AoError::RegisterOutOfBounds)?;
+    register_data
+        .get_mut(offset..end)
+        .ok_or(AoError::RegisterOutOfBounds)?
+        .copy_from_slice(&bytes);
+    Ok(())
+}
+
+/// The largest serialized size of an instruction's output written to the register account, reached by the order ids
+/// of a full post_orders_batch instruction. This is the minimum size of a register account.
+pub const INSTRUCTION_OUTPUT_SIZE: usize = max_usize(
+    ORDER_SUMMARY_SIZE as usize + 1,
+    4 + 16 * post_orders_batch::MAX_BATCH_SIZE,
+);
+
+const fn max_usize(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Computes the size of a register account which holds an instruction's output followed by `extra_len` bytes of
+/// caller-defined values, such as a cursor or a watermark.
+///
+/// The caller-defined values start at offset [`INSTRUCTION_OUTPUT_SIZE`] and are written with [`write_register_at`],
+/// so that they're never overwritten by an instruction's output.
+pub fn register_size(extra_len: usize) -> usize {
+    INSTRUCTION_OUTPUT_SIZE + extra_len
+}
+
+/// Writes an instruction's output to the register account once the instruction has been processed.
+///
+/// The register has a single writer: nothing may write to it while an instruction is being processed, and the output
+/// is written last. `register_before` is a copy of the register taken before processing the instruction, and the write
+/// fails with [`AoError::RegisterClobbered`] if the register was modified in the meantime, in which case the register
+/// is left untouched.
+pub fn write_register_output<T: BorshSerialize>(
+    register_data: &mut [u8],
+    register_before: &[u8],
+    output: &T,
+) -> Result<(), AoError> {
+    if register_data != register_before {
+        return Err(AoError::RegisterClobbered);
+    }
+    write_register_at(register_data, 0, output)
+}
+
+/// Hashes two sibling nodes of a Merkle tree into their parent node.
+///
+/// Inner nodes and leaves are hashed with different prefixes, so that a leaf can't be passed off as an inner node.
+pub fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&[1], left, right]).to_bytes()
+}
+
+fn merkle_parent_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => merkle_node(left, right),
+            [last] => *last,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Computes the root of the Merkle tree whose leaves are the given hashes, or `None` if there are no leaves.
+///
+/// Each level pairs up consecutive nodes. The last node of a level with an odd number of nodes has no sibling and is
+/// carried over to the next level as is.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = merkle_parent_level(&level);
+    }
+    level.pop()
+}
+
+/// Computes the proof that the leaf at position `index` is included in the Merkle tree built by [`merkle_root`]:
+/// the siblings of the nodes on the path from the leaf to the root, starting from the leaf's sibling.
+///
+/// Returns `None` if there is no leaf at position `index`.
+pub fn merkle_proof(leaves: &[[u8; 32]], mut index: usize) -> Option<Vec<[u8; 32]>> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut proof = vec![];
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        level = merkle_parent_level(&level);
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Checks a proof generated by [`merkle_proof`] that `leaf` is the leaf at position `index` of a Merkle tree with
+/// `leaf_count` leaves and the given root.
+///
+/// For the tree of a crank's consumed events, `leaf_count` is the summary's `events_consumed`.
+pub fn verify_merkle_proof(
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    mut index: usize,
+    leaf_count: usize,
+    proof: &[[u8; 32]],
+) -> bool {
+    if index >= leaf_count {
+        return false;
+    }
+    let mut proof = proof.iter();
+    let mut node = *leaf;
+    let mut width = leaf_count;
+    while width > 1 {
+        if index % 2 == 1 {
+            match proof.next() {
+                Some(sibling) => node = merkle_node(sibling, &node),
+                None => return false,
+            }
+        } else if index + 1 < width {
+            match proof.next() {
+                Some(sibling) => node = merkle_node(&node, sibling),
+                None => return false,
+            }
+        }
+        index /= 2;
+        width = (width + 1) / 2;
+    }
+    proof.next().is_none() && &node == root
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +439,529 @@ mod tests {
         let rounded_price_ask = round_price(tick_size, price, Side::Ask);
         assert_eq!(rounded_price_ask, (5.5 * 2.0f64.powi(32)) as u64);
     }
+
+    #[test]
+    fn test_i64_le_round_trip() {
+        let mut callback_info = [0u8; 32];
+        for &(offset, value) in &[(0, 42), (8, -42), (17, i64::MIN), (24, i64::MAX), (3, -1)] {
+            write_i64_le(&mut callback_info, offset, value).unwrap();
+            assert_eq!(read_i64_le(&callback_info, offset), Some(value));
+        }
+        write_i64_le(&mut callback_info, 0, -2).unwrap();
+        assert_eq!(
+            &callback_info[..8],
+            &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+
+        assert_eq!(read_i64_le(&callback_info, 25), None);
+        assert_eq!(read_i64_le(&callback_info, usize::MAX), None);
+        let before = callback_info;
+        assert_eq!(write_i64_le(&mut callback_info, 25, 1), None);
+        assert_eq!(callback_info, before);
+    }
+
+    #[test]
+    fn test_callback_info_from_bytes() {
+        let callback_info = callback_info_from_bytes::<[u8; 32]>(&[7; 32]).unwrap();
+        assert_eq!(callback_info, [7; 32]);
+        let callback_info = callback_info_from_bytes::<[u8; 32]>(&[7; 5]).unwrap();
+        assert_eq!(callback_info[..5], [7; 5]);
+        assert_eq!(callback_info[5..], [0; 27]);
+        assert_eq!(callback_info_from_bytes::<[u8; 32]>(&[]).unwrap(), [0; 32]);
+
+        // A longer callback info would overrun the event's callback info slot
+        assert!(matches!(
+            callback_info_from_bytes::<[u8; 32]>(&[7; 33]),
+            Err(AoError::CallbackInfoLengthMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_read_register_opt() {
+        use crate::state::{OrderSummary, ORDER_SUMMARY_SIZE};
+        use borsh::BorshSerialize;
+
+        let mut register = [0u8; ORDER_SUMMARY_SIZE as usize];
+        assert!(read_register_opt::<OrderSummary>(&register).is_none());
+        assert!(read_register_opt::<OrderSummary>(&[]).is_none());
+
+        let order_summary = OrderSummary {
+            posted_order_id: None,
+            total_base_qty: 1,
+            total_quote_qty: 2,
+            total_base_qty_posted: 3,
+            total_fee: 0,
+        };
+        Some(order_summary)
+            .serialize(&mut (&mut register as &mut [u8]))
+            .unwrap();
+        let read = read_register_opt::<OrderSummary>(&register).unwrap();
+        assert_eq!(read.total_base_qty, 1);
+        assert_eq!(read.total_quote_qty, 2);
+        assert_eq!(read.total_base_qty_posted, 3);
+
+        // Truncated registers are handled gracefully as well
+        assert!(read_register_opt::<OrderSummary>(&register[..10]).is_none());
+    }
+
+    #[test]
+    fn test_read_and_clear_register() {
+        use crate::state::{OrderId, OrderSummary, ORDER_SUMMARY_SIZE};
+        use borsh::BorshSerialize;
+
+        let mut register = [0u8; ORDER_SUMMARY_SIZE as usize + 1];
+        assert!(read_and_clear_register::<OrderSummary>(&mut register).is_none());
+
+        Some(OrderSummary {
+            posted_order_id: Some(OrderId(42)),
+            total_base_qty: 1,
+            total_quote_qty: 2,
+            total_base_qty_posted: 3,
+            total_fee: 0,
+        })
+        .serialize(&mut (&mut register as &mut [u8]))
+        .unwrap();
+        let read = read_and_clear_register::<OrderSummary>(&mut register).unwrap();
+        assert_eq!(read.posted_order_id, Some(OrderId(42)));
+        assert_eq!(read.total_base_qty, 1);
+
+        // The value was consumed by the first read
+        assert_eq!(register, [0; ORDER_SUMMARY_SIZE as usize + 1]);
+        assert!(read_register_opt::<OrderSummary>(&register).is_none());
+        assert!(read_and_clear_register::<OrderSummary>(&mut register).is_none());
+    }
+
+    #[test]
+    fn test_write_register_output() {
+        use crate::state::{OrderId, OrderSummary, ORDER_SUMMARY_SIZE};
+
+        let summary = |base_qty| {
+            Some(OrderSummary {
+                posted_order_id: Some(OrderId(42)),
+                total_base_qty: base_qty,
+                total_quote_qty: 2,
+                total_base_qty_posted: 3,
+                total_fee: 0,
+            })
+        };
+        // The register still holds the output of a previous instruction
+        let mut register = [0u8; ORDER_SUMMARY_SIZE as usize];
+        write_register_at(&mut register, 0, &summary(1)).unwrap();
+
+        // A write interleaved with the instruction's processing is detected, and the register is left as is
+        let register_before = register;
+        write_register_at(&mut register, 0, &summary(5)).unwrap();
+        let clobbered = register;
+        assert!(matches!(
+            write_register_output(&mut register, &register_before, &summary(7)),
+            Err(AoError::RegisterClobbered)
+        ));
+        assert_eq!(register, clobbered);
+
+        // Without interleaved writes, the final output overwrites the previous one
+        let register_before = register;
+        write_register_output(&mut register, &register_before, &summary(7)).unwrap();
+        let read = read_register_opt::<OrderSummary>(&register).unwrap();
+        assert_eq!(read.posted_order_id, Some(OrderId(42)));
+        assert_eq!(read.total_base_qty, 7);
+        assert_eq!(read.total_base_qty_posted, 3);
+    }
+
+    #[test]
+    fn test_order_summary_register_round_trip() {
+        use crate::processor::InstructionOutput;
+        use crate::state::{OrderId, OrderSummary, ORDER_SUMMARY_SIZE};
+
+        let order_summary = || OrderSummary {
+            posted_order_id: Some(OrderId(0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10)),
+            total_base_qty: 1_000,
+            total_quote_qty: 11_500,
+            total_base_qty_posted: 250,
+            total_fee: 12,
+        };
+        assert_eq!(
+            order_summary().try_to_vec().unwrap().len(),
+            ORDER_SUMMARY_SIZE as usize
+        );
+
+        // The instruction output fits in the smallest register
+        let mut register = vec![0u8; register_size(0)];
+        let output = InstructionOutput::OrderSummary(Some(order_summary()));
+        assert!(output.try_to_vec().unwrap().len() <= register.len());
+        let register_before = register.clone();
+        write_register_output(&mut register, &register_before, &output).unwrap();
+        assert_eq!(
+            read_register_opt::<OrderSummary>(&register),
+            Some(order_summary())
+        );
+
+        // An instruction without an order summary reads back as none
+        let register_before = register.clone();
+        write_register_output(
+            &mut register,
+            &register_before,
+            &InstructionOutput::OrderSummary(None),
+        )
+        .unwrap();
+        assert_eq!(read_register_opt::<OrderSummary>(&register), None);
+    }
+
+    #[test]
+    fn test_register_at_offsets() {
+        use crate::state::{OrderId, OrderSummary, ORDER_SUMMARY_SIZE};
+
+        let cursor_offset = ORDER_SUMMARY_SIZE as usize;
+        let mut register = vec![0u8; cursor_offset + 16];
+        let order_summary = OrderSummary {
+            posted_order_id: Some(OrderId(7)),
+            total_base_qty: 1,
+            total_quote_qty: 2,
+            total_base_qty_posted: 3,
+            total_fee: 0,
+        };
+        write_register_at(&mut register, 0, &order_summary).unwrap();
+        write_register_at(&mut register, cursor_offset, &OrderId(42)).unwrap();
+
+        let read = read_register_at::<OrderSummary>(&register, 0).unwrap();
+        assert_eq!(read.posted_order_id, Some(OrderId(7)));
+        assert_eq!(read.total_base_qty_posted, 3);
+        assert_eq!(
+            read_register_at::<OrderId>(&register, cursor_offset),
+            Some(OrderId(42))
+        );
+
+        // Values which don't fit are rejected without touching the register
+        let before = register.clone();
+        for &offset in &[cursor_offset + 1, register.len(), usize::MAX] {
+            assert!(matches!(
+                write_register_at(&mut register, offset, &OrderId(1)),
+                Err(AoError::RegisterOutOfBounds)
+            ));
+        }
+        assert_eq!(register, before);
+        assert_eq!(
+            read_register_at::<OrderId>(&register, cursor_offset + 1),
+            None
+        );
+        assert_eq!(read_register_at::<u8>(&register, usize::MAX), None);
+    }
+
+    #[test]
+    fn test_register_size() {
+        use crate::processor::InstructionOutput;
+        use crate::state::{event_queue::ConsumeSummary, OrderId, OrderSummary};
+
+        // An order summary followed by a cursor and a watermark
+        let extra_len = 16 + 8;
+        let cursor_offset = INSTRUCTION_OUTPUT_SIZE;
+        let watermark_offset = cursor_offset + 16;
+        let mut register = vec![0u8; register_size(extra_len)];
+        let output = InstructionOutput::OrderSummary(Some(OrderSummary {
+            posted_order_id: Some(OrderId(u128::MAX)),
+            total_base_qty: u64::MAX,
+            total_quote_qty: u64::MAX,
+            total_base_qty_posted: u64::MAX,
+            total_fee: u64::MAX,
+        }));
+        let register_before = register.clone();
+        write_register_output(&mut register, &register_before, &output).unwrap();
+        write_register_at(&mut register, cursor_offset, &OrderId(42)).unwrap();
+        write_register_at(&mut register, watermark_offset, &7u64).unwrap();
+
+        let read = read_register_opt::<OrderSummary>(&register).unwrap();
+        assert_eq!(read.posted_order_id, Some(OrderId(u128::MAX)));
+        assert_eq!(read.total_fee, u64::MAX);
+        assert_eq!(
+            read_register_at::<OrderId>(&register, cursor_offset),
+            Some(OrderId(42))
+        );
+        assert_eq!(
+            read_register_at::<u64>(&register, watermark_offset),
+            Some(7)
+        );
+
+        // A smaller output leaves the caller-defined values untouched
+        let output = InstructionOutput::ConsumeSummary(ConsumeSummary {
+            crank_fee: 1,
+            events_consumed: 2,
+            merkle_root: None,
+        });
+        let register_before = register.clone();
+        write_register_output(&mut register, &register_before, &output).unwrap();
+        assert_eq!(
+            read_register_at::<ConsumeSummary>(&register, 0).map(|s| s.events_consumed),
+            Some(2)
+        );
+        assert_eq!(register[cursor_offset..], register_before[cursor_offset..]);
+
+        // The ids of a full batch fit before the caller-defined values
+        let order_ids = vec![OrderId(u128::MAX); post_orders_batch::MAX_BATCH_SIZE];
+        let output = InstructionOutput::OrderIds(order_ids.clone());
+        let register_before = register.clone();
+        write_register_output(&mut register, &register_before, &output).unwrap();
+        assert_eq!(
+            read_register_at::<Vec<OrderId>>(&register, 0),
+            Some(order_ids)
+        );
+        assert_eq!(register[cursor_offset..], register_before[cursor_offset..]);
+
+        // A register which is a byte short can't hold the watermark
+        let mut register = vec![0u8; register_size(extra_len) - 1];
+        assert!(matches!(
+            write_register_at(&mut register, watermark_offset, &7u64),
+            Err(AoError::RegisterOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_merkle_proofs() {
+        assert_eq!(merkle_root(&[]), None);
+        assert_eq!(merkle_proof(&[], 0), None);
+        assert_eq!(merkle_root(&[[1; 32]]), Some([1; 32]));
+
+        for leaf_count in 1..=9usize {
+            let leaves = (0..leaf_count)
+                .map(|i| hashv(&[&[0], &[i as u8]]).to_bytes())
+                .collect::<Vec<_>>();
+            let root = merkle_root(&leaves).unwrap();
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, index).unwrap();
+                assert!(verify_merkle_proof(&root, leaf, index, leaf_count, &proof));
+                // The proof only holds for the leaf's position and the tree's size
+                if leaf_count > 1 {
+                    let other_index = (index + 1) % leaf_count;
+                    assert!(!verify_merkle_proof(
+                        &root,
+                        leaf,
+                        other_index,
+                        leaf_count,
+                        &proof
+                    ));
+                }
+                assert!(!verify_merkle_proof(
+                    &root,
+                    leaf,
+                    index,
+                    leaf_count + 1,
+                    &proof
+                ));
+                assert!(!verify_merkle_proof(
+                    &root,
+                    &[0xff; 32],
+                    index,
+                    leaf_count,
+                    &proof
+                ));
+            }
+            assert_eq!(merkle_proof(&leaves, leaf_count), None);
+            assert!(!verify_merkle_proof(
+                &root,
+                &leaves[0],
+                leaf_count,
+                leaf_count,
+                &[]
+            ));
+        }
+    }
+
+    #[test]
+    fn test_check_book_accounts() {
+        use bytemuck::Zeroable;
+        use solana_program::pubkey::Pubkey;
+
+        let (bids_key, asks_key, owner) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let market_state = MarketState {
+            bids: bids_key,
+            asks: asks_key,
+            ..Zeroable::zeroed()
+        };
+        let (mut bids_lamports, mut asks_lamports) = (0, 0);
+        let mut bids_data = [AccountTag::Bids as u8; 8];
+        let mut asks_data = [AccountTag::Asks as u8; 8];
+        let bids = AccountInfo::new(
+            &bids_key,
+            false,
+            true,
+            &mut bids_lamports,
+            &mut bids_data,
+            &owner,
+            false,
+            0,
+        );
+        let asks = AccountInfo::new(
+            &asks_key,
+            false,
+            true,
+            &mut asks_lamports,
+            &mut asks_data,
+            &owner,
+            false,
+            0,
+        );
+
+        assert!(check_book_accounts(&bids, &asks, &market_state).is_ok());
+        assert!(matches!(
+            check_book_accounts(&asks, &bids, &market_state),
+            Err(AoError::WrongBookAccount)
+        ));
+
+        // The market's keys can't be bypassed by swapping the account contents either
+        asks.data.borrow_mut()[0] = AccountTag::Bids as u8;
+        bids.data.borrow_mut()[0] = AccountTag::Asks as u8;
+        assert!(matches!(
+            check_book_accounts(&bids, &asks, &market_state),
+            Err(AoError::WrongBookAccount)
+        ));
+    }
+
+    #[test]
+    fn test_account_checks() {
+        let (key, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut lamports, mut data) = (0, [AccountTag::Market as u8; 8]);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+        assert!(check_tag(&account, AccountTag::Market).is_ok());
+        assert!(matches!(
+            check_tag(&account, AccountTag::EventQueue),
+            Err(AoError::WrongAccountTag)
+        ));
+        assert!(check_owner(&account, &program_id).is_ok());
+        assert!(matches!(
+            check_owner(&account, &key),
+            Err(AoError::WrongAccountOwner)
+        ));
+        assert!(check_writable(&account).is_ok());
+
+        let (mut lamports, mut data) = (0, []);
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &program_id,
+            false,
+            0,
+        );
+        assert!(matches!(
+            check_writable(&account),
+            Err(AoError::AccountNotWritable)
+        ));
+        // An empty account has no tag
+        assert!(matches!(
+            check_tag(&account, AccountTag::Uninitialized),
+            Err(AoError::WrongAccountTag)
+        ));
+    }
+
+    #[test]
+    fn test_apply_rounding() {
+        // (numerator, denominator, up, down, nearest)
+        let cases = [
+            (0, 4, 0, 0, 0),
+            (8, 4, 2, 2, 2),
+            (9, 4, 3, 2, 2),
+            (10, 4, 3, 2, 3),
+            (11, 4, 3, 2, 3),
+            (13, 5, 3, 2, 3),
+            (12, 5, 3, 2, 2),
+            (1, 3, 1, 0, 0),
+            (2, 3, 1, 0, 1),
+            (u128::MAX, u128::MAX, 1, 1, 1),
+            (u128::MAX - 1, u128::MAX, 1, 0, 1),
+        ];
+        for &(num, den, up, down, nearest) in &cases {
+            assert_eq!(apply_rounding(num, den, Rounding::Up), Some(up));
+            assert_eq!(apply_rounding(num, den, Rounding::Down), Some(down));
+            assert_eq!(apply_rounding(num, den, Rounding::Nearest), Some(nearest));
+        }
+
+        // Rounding up can push a quotient just out of the u64 range
+        let max = (u64::MAX as u128) << 32;
+        assert_eq!(apply_rounding(max, 1 << 32, Rounding::Up), Some(u64::MAX));
+        assert_eq!(
+            apply_rounding(max + 1, 1 << 32, Rounding::Down),
+            Some(u64::MAX)
+        );
+        assert_eq!(apply_rounding(max + 1, 1 << 32, Rounding::Up), None);
+        assert_eq!(
+            apply_rounding(max + (1 << 31), 1 << 32, Rounding::Nearest),
+            None
+        );
+        assert_eq!(apply_rounding(u128::MAX, 1, Rounding::Down), None);
+    }
+
+    #[test]
+    fn test_compute_quote_size() {
+        let price = (2.5 * 2.0f64.powi(32)) as u64;
+        assert_eq!(compute_quote_size(3, price, Side::Bid).unwrap(), 8);
+        assert_eq!(compute_quote_size(3, price, Side::Ask).unwrap(), 7);
+        assert_eq!(compute_quote_size(4, price, Side::Ask).unwrap(), 10);
+
+        // The largest price which keeps the quote size of u64::MAX base within a u64
+        assert_eq!(
+            compute_quote_size(u64::MAX, 1 << 32, Side::Bid).unwrap(),
+            u64::MAX
+        );
+        assert!(matches!(
+            compute_quote_size(u64::MAX, (1 << 32) + 1, Side::Ask),
+            Err(AoError::QuoteOverflow)
+        ));
+        assert!(matches!(
+            compute_quote_size(u64::MAX, u64::MAX, Side::Bid),
+            Err(AoError::QuoteOverflow)
+        ));
+        assert!(matches!(
+            compute_quote_size(u64::MAX - 1, u64::MAX - 1, Side::Ask),
+            Err(AoError::QuoteOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_compute_fill_price() {
+        assert_eq!(compute_fill_price(10, 20, Side::Bid, 0), 10);
+        assert_eq!(compute_fill_price(10, 20, Side::Bid, 5_000), 15);
+        assert_eq!(compute_fill_price(20, 10, Side::Ask, 2_500), 18);
+        // A taker limit price worse than the maker's price can't lower the fill price
+        assert_eq!(compute_fill_price(10, 5, Side::Bid, 5_000), 10);
+
+        // The maker's share is capped at the whole improvement
+        for &bps in &[10_000, 10_001, u16::MAX] {
+            assert_eq!(compute_fill_price(10, 20, Side::Bid, bps), 20);
+            assert_eq!(compute_fill_price(20, 10, Side::Ask, bps), 10);
+            assert_eq!(compute_fill_price(u64::MAX, 0, Side::Ask, bps), 0);
+        }
+    }
+
+    #[test]
+    fn test_compute_taker_fee() {
+        assert_eq!(compute_taker_fee(1_000, 0).unwrap(), 0);
+        assert_eq!(compute_taker_fee(1_000, 25).unwrap(), 3);
+        assert_eq!(compute_taker_fee(1, 1).unwrap(), 1);
+        assert_eq!(compute_taker_fee(u64::MAX, 10_000).unwrap(), u64::MAX);
+
+        // A fee above 100% could exceed the quote size and overflow
+        for &bps in &[10_001, u16::MAX] {
+            assert!(matches!(
+                compute_taker_fee(u64::MAX, bps),
+                Err(AoError::InvalidFeeBps)
+            ));
+            assert!(matches!(
+                compute_taker_fee(1, bps),
+                Err(AoError::InvalidFeeBps)
+            ));
+        }
+    }
 }
@@ -1,7 +1,8 @@
 use agnostic_orderbook::instruction::create_market;
 use agnostic_orderbook::state::critbit::Slab;
-use agnostic_orderbook::state::event_queue::EventQueue;
+use agnostic_orderbook::state::event_queue::{EventQueue, OrderIdMode, OverflowPolicy};
 use agnostic_orderbook::state::market_state::MarketState;
+use agnostic_orderbook::state::SelfTradeMatch;
 use solana_program::instruction::Instruction;
 use solana_program::pubkey::Pubkey;
 use solana_program::system_instruction::create_account;
@@ -108,6 +109,18 @@ pub async fn create_market_and_accounts(
         create_market::Params {
             min_base_order_size: 10,
             tick_size: 1,
+            authority: prg_test_ctx.payer.pubkey(),
+            fee_per_event: 0,
+            overflow_policy: OverflowPolicy::Reject,
+            price_band_bps: 0,
+            max_orders_per_owner: 0,
+            base_lot_size: 0,
+            self_trade_match: SelfTradeMatch::CallbackId,
+            maker_price_improvement_bps: 0,
+            max_levels_per_side: 0,
+            fee_recipient: prg_test_ctx.payer.pubkey(),
+            taker_fee_bps: 0,
+            order_id_mode: OrderIdMode::Sequence,
         },
     );
     sign_send_instructions(prg_test_ctx, vec![create_market_instruction], vec![])
@@ -6,8 +6,8 @@ use agnostic_orderbook::{
     instruction::{cancel_order, new_order},
     orderbook::OrderBookState,
     state::{
-        AccountTag, EventQueue, EventQueueHeader, MarketState, SelfTradeBehavior, Side,
-        MARKET_STATE_LEN,
+        AccountTag, EventQueue, EventQueueHeader, MarketState, OrderId, PostOnly,
+        SelfTradeBehavior, Side, MARKET_STATE_LEN,
     },
 };
 use bonfida_utils::bench::get_env_arg;
@@ -27,7 +27,7 @@ async fn main() {
 pub struct Context {
     test_context: ProgramTest,
     caller_authority: Keypair,
-    test_order_id: u128,
+    test_order_id: OrderId,
     market: Pubkey,
     event_queue: Pubkey,
     bids: Pubkey,
@@ -134,7 +134,7 @@ async fn prepare() -> Context {
                     side: Side::Bid,
                     match_limit: 10,
                     callback_info: Pubkey::new_unique().to_bytes().to_vec(),
-                    post_only: true,
+                    post_only: PostOnly::Enabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
@@ -153,7 +153,7 @@ async fn prepare() -> Context {
                     side: Side::Ask,
                     match_limit: 10,
                     callback_info: Pubkey::new_unique().to_bytes().to_vec(),
-                    post_only: true,
+                    post_only: PostOnly::Enabled,
                     post_allowed: true,
                     self_trade_behavior: SelfTradeBehavior::DecrementTake,
                     max_ts: u64::MAX,
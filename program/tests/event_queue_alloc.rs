@@ -0,0 +1,112 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use agnostic_orderbook::error::AoError;
+use agnostic_orderbook::instruction::new_order;
+use agnostic_orderbook::state::critbit::Slab;
+use agnostic_orderbook::state::event_queue::{iter_events, EventQueue, EventRef};
+use agnostic_orderbook::state::orderbook::OrderBookState;
+use agnostic_orderbook::state::{AccountTag, PostOnly, SelfTradeBehavior, SelfTradeMatch, Side};
+
+/// Counts the heap allocations made by the test, which is the only one in this binary
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn params(side: Side, owner: u8) -> new_order::Params<[u8; 32]> {
+    new_order::Params {
+        max_base_qty: 1_000,
+        max_quote_qty: u64::MAX,
+        limit_price: 10 << 32,
+        side,
+        match_limit: 10,
+        callback_info: [owner; 32],
+        post_only: PostOnly::Disabled,
+        post_allowed: true,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        max_ts: u64::MAX,
+    }
+}
+
+#[test]
+fn test_event_iteration_doesnt_allocate() {
+    let slab_size = Slab::<[u8; 32]>::compute_allocation_size(100);
+    let (mut asks_buffer, mut bids_buffer) = (vec![0; slab_size], vec![0; slab_size]);
+    Slab::<[u8; 32]>::initialize(&mut asks_buffer, &mut bids_buffer).unwrap();
+    let mut event_queue_buffer = vec![0; EventQueue::<[u8; 32]>::compute_allocation_size(100)];
+    {
+        let mut order_book = OrderBookState {
+            bids: Slab::from_buffer(&mut bids_buffer, AccountTag::Bids).unwrap(),
+            asks: Slab::from_buffer(&mut asks_buffer, AccountTag::Asks).unwrap(),
+            self_trade_match: SelfTradeMatch::CallbackId,
+            maker_price_improvement_bps: 0,
+            log_matches: false,
+            taker_fee_bps: 0,
+            fills_only: false,
+            owner_index: None,
+        };
+        let mut event_queue =
+            EventQueue::from_buffer(&mut event_queue_buffer, AccountTag::Uninitialized).unwrap();
+        for owner in 0..10 {
+            order_book
+                .new_order(params(Side::Ask, owner), &mut event_queue, 1, 0)
+                .unwrap();
+            order_book
+                .new_order(params(Side::Bid, owner + 1), &mut event_queue, 1, 0)
+                .unwrap();
+        }
+
+        let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+        let mut fills = 0;
+        for event in event_queue.iter() {
+            if let EventRef::Fill(f) = event {
+                assert_eq!(f.maker_callback_info[0] + 1, f.taker_callback_info[0]);
+                fills += 1;
+            }
+        }
+        assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), allocations);
+        assert_eq!(fills, 10);
+    }
+
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+    let fills = iter_events::<[u8; 32]>(&event_queue_buffer)
+        .unwrap()
+        .filter(|e| matches!(e, EventRef::Fill(_)))
+        .count();
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), allocations);
+    assert_eq!(fills, 10);
+
+    let mut event_queue =
+        EventQueue::<[u8; 32]>::from_buffer(&mut event_queue_buffer, AccountTag::EventQueue)
+            .unwrap();
+    let len = event_queue.len();
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+    let mut consumed = 0;
+    while let Ok(event) = event_queue.pop_front_ref() {
+        if let EventRef::Fill(f) = event {
+            assert_eq!(f.maker_callback_info[0] + 1, f.taker_callback_info[0]);
+        }
+        consumed += 1;
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), allocations);
+    assert_eq!(consumed, len);
+    assert!(event_queue.is_empty());
+    assert!(matches!(
+        event_queue.pop_front_ref(),
+        Err(AoError::EventQueueEmpty)
+    ));
+}
@@ -1,6 +1,8 @@
 use agnostic_orderbook::instruction::{cancel_order, close_market, consume_events, new_order};
+use agnostic_orderbook::state::event_queue::ConsumeSummary;
 use agnostic_orderbook::state::{market_state::MarketState, OrderSummary};
-use agnostic_orderbook::state::{AccountTag, SelfTradeBehavior, Side};
+use agnostic_orderbook::state::{AccountTag, PostOnly, SelfTradeBehavior, Side};
+use agnostic_orderbook::utils::{read_register_at, register_size};
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::program_option::COption;
@@ -56,7 +58,7 @@ async fn test_agnostic_orderbook() {
         register_account,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; register_size(0)],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -128,7 +130,7 @@ async fn test_agnostic_orderbook() {
             limit_price: 1000 << 32,
             side: Side::Bid,
             callback_info: C(Pubkey::new_unique().to_bytes()),
-            post_only: false,
+            post_only: PostOnly::Disabled,
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
@@ -169,7 +171,7 @@ async fn test_agnostic_orderbook() {
             limit_price: 1000 << 32,
             side: Side::Ask,
             callback_info: C(Pubkey::new_unique().to_bytes()),
-            post_only: false,
+            post_only: PostOnly::Disabled,
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
@@ -248,6 +250,19 @@ async fn test_agnostic_orderbook() {
         .await
         .unwrap();
 
+    // The crank's summary is read back from the raw register account data
+    let register_data = prg_test_ctx
+        .banks_client
+        .get_account(register_account)
+        .await
+        .unwrap()
+        .unwrap()
+        .data;
+    let consume_summary = read_register_at::<ConsumeSummary>(&register_data, 0).unwrap();
+    // The fill of the bid, which is fully filled and leaves the book without an Out event
+    assert_eq!(consume_summary.events_consumed, 1);
+    assert_eq!(consume_summary.crank_fee, 0);
+
     // Close Market
     let close_market_instruction = close_market(
         close_market::Accounts {
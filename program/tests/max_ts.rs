@@ -1,6 +1,7 @@
 use agnostic_orderbook::instruction::{cancel_order, close_market, consume_events, new_order};
 use agnostic_orderbook::state::{market_state::MarketState, OrderSummary};
-use agnostic_orderbook::state::{AccountTag, SelfTradeBehavior, Side};
+use agnostic_orderbook::state::{AccountTag, PostOnly, SelfTradeBehavior, Side};
+use agnostic_orderbook::utils::register_size;
 use bonfida_utils::BorshSize;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::clock::Clock;
@@ -56,7 +57,7 @@ async fn test_agnostic_orderbook() {
         register_account,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; register_size(0)],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -66,7 +67,7 @@ async fn test_agnostic_orderbook() {
         register_account2,
         Account {
             lamports: 1_000_000,
-            data: vec![0; 42],
+            data: vec![0; register_size(0)],
             owner: agnostic_orderbook::ID,
             ..Account::default()
         },
@@ -139,7 +140,7 @@ async fn test_agnostic_orderbook() {
             limit_price: 1000 << 32,
             side: Side::Bid,
             callback_info: C(Pubkey::new_unique().to_bytes()),
-            post_only: false,
+            post_only: PostOnly::Disabled,
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
@@ -182,7 +183,7 @@ async fn test_agnostic_orderbook() {
             limit_price: 1000 << 32,
             side: Side::Ask,
             callback_info: C(Pubkey::new_unique().to_bytes()),
-            post_only: false,
+            post_only: PostOnly::Disabled,
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,
@@ -208,7 +209,7 @@ async fn test_agnostic_orderbook() {
             limit_price: 1000 << 32,
             side: Side::Ask,
             callback_info: C(Pubkey::new_unique().to_bytes()),
-            post_only: false,
+            post_only: PostOnly::Disabled,
             post_allowed: true,
             self_trade_behavior: SelfTradeBehavior::CancelProvide,
             match_limit: 3,